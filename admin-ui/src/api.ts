@@ -18,6 +18,10 @@ if (process.env.NODE_ENV === 'production') {
   apiRoot = 'http://localhost:5000/api';
 }
 
+// Keeps the tokens in HttpOnly cookies, out of reach of scripts
+const cookieSessions = !!process.env.REACT_APP_COOKIE_SESSIONS;
+const csrfCookie = 'csrf_token';
+
 export interface ApiCall<T> {
   (): Promise<T>;
 }
@@ -31,58 +35,136 @@ export function signin(
       headers: {
         'Content-Type': 'application/json',
       },
-      body: JSON.stringify(data),
+      credentials: 'include',
+      body: JSON.stringify({...data, cookie: cookieSessions}),
     })
       .then(assertStatusOk)
       .then(res => {
-        setToken(res.token);
+        setTokens(res);
         return Ok(res);
       })
       // force type casting, needs manual verification
       .catch(error => Err({error})) as any;
 }
 
-export const whoami: ApiCall<Result<AuthSuccess, GenericError>> = () =>
-  match(getToken(), {
-    Ok: (token: string) =>
-      fetch(`${apiRoot}/auth`, {
-        method: 'GET',
-        headers: {
-          Authorization: `Bearer ${token}`,
-          'Content-Type': 'application/json',
-        },
-      })
-        .then(assertStatusOk)
-        .then(user => Ok({user, token}))
-        // force type casting, needs manual verification
-        .catch(error => Err({error})) as any,
-    Err: () => Promise.resolve(Err({error: 'no token'})) as any,
-  });
-
-export const signout: ApiCall<void> = () => {
-  deleteToken();
-  return Promise.resolve();
+export const whoami: ApiCall<Result<AuthSuccess, GenericError>> = () => {
+  takeFragmentTokens();
+  return (
+    authorizedFetch(`${apiRoot}/auth`, 'GET')
+      .then(assertStatusOk)
+      .then(user => Ok({user, token: localStorage.getItem(tokenKey())}))
+      // force type casting, needs manual verification
+      .catch(error => Err({error})) as any
+  );
 };
 
+// Single sign-on hands the tokens over in the url fragment, unless it set
+// the session cookies, in which case they're never kept
+function takeFragmentTokens() {
+  const params = new URLSearchParams(window.location.hash.slice(1));
+  const token = params.get('token');
+  if (token) {
+    const refresh_token = params.get('refresh_token') || undefined;
+    setTokens({token, refresh_token});
+    window.history.replaceState(
+      null,
+      document.title,
+      window.location.pathname + window.location.search,
+    );
+  }
+}
+
+export const oidcLoginUrl = cookieSessions
+  ? `${apiRoot}/auth/oidc/login?cookie=true`
+  : `${apiRoot}/auth/oidc/login`;
+
+export const signout: ApiCall<void> = () =>
+  authorizedFetch(`${apiRoot}/auth/logout`, 'POST')
+    // signed out locally, even when the session can't be revoked
+    .catch(() => undefined)
+    .then(deleteTokens);
+
 export function createBookmark(
   data: Bookmark,
 ): ApiCall<Result<void, GenericError>> {
   return () =>
-    match(getToken(), {
-      Ok: (token: string) =>
-        fetch(`${apiRoot}/bookmarks`, {
-          method: 'POST',
-          headers: {
-            Authorization: `Bearer ${token}`,
-            'Content-Type': 'application/json',
-          },
-          body: JSON.stringify(data),
-        })
-          .then(assertStatusOk)
-          .then(Ok)
-          // force type casting, needs manual verification
-          .catch(error => Err({error})) as any,
-      Err: () => Promise.resolve(Err({error: 'no token'})) as any,
+    authorizedFetch(`${apiRoot}/bookmarks`, 'POST', JSON.stringify(data))
+      .then(assertStatusOk)
+      .then(Ok)
+      // force type casting, needs manual verification
+      .catch(error => Err({error})) as any;
+}
+
+// Access tokens are short-lived, a request rejected with 401 is sent once
+// more with a token renewed from the refresh token.
+function authorizedFetch(
+  url: string,
+  method: string,
+  body?: string,
+): Promise<Response> {
+  if (cookieSessions) {
+    const send = () =>
+      fetch(url, {
+        method,
+        headers: {
+          'Content-Type': 'application/json',
+          'X-CSRF-Token': getCookie(csrfCookie),
+        },
+        credentials: 'include',
+        body,
+      });
+    return send().then(res =>
+      res.status === 401 ? refreshToken().then(send, () => res) : res,
+    );
+  }
+
+  const send = (token: string) =>
+    fetch(url, {
+      method,
+      headers: {
+        Authorization: `Bearer ${token}`,
+        'Content-Type': 'application/json',
+      },
+      body,
+    });
+
+  return match(getToken(), {
+    Ok: (token: string) =>
+      send(token).then(res =>
+        res.status === 401 ? refreshToken().then(send, () => res) : res,
+      ),
+    Err: () => refreshToken().then(send),
+  });
+}
+
+function refreshToken(): Promise<string> {
+  if (cookieSessions) {
+    return fetch(`${apiRoot}/auth/refresh`, {
+      method: 'POST',
+      headers: {
+        'X-CSRF-Token': getCookie(csrfCookie),
+      },
+      credentials: 'include',
+    })
+      .then(assertStatusOk)
+      .then(() => '');
+  }
+
+  const token = localStorage.getItem(refreshTokenKey());
+  if (!token) {
+    return Promise.reject('no token');
+  }
+  return fetch(`${apiRoot}/auth/refresh`, {
+    method: 'POST',
+    headers: {
+      'Content-Type': 'application/json',
+    },
+    body: JSON.stringify({refresh_token: token}),
+  })
+    .then(assertStatusOk)
+    .then((res: AuthSuccess) => {
+      setTokens(res);
+      return res.token || '';
     });
 }
 
@@ -100,12 +182,29 @@ function assertStatusOk(res: Response) {
   }
 }
 
-function setToken(token: string) {
-  localStorage.setItem(tokenKey(), token);
+function setTokens(res: {token?: string; refresh_token?: string}) {
+  if (cookieSessions) {
+    return;
+  }
+  if (res.token) {
+    localStorage.setItem(tokenKey(), res.token);
+  }
+  if (res.refresh_token) {
+    localStorage.setItem(refreshTokenKey(), res.refresh_token);
+  }
 }
 
-function deleteToken() {
+function deleteTokens() {
   localStorage.removeItem(tokenKey());
+  localStorage.removeItem(refreshTokenKey());
+}
+
+function getCookie(name: string): string {
+  const prefix = `${name}=`;
+  const cookie = document.cookie
+    .split('; ')
+    .find(cookie => cookie.startsWith(prefix));
+  return cookie ? cookie.slice(prefix.length) : '';
 }
 
 function getToken(): Result<string, null> {
@@ -120,3 +219,7 @@ function getToken(): Result<string, null> {
 function tokenKey(): string {
   return `${apiRoot}/token`;
 }
+
+function refreshTokenKey(): string {
+  return `${apiRoot}/refresh_token`;
+}
@@ -1,6 +1,7 @@
 import * as React from 'react';
 import {AuthData, Callback} from '../interface';
 import {Input} from './Input';
+import {oidcLoginUrl} from '../api';
 
 export interface Props {
   onSubmit: Callback<AuthData>;
@@ -41,6 +42,9 @@ export function AuthForm(props: Props): React.ReactElement {
         />
         <button type="submit">Login</button>
       </form>
+      {process.env.REACT_APP_OIDC && (
+        <a href={oidcLoginUrl}>Sign in with SSO</a>
+      )}
     </div>
   );
 }
@@ -65,7 +65,9 @@ export type AuthResult = Result<AuthSuccess, GenericError>;
 
 export interface AuthSuccess {
   user: AdminUser;
-  token: string;
+  // cookie sessions get neither token
+  token?: string;
+  refresh_token?: string;
 }
 
 // wraps in an object, so JSON.stringify works mostly
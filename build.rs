@@ -0,0 +1,22 @@
+// Passes the version of the newest migration on to the server, whose
+// readiness depends on the database having it
+use std::fs;
+
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+
+    // as diesel has it: the part before `_`, without dashes
+    let latest = fs::read_dir("migrations")
+        .expect("Failed to read migrations")
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter_map(|name| {
+            name.split('_')
+                .next()
+                .map(|version| version.replace('-', ""))
+        })
+        .max()
+        .expect("No migrations");
+    println!("cargo:rustc-env=LATEST_MIGRATION={}", latest);
+}
@@ -0,0 +1,8 @@
+-- Create saved searches table
+CREATE TABLE saved_searches (
+  id SERIAL PRIMARY KEY,
+  created TIMESTAMP NOT NULL default now(),
+  name VARCHAR NOT NULL,
+  slug VARCHAR UNIQUE NOT NULL,
+  query TEXT NOT NULL
+);
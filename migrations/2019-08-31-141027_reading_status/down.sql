@@ -0,0 +1,7 @@
+-- Drop index
+DROP INDEX status_created_idx;
+
+-- Drop columns
+ALTER TABLE bookmarks
+DROP COLUMN status,
+DROP COLUMN read_at;
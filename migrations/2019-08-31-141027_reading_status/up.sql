@@ -0,0 +1,8 @@
+-- track reading status
+ALTER TABLE bookmarks
+ADD COLUMN status VARCHAR NOT NULL DEFAULT 'unread'
+  CHECK (status IN ('unread', 'reading', 'read', 'archived')),
+ADD COLUMN read_at TIMESTAMP;
+
+-- index for the read-later queue
+CREATE INDEX status_created_idx ON bookmarks (status, created);
@@ -0,0 +1,6 @@
+-- Drop index
+DROP INDEX starred_created_idx;
+
+-- Drop column
+ALTER TABLE bookmarks
+DROP COLUMN starred;
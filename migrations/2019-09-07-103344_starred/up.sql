@@ -0,0 +1,5 @@
+-- starred bookmarks
+ALTER TABLE bookmarks
+ADD COLUMN starred BOOLEAN NOT NULL DEFAULT false;
+
+CREATE INDEX starred_created_idx ON bookmarks (created) WHERE starred;
@@ -0,0 +1,3 @@
+-- Drop column
+ALTER TABLE bookmarks
+DROP COLUMN visibility;
@@ -0,0 +1,3 @@
+-- public or private bookmarks
+ALTER TABLE bookmarks
+ADD COLUMN visibility VARCHAR NOT NULL DEFAULT 'public';
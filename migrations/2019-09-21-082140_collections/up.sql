@@ -0,0 +1,20 @@
+-- Create collections table
+CREATE TABLE collections (
+  id SERIAL PRIMARY KEY,
+  created TIMESTAMP NOT NULL default now(),
+  slug VARCHAR UNIQUE NOT NULL,
+  title VARCHAR NOT NULL,
+  intro TEXT NOT NULL DEFAULT ''
+);
+
+-- Create collection items table
+CREATE TABLE collection_items (
+  collection_id INTEGER NOT NULL REFERENCES collections (id) ON DELETE CASCADE,
+  bookmark_id INTEGER NOT NULL REFERENCES bookmarks (id) ON DELETE CASCADE,
+  position INTEGER NOT NULL,
+  PRIMARY KEY (collection_id, bookmark_id)
+);
+
+-- Create index
+CREATE INDEX collection_items_position_idx
+ON collection_items (collection_id, position);
@@ -0,0 +1,13 @@
+-- Create highlights table
+CREATE TABLE highlights (
+  id SERIAL PRIMARY KEY,
+  bookmark_id INTEGER NOT NULL REFERENCES bookmarks (id) ON DELETE CASCADE,
+  created TIMESTAMP NOT NULL default now(),
+  quote TEXT NOT NULL,
+  comment TEXT,
+  position INTEGER NOT NULL
+);
+
+-- Create index
+CREATE INDEX highlights_position_idx
+ON highlights (bookmark_id, position);
@@ -0,0 +1,16 @@
+-- Create snapshots table
+CREATE TABLE snapshots (
+  id SERIAL PRIMARY KEY,
+  bookmark_id INTEGER NOT NULL REFERENCES bookmarks (id) ON DELETE CASCADE,
+  fetched TIMESTAMP NOT NULL default now(),
+  -- pages which could not be fetched have no status
+  http_status INTEGER,
+  final_url VARCHAR NOT NULL,
+  title VARCHAR,
+  byline VARCHAR,
+  content TEXT NOT NULL
+);
+
+-- Create index
+CREATE INDEX snapshots_fetched_idx
+ON snapshots (bookmark_id, fetched);
@@ -0,0 +1,8 @@
+-- Drop index
+DROP INDEX last_checked_idx;
+
+-- Drop columns
+ALTER TABLE bookmarks
+DROP COLUMN last_checked,
+DROP COLUMN last_status,
+DROP COLUMN final_url;
@@ -0,0 +1,8 @@
+-- result of the last dead-link check
+ALTER TABLE bookmarks
+ADD COLUMN last_checked TIMESTAMP,
+ADD COLUMN last_status INTEGER,
+ADD COLUMN final_url VARCHAR;
+
+-- index for picking bookmarks due for a check
+CREATE INDEX last_checked_idx ON bookmarks (last_checked);
@@ -0,0 +1,7 @@
+-- Drop index
+DROP INDEX bm_user_created_idx;
+
+-- Drop columns
+ALTER TABLE collections DROP COLUMN user_id;
+ALTER TABLE bookmarks DROP COLUMN user_id;
+ALTER TABLE users DROP COLUMN name;
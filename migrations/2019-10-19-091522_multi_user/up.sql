@@ -0,0 +1,41 @@
+-- public name of a user, used in profile urls
+ALTER TABLE users ADD COLUMN name VARCHAR;
+
+UPDATE users SET name = split_part(email, '@', 1);
+UPDATE users u SET name = u.name || '-' || u.id
+WHERE EXISTS (SELECT 1 FROM users o WHERE o.name = u.name AND o.id < u.id);
+
+ALTER TABLE users
+ALTER COLUMN name SET NOT NULL,
+ADD CONSTRAINT users_name_key UNIQUE (name);
+
+-- owner of a bookmark, existing ones go to the first admin
+DO $$
+BEGIN
+  IF (EXISTS (SELECT 1 FROM bookmarks) OR EXISTS (SELECT 1 FROM collections))
+     AND NOT EXISTS (SELECT 1 FROM users) THEN
+    RAISE EXCEPTION 'Existing bookmarks and collections need an owner, '
+      'create an admin user with the previous version before upgrading';
+  END IF;
+END
+$$;
+
+ALTER TABLE bookmarks ADD COLUMN user_id INTEGER REFERENCES users (id);
+
+UPDATE bookmarks SET user_id = (
+  SELECT id FROM users ORDER BY is_admin DESC, id ASC LIMIT 1
+);
+
+ALTER TABLE bookmarks ALTER COLUMN user_id SET NOT NULL;
+
+-- owner of a collection, the same as for bookmarks
+ALTER TABLE collections ADD COLUMN user_id INTEGER REFERENCES users (id);
+
+UPDATE collections SET user_id = (
+  SELECT id FROM users ORDER BY is_admin DESC, id ASC LIMIT 1
+);
+
+ALTER TABLE collections ALTER COLUMN user_id SET NOT NULL;
+
+-- index for profile pages
+CREATE INDEX bm_user_created_idx ON bookmarks (user_id, created);
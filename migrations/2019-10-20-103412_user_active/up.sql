@@ -0,0 +1,2 @@
+-- deactivated users can no longer sign in
+ALTER TABLE users ADD COLUMN active BOOLEAN NOT NULL DEFAULT true;
@@ -0,0 +1,15 @@
+-- sign-ins, access tokens carry the session id as `jti`
+CREATE TABLE sessions (
+  id SERIAL PRIMARY KEY,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  created TIMESTAMP NOT NULL DEFAULT now(),
+  last_used TIMESTAMP NOT NULL DEFAULT now(),
+  expires TIMESTAMP NOT NULL,
+  -- sha-256 of the current refresh token, replaced on every refresh
+  refresh_hash VARCHAR UNIQUE NOT NULL,
+  revoked TIMESTAMP,
+  user_agent VARCHAR
+);
+
+-- index for syncing recently revoked sessions
+CREATE INDEX sessions_revoked_idx ON sessions (revoked);
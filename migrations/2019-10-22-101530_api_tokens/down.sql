@@ -0,0 +1,2 @@
+-- Drop table
+DROP TABLE api_tokens;
@@ -0,0 +1,14 @@
+-- long-lived tokens for scripts, used in place of an access token
+CREATE TABLE api_tokens (
+  id SERIAL PRIMARY KEY,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  name VARCHAR NOT NULL,
+  scope VARCHAR NOT NULL,
+  -- sha-256 of the token, which is only shown once
+  token_hash VARCHAR UNIQUE NOT NULL,
+  created TIMESTAMP NOT NULL DEFAULT now(),
+  last_used TIMESTAMP,
+  revoked TIMESTAMP
+);
+
+CREATE INDEX api_tokens_user_idx ON api_tokens (user_id);
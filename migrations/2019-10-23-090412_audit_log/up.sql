@@ -0,0 +1,13 @@
+-- changes made through the admin api, see `db/audit.rs`
+CREATE TABLE audit_log (
+  id SERIAL PRIMARY KEY,
+  created TIMESTAMP NOT NULL DEFAULT now(),
+  -- email of the user who made the change
+  actor VARCHAR NOT NULL,
+  action VARCHAR NOT NULL,
+  target_id INTEGER,
+  -- changed fields, as {"field": {"before": ..., "after": ...}}
+  diff JSONB NOT NULL
+);
+
+CREATE INDEX audit_log_created_idx ON audit_log (created DESC);
@@ -8,8 +8,15 @@ interface MoreItems {
   next_page: number | undefined;
 }
 
-const fetchMore: Api<number, MoreItems> = Observable.liftPromise(nextPage => {
-  return fetch(`/api/bookmarks:page/${nextPage}`)
+interface MoreRequest {
+  source: string;
+  page: number;
+}
+
+const defaultSource = "/api/bookmarks:page";
+
+const fetchMore: Api<MoreRequest, MoreItems> = Observable.liftPromise(req => {
+  return fetch(`${req.source}/${req.page}`)
     .then(res => {
       if (res.ok) {
         return res.json();
@@ -21,7 +28,7 @@ const fetchMore: Api<number, MoreItems> = Observable.liftPromise(nextPage => {
     }));
 });
 
-const clicks$: Observable<number> = Observable.fromEventPattern(listener => {
+const clicks$: Observable<MoreRequest> = Observable.fromEventPattern(listener => {
   function onClick(e: MouseEvent) {
     const { target } = e;
     if (target instanceof HTMLElement) {
@@ -29,7 +36,7 @@ const clicks$: Observable<number> = Observable.fromEventPattern(listener => {
       const nextPage = parseInt(page || "NaN", 10);
       if (Number.isFinite(nextPage)) {
         e.preventDefault();
-        listener(nextPage);
+        listener({ source: getSource(), page: nextPage });
       }
     }
   }
@@ -43,6 +50,14 @@ const data$ = clicks$.switchMap(fetchMore);
 
 // DOM
 
+function getSource(): string {
+  const container = document.querySelector(containerSelector);
+  if (container instanceof HTMLElement && container.dataset.source) {
+    return container.dataset.source;
+  }
+  return defaultSource;
+}
+
 function getErrUi(err: unknown): JsonMl {
   let message: string;
 
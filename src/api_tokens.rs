@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_web::http::Method;
+use chrono::{NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use log::*;
+
+use crate::db::{DbExecutor, QueryActiveApiTokens, RecordApiTokenUse};
+use crate::models::{ApiToken, SlimUser, TokenScope};
+use crate::sessions::{hash_token, random_token};
+
+// Tells api tokens apart from access tokens in the Authorization header
+pub const TOKEN_PREFIX: &str = "rl_";
+
+// How often tokens created or revoked elsewhere are picked up, and their
+// use is recorded
+const SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+// Managing users, sessions and tokens takes signing in with a password
+const ACCOUNT_PATHS: [&str; 4] =
+    ["/api/auth/", "/api/sessions", "/api/tokens", "/api/users"];
+
+struct Entry {
+    id: i32,
+    scope: TokenScope,
+    user: SlimUser,
+}
+
+#[derive(Default)]
+struct Tokens {
+    // usable tokens by hash
+    by_hash: HashMap<String, Entry>,
+    // when tokens (by hash) were last added or removed by this process
+    changed: HashMap<String, Instant>,
+}
+
+lazy_static! {
+    static ref TOKENS: RwLock<Tokens> = RwLock::new(Tokens::default());
+    // Uses not yet written to the database, by token id
+    static ref USED: Mutex<HashMap<i32, NaiveDateTime>> =
+        Mutex::new(HashMap::new());
+}
+
+// Returns a new token, along with the hash stored in its place.
+pub fn new_api_token() -> (String, String) {
+    let token = format!("{}{}", TOKEN_PREFIX, random_token());
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+// Whether a token with `scope` may be used for a request
+pub fn allows(scope: TokenScope, method: &Method, path: &str) -> bool {
+    if ACCOUNT_PATHS.iter().any(|prefix| path.starts_with(prefix)) {
+        return false;
+    }
+    let read = *method == Method::GET || *method == Method::HEAD;
+    match scope {
+        TokenScope::Read => read,
+        TokenScope::WriteBookmarks => {
+            read || path.starts_with("/api/bookmarks")
+                || path.starts_with("/api/highlights/")
+        }
+    }
+}
+
+// The user a token belongs to, when it may be used for the request
+pub fn authenticate(
+    token: &str,
+    method: &Method,
+    path: &str,
+) -> Option<SlimUser> {
+    let tokens = TOKENS.read().ok()?;
+    let entry = tokens.by_hash.get(&hash_token(token))?;
+    if !allows(entry.scope, method, path) {
+        return None;
+    }
+    if let Ok(mut used) = USED.lock() {
+        used.insert(entry.id, Utc::now().naive_utc());
+    }
+    Some(entry.user.clone())
+}
+
+// Tokens created or revoked by this process can be used (or not) right
+// away, instead of after the next sync.
+pub fn add_token(token: &ApiToken, user: SlimUser) {
+    if let Ok(mut tokens) = TOKENS.write() {
+        let hash = token.token_hash.clone();
+        tokens.by_hash.insert(hash.clone(), entry(token, user));
+        tokens.changed.insert(hash, Instant::now());
+    }
+}
+
+pub fn remove_token(token: &ApiToken) {
+    if let Ok(mut tokens) = TOKENS.write() {
+        tokens.by_hash.remove(&token.token_hash);
+        tokens
+            .changed
+            .insert(token.token_hash.clone(), Instant::now());
+    }
+}
+
+// Tokens added or removed by this process after the sync `started` may
+// be missing from its result, or still be in it, and are kept as they are.
+fn replace_tokens(active: Vec<(ApiToken, SlimUser)>, started: Instant) {
+    if let Ok(mut tokens) = TOKENS.write() {
+        let mut by_hash: HashMap<_, _> = active
+            .into_iter()
+            .map(|(token, user)| {
+                let entry = entry(&token, user);
+                (token.token_hash, entry)
+            })
+            .collect();
+        let Tokens {
+            by_hash: ref mut current,
+            ref mut changed,
+        } = *tokens;
+        changed.retain(|_, at| *at >= started);
+        for hash in changed.keys() {
+            match current.remove(hash) {
+                Some(entry) => by_hash.insert(hash.clone(), entry),
+                None => by_hash.remove(hash),
+            };
+        }
+        *current = by_hash;
+    }
+}
+
+fn entry(token: &ApiToken, user: SlimUser) -> Entry {
+    Entry {
+        id: token.id,
+        scope: token.scope,
+        user: SlimUser {
+            session_id: None,
+            ..user
+        },
+    }
+}
+
+// Keeps the tokens in memory in line with the database, so that the
+// `SlimUser` extractor and permission guards don't need a query.
+pub struct ApiTokenSync {
+    db: Addr<DbExecutor>,
+}
+
+impl ApiTokenSync {
+    pub fn new(db: Addr<DbExecutor>) -> Self {
+        ApiTokenSync { db }
+    }
+
+    fn sync(&mut self, ctx: &mut Context<Self>) {
+        let used: Vec<_> = match USED.lock() {
+            Ok(mut used) => used.drain().collect(),
+            Err(_) => vec![],
+        };
+        if !used.is_empty() {
+            let record = self.db.send(RecordApiTokenUse(used));
+            ctx.spawn(record.into_actor(self).then(|res, _, _| {
+                match res {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => {
+                        error!("Failed to record token use: {:?}", err)
+                    }
+                    Err(err) => error!("Database executor error: {:?}", err),
+                }
+                fut::ok(())
+            }));
+        }
+
+        let started = Instant::now();
+        let query = self.db.send(QueryActiveApiTokens);
+        ctx.spawn(query.into_actor(self).then(move |res, _, _| {
+            match res {
+                Ok(Ok(active)) => replace_tokens(active, started),
+                Ok(Err(err)) => error!("Failed to sync tokens: {:?}", err),
+                Err(err) => error!("Database executor error: {:?}", err),
+            }
+            fut::ok(())
+        }));
+    }
+}
+
+impl Actor for ApiTokenSync {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.sync(ctx);
+        ctx.run_interval(SYNC_INTERVAL, |act, ctx| act.sync(ctx));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allows() {
+        use TokenScope::*;
+
+        assert!(allows(Read, &Method::GET, "/api/bookmarks:page/1"));
+        assert!(allows(Read, &Method::GET, "/api/auth"));
+        assert!(!allows(Read, &Method::POST, "/api/bookmarks"));
+        assert!(!allows(Read, &Method::GET, "/api/tokens"));
+
+        assert!(allows(WriteBookmarks, &Method::POST, "/api/bookmarks"));
+        assert!(allows(WriteBookmarks, &Method::DELETE, "/api/highlights/1"));
+        assert!(allows(WriteBookmarks, &Method::GET, "/api/tags"));
+        assert!(!allows(WriteBookmarks, &Method::PUT, "/api/tags/rust"));
+        assert!(!allows(WriteBookmarks, &Method::POST, "/api/auth/logout"));
+        assert!(!allows(WriteBookmarks, &Method::POST, "/api/tokens"));
+        assert!(!allows(WriteBookmarks, &Method::PUT, "/api/users/1"));
+    }
+
+    #[test]
+    fn test_new_api_token() {
+        let (token, hash) = new_api_token();
+
+        assert!(is_api_token(&token));
+        assert_eq!(hash_token(&token), hash);
+    }
+
+    fn api_token(id: i32, token_hash: String) -> ApiToken {
+        ApiToken {
+            id,
+            user_id: 1,
+            name: "feed reader".into(),
+            scope: TokenScope::Read,
+            token_hash,
+            created: Utc::now().naive_utc(),
+            last_used: None,
+            revoked: None,
+        }
+    }
+
+    #[test]
+    fn test_replace_tokens() {
+        let usable = |token: &str| {
+            authenticate(token, &Method::GET, "/api/tags").is_some()
+        };
+        let user = SlimUser {
+            id: 1,
+            email: "ann@example.com".into(),
+            name: "ann".into(),
+            roles: vec![],
+            session_id: None,
+        };
+        let (stale, stale_hash) = new_api_token();
+        let (added, added_hash) = new_api_token();
+        let (revoked, revoked_hash) = new_api_token();
+        add_token(&api_token(1, stale_hash), user.clone());
+        let revoked_token = api_token(3, revoked_hash);
+        add_token(&revoked_token, user.clone());
+        std::thread::sleep(Duration::from_millis(1));
+
+        // changed while the sync is running, which still sees the revoked
+        // token and not the added one
+        let started = Instant::now();
+        add_token(&api_token(2, added_hash), user.clone());
+        remove_token(&revoked_token);
+        replace_tokens(vec![(revoked_token, user)], started);
+
+        assert!(!usable(&stale));
+        assert!(usable(&added));
+        assert!(!usable(&revoked));
+    }
+}
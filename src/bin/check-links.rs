@@ -0,0 +1,62 @@
+use std::error::Error;
+
+use actix::prelude::*;
+use diesel::{r2d2::ConnectionManager, PgConnection};
+use dotenv::dotenv;
+use structopt::StructOpt;
+
+use common::config;
+use common::db::DbExecutor;
+use common::links::{check_pending, CheckOptions};
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "check-links")]
+struct Opt {
+    /// Number of bookmarks to check
+    #[structopt(short = "n", long = "limit", default_value = "100")]
+    limit: i64,
+
+    /// Number of hosts requested at the same time
+    #[structopt(short = "c", long = "concurrency", default_value = "8")]
+    concurrency: usize,
+
+    /// Skip bookmarks checked within this many days
+    #[structopt(short = "d", long = "days", default_value = "7")]
+    days: i64,
+}
+
+impl From<Opt> for CheckOptions {
+    fn from(opt: Opt) -> Self {
+        CheckOptions {
+            concurrency: opt.concurrency,
+            batch_size: opt.limit,
+            recheck_after: chrono::Duration::days(opt.days),
+            ..CheckOptions::default()
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    dotenv().ok();
+    env_logger::init();
+
+    let opt = Opt::from_args();
+
+    let config = config::load()
+        .map_err(|err| format!("Invalid configuration:\n{}", err))?;
+    let manager =
+        ConnectionManager::<PgConnection>::new(config.database_url.clone());
+    let pool = r2d2::Pool::builder()
+        .max_size(config.pool_size)
+        .build(manager)?;
+
+    let mut sys = System::new("check-links");
+    let addr = SyncArbiter::start(1, move || DbExecutor(pool.clone()));
+    let checked = sys
+        .block_on(check_pending(addr, opt.into()))
+        .map_err(|err| format!("Failed to check links: {}", err))?;
+
+    println!("Checked {} link(s)", checked);
+
+    Ok(())
+}
@@ -7,7 +7,8 @@ use diesel::prelude::*;
 use dotenv::dotenv;
 use structopt::StructOpt;
 
-use common::models::NewUser;
+use common::models::{NewUser, Role, UserRole};
+use common::utils::check_password;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "create-admin-user")]
@@ -17,6 +18,11 @@ struct AdminOpt {
 
     #[structopt(short = "p", long = "password")]
     password: String,
+
+    /// Name shown in profile urls, defaults to the part of the email
+    /// before the `@`
+    #[structopt(short = "n", long = "name")]
+    name: Option<String>,
 }
 
 impl<'a> TryFrom<&'a mut AdminOpt> for NewUser<'a> {
@@ -24,11 +30,14 @@ impl<'a> TryFrom<&'a mut AdminOpt> for NewUser<'a> {
 
     fn try_from(opt: &'a mut AdminOpt) -> Result<Self, Self::Error> {
         opt.password = hash(&opt.password, DEFAULT_COST)?;
+        if opt.name.is_none() {
+            opt.name = opt.user.split('@').next().map(str::to_owned);
+        }
 
         Ok(NewUser {
             email: &opt.user,
             password: &opt.password,
-            is_admin: true,
+            name: opt.name.as_ref().map_or("", String::as_str),
         })
     }
 }
@@ -46,22 +55,37 @@ fn create_admin_user<'a>(
     conn: &PgConnection,
     user: NewUser<'a>,
 ) -> Result<(), diesel::result::Error> {
+    use common::schema::user_roles;
     use common::schema::users::dsl::*;
 
-    let _ = diesel::insert_into(users)
-        .values(&user)
-        .on_conflict(email)
-        .do_update()
-        .set(&user)
-        .execute(conn)?;
-
-    Ok(())
+    conn.transaction(|| {
+        let user_id = diesel::insert_into(users)
+            .values(&user)
+            .on_conflict(email)
+            .do_update()
+            .set(&user)
+            .returning(id)
+            .get_result::<i32>(conn)?;
+        diesel::insert_into(user_roles::table)
+            .values(&UserRole {
+                user_id,
+                role: Role::Admin,
+            })
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+        Ok(())
+    })
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let mut opt = AdminOpt::from_args();
     assert!(!opt.user.is_empty());
     assert!(!opt.password.is_empty());
+    let identity = [
+        opt.user.as_str(),
+        opt.name.as_ref().map_or("", String::as_str),
+    ];
+    check_password(&opt.password, &identity).map_err(|err| err.to_string())?;
 
     let user = NewUser::try_from(&mut opt)?;
 
@@ -0,0 +1,50 @@
+// Runs the identity provider of `common::mock_oidc`, signing everyone in as
+// the same user.
+use std::error::Error;
+
+use actix_web::{web, App, HttpServer};
+use structopt::StructOpt;
+
+use common::mock_oidc::{routes, Provider};
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "mock-oidc")]
+struct Opt {
+    #[structopt(short = "p", long = "port", default_value = "8090")]
+    port: u16,
+
+    /// Email of the signed in user
+    #[structopt(long = "email", default_value = "admin@example.com")]
+    email: String,
+
+    /// Groups the user is in, comma separated
+    #[structopt(long = "groups", default_value = "")]
+    groups: String,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+
+    let opt = Opt::from_args();
+    let groups = opt
+        .groups
+        .split(',')
+        .map(str::trim)
+        .filter(|group| !group.is_empty())
+        .map(String::from)
+        .collect();
+    let provider = web::Data::new(Provider::new(
+        format!("http://localhost:{}", opt.port),
+        opt.email,
+        groups,
+    ));
+
+    println!("Identity provider listening on {}", provider.issuer());
+    HttpServer::new(move || {
+        App::new().register_data(provider.clone()).configure(routes)
+    })
+    .bind(("127.0.0.1", opt.port))?
+    .run()?;
+
+    Ok(())
+}
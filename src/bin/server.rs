@@ -1,13 +1,18 @@
 #[macro_use]
 extern crate diesel_migrations;
 
+use std::iter;
+use std::path::PathBuf;
+use std::time::Instant;
+
 use actix::prelude::*;
 use actix_web::{
+    client::Client,
+    cookie::{Cookie, SameSite},
     error::ResponseError,
-    guard,
     http::{self, header},
     middleware::cors,
-    web, App, Error, HttpResponse, HttpServer,
+    web, App, Error, HttpRequest, HttpResponse, HttpServer,
 };
 use diesel::prelude::*;
 use diesel::{r2d2::ConnectionManager, PgConnection};
@@ -17,35 +22,164 @@ use futures::{
     future::{self, ok, Either},
     Future,
 };
-use horrorshow::Template;
+use horrorshow::{RenderOnce, Template};
 use log::*;
 use serde_json::json;
+use structopt::StructOpt;
 
-use common::config::CONFIG;
-use common::db::{AuthData, BookmarkIndexed, DbExecutor, QueryRecent};
+use common::api_tokens::{
+    add_token, new_api_token, remove_token, ApiTokenSync,
+};
+use common::config::{self, CONFIG};
+use common::cookies::{
+    check_csrf, cleared_session_cookies, cookie_value, session_cookies,
+    CSRF_HEADER, REFRESH_COOKIE,
+};
+use common::db::{
+    AddCollectionItem, AddHighlight, AuthData, ChangePassword, CheckDatabase,
+    ClearIndexed, CreateCollection, CreateSavedSearch, CreateUser, DbExecutor,
+    DeleteCollection, DeleteHighlight, DeleteTag, MergeTags, QueryApiTokens,
+    QueryAuditLog, QueryBookmark, QueryBookmarkDoc, QueryBrokenLinks,
+    QueryCollection, QueryCollections, QueryNewest, QueryQueue, QueryRecent,
+    QuerySavedSearch, QuerySavedSearches, QuerySessions, QuerySnapshot,
+    QueryStarred, QueryTagged, QueryTags, QueryUser, QueryUserBookmarks,
+    QueryUsers, RecordFailedLogin, RefreshSession, RemoveCollectionItem,
+    RenameTag, ReorderCollection, RevokeApiToken, RevokeSession, ToggleStar,
+    UpdateCollection, UpdateStatus, UpdateUser,
+};
 use common::error::ServiceError;
-use common::models::{Bookmark, BookmarkDoc, NewBookmark, PageData, SlimUser};
-use common::search::{QueryParser, Search, SearchClient};
-use common::templates::{bookmark_jsonml, BookmarkItem, PageTemplate};
-use common::utils::{admin_guard, create_token};
+use common::fetch::fetch_page;
+use common::health::{check_migrations, Check, Report};
+use common::indexing::{index_bookmark, index_bookmarks, index_pending};
+use common::links::LinkCheckScheduler;
+use common::models::{
+    ApiTokenData, Audience, Bookmark, BookmarkDoc, CollectionData,
+    CollectionItemData, CollectionOrderData, HighlightData,
+    HighlightedBookmark, MergeTagsData, NewApiToken, NewBookmark,
+    NewCollection, NewSavedSearch, NewSession, NewSnapshot, PageData,
+    PasswordData, Permission, RefreshData, RenameTagData, SavedSearchData,
+    SearchResults, SlimUser, StatusData, UserChanges, UserData,
+};
+use common::oidc::{
+    authorize_url, discover, fetch_user_info, login_of, Callback, Flow,
+    LoginQuery, FLOW_COOKIE,
+};
+use common::page_cache;
+use common::readability::{self, Extracted};
+use common::search::{
+    more_like_this, owned_by, visible_to, QueryParser, Search, SearchClient,
+};
+use common::sessions::{
+    hash_token, mark_revoked, new_refresh_token, refresh_expiry, RevocationSync,
+};
+use common::templates::{
+    bookmark_jsonml, tag_href, user_href, ArchiveItem, AtomFeed, BookmarkItem,
+    Breadcrumbs, BrokenLinkItem, PageTemplate, Permalink, TagCloud,
+};
+use common::throttle::{check_login, client_ip, login_failed, login_succeeded};
+use common::utils::{check_password, create_token, Require};
 
 embed_migrations!("migrations");
 
+#[derive(StructOpt, Debug)]
+#[structopt(name = "server")]
+struct Opt {
+    /// Config file, in place of CONFIG_FILE
+    #[structopt(short = "c", long = "config", parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Print the configuration in effect, without secrets, and exit
+    #[structopt(long = "check-config")]
+    check_config: bool,
+}
+
 fn create_pool() -> r2d2::Pool<ConnectionManager<PgConnection>> {
     // create db connection pool
     let manager =
         ConnectionManager::<PgConnection>::new(CONFIG.database_url.clone());
 
     r2d2::Pool::builder()
+        .max_size(CONFIG.pool_size)
         .build(manager)
         .expect("Failed to create pool.")
 }
 
 fn recent_bookmarks(
     page: web::Path<i64>,
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    db.send(QueryRecent(page.into_inner(), audience))
+        .from_err()
+        .and_then(|res| match res {
+            Ok(bookmarks) => {
+                let contents: Vec<_> =
+                    bookmarks.data.iter().map(bookmark_jsonml).collect();
+                let res = PageData {
+                    data: contents,
+                    total_pages: bookmarks.total_pages,
+                    next_page: bookmarks.next_page,
+                };
+                Ok(HttpResponse::Ok().json(res))
+            }
+            _ => Ok(HttpResponse::InternalServerError().into()),
+        })
+}
+
+fn queued_bookmarks(
+    page: web::Path<i64>,
+    user: SlimUser,
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = QueryQueue {
+        page: page.into_inner(),
+        owner: user.id,
+        audience,
+    };
+    db.send(msg).from_err().and_then(|res| match res {
+        Ok(bookmarks) => {
+            let contents: Vec<_> =
+                bookmarks.data.iter().map(bookmark_jsonml).collect();
+            let res = PageData {
+                data: contents,
+                total_pages: bookmarks.total_pages,
+                next_page: bookmarks.next_page,
+            };
+            Ok(HttpResponse::Ok().json(res))
+        }
+        _ => Ok(HttpResponse::InternalServerError().into()),
+    })
+}
+
+fn queued_bookmarks_html(
+    user: SlimUser,
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = QueryQueue {
+        page: 1,
+        owner: user.id,
+        audience,
+    };
+    db.send(msg).from_err().and_then(|res| match res {
+        Ok(bookmarks) => {
+            let items = bookmarks.data.into_iter().map(BookmarkItem::new);
+            let page =
+                PageTemplate::new_with_next_page(bookmarks.next_page, items)
+                    .with_source("/api/queue:page".to_owned());
+            Ok(render_html(page))
+        }
+        _ => Ok(HttpResponse::InternalServerError().into()),
+    })
+}
+
+fn starred_bookmarks(
+    page: web::Path<i64>,
+    audience: Audience,
     db: web::Data<Addr<DbExecutor>>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
-    db.send(QueryRecent(page.into_inner()))
+    db.send(QueryStarred(page.into_inner(), audience))
         .from_err()
         .and_then(|res| match res {
             Ok(bookmarks) => {
@@ -62,10 +196,48 @@ fn recent_bookmarks(
         })
 }
 
+fn starred_bookmarks_html(
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    db.send(QueryStarred(1, audience))
+        .from_err()
+        .and_then(|res| match res {
+            Ok(bookmarks) => {
+                let items = bookmarks.data.into_iter().map(BookmarkItem::new);
+                let page = PageTemplate::new_with_next_page(
+                    bookmarks.next_page,
+                    items,
+                )
+                .with_source("/api/best:page".to_owned())
+                .with_feed("/best/feed.atom".to_owned());
+                Ok(render_html(page))
+            }
+            _ => Ok(HttpResponse::InternalServerError().into()),
+        })
+}
+
+fn starred_bookmarks_feed(
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    db.send(QueryStarred(1, audience))
+        .from_err()
+        .and_then(|res| match res {
+            Ok(bookmarks) => Ok(render_feed(AtomFeed::new(
+                "Insightful Reads: Best of".to_owned(),
+                "/best/feed.atom".to_owned(),
+                bookmarks.data.into_iter(),
+            ))),
+            _ => Ok(HttpResponse::InternalServerError().into()),
+        })
+}
+
 fn recent_bookmarks_html(
+    audience: Audience,
     db: web::Data<Addr<DbExecutor>>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
-    db.send(QueryRecent(1))
+    db.send(QueryRecent(1, audience))
         .from_err()
         .and_then(|res| match res {
             Ok(bookmarks) => {
@@ -86,13 +258,17 @@ fn recent_bookmarks_html(
 }
 
 fn search_bookmark(
+    audience: Audience,
     search_client: web::Data<SearchClient>,
     search: Option<web::Query<Search>>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     match search {
         Some(ref search) if !search.q.is_empty() => Either::A(
             search_client
-                .query_docs(QueryParser::new(&search.q).parse())
+                .query_docs(visible_to(
+                    QueryParser::new(&search.q).parse(),
+                    audience,
+                ))
                 .map(move |results| HttpResponse::Ok().json(results)),
         ),
         _ => Either::B(ok(HttpResponse::BadRequest().into())),
@@ -100,6 +276,8 @@ fn search_bookmark(
 }
 
 fn search_bookmark_html(
+    req: HttpRequest,
+    audience: Audience,
     search_client: web::Data<SearchClient>,
     search: Option<web::Query<Search>>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
@@ -120,21 +298,18 @@ fn search_bookmark_html(
             if query.is_empty() {
                 return Either::B(redirect_empty_search());
             }
-            Either::A(search_client.query_docs(query).and_then(
+            let key = page_key(audience, &req);
+            let query = visible_to(query, audience);
+            Either::A(search_html(
+                key,
+                search_client.query_docs(query),
                 move |bookmarks| {
                     info!("Search complete, found {} items.", bookmarks.hits);
                     let items = bookmarks
                         .docs
                         .into_iter()
                         .map(|doc| BookmarkItem::new(doc.doc));
-                    let page =
-                        PageTemplate::new_with_query(items, query_string);
-                    match page.into_string() {
-                        Ok(body) => Ok(HttpResponse::Ok()
-                            .content_type("text/html")
-                            .body(body)),
-                        _ => Ok(HttpResponse::InternalServerError().into()),
-                    }
+                    PageTemplate::new_with_query(items, query_string)
                 },
             ))
         }
@@ -142,151 +317,1977 @@ fn search_bookmark_html(
     }
 }
 
-fn create_bookmark(
-    bookmark: web::Json<NewBookmark>,
+fn saved_search_html(
+    req: HttpRequest,
+    slug: web::Path<String>,
+    audience: Audience,
     db: web::Data<Addr<DbExecutor>>,
     search_client: web::Data<SearchClient>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
-    db.send(bookmark.into_inner())
+    db.send(QuerySavedSearch(slug.into_inner()))
         .from_err()
-        .and_then::<_, Box<Future<Item = Result<Bookmark, _>, Error = Error>>>(
-            move |created| match created {
-                Ok(created) => {
-                    info!("Created database record for: {:?}", &created);
-                    let bookmark_id = created.id;
-                    let doc: BookmarkDoc = created.clone().into();
-                    Box::new(
-                        search_client
-                            .insert_doc(doc)
-                            .and_then(move |_| {
-                                info!(
-                                    "Bookmark(id={}) indexed, updating db...",
-                                    bookmark_id,
-                                );
-                                db.send(BookmarkIndexed::new(bookmark_id))
-                                    .from_err()
-                            })
-                            .map(|bm| {
-                                if let Ok(ref bm) = bm {
-                                    info!(
-                                        "Bookmark(id={}) updated: {:?}",
-                                        bm.id, bm
-                                    );
-                                }
-                                bm.map_err(Into::into)
-                            })
-                            .or_else(|err| {
-                                error!("Failed to index doc: {:?}", err);
-                                future::ok(Ok(created))
-                            }),
-                    )
-                }
-                Err(_) => {
-                    Box::new(future::ok(Err(ServiceError::InternalServerError)))
-                }
-            },
-        )
         .and_then(move |res| match res {
-            Ok(created) => Ok(HttpResponse::Created().json(created)),
-            Err(err) => Ok(err.error_response()),
+            Ok(saved) => {
+                let query = visible_to(
+                    QueryParser::new(&saved.query).parse(),
+                    audience,
+                );
+                let feed_href = format!("/s/{}/feed.atom", saved.slug);
+                Either::A(search_html(
+                    page_key(audience, &req),
+                    search_client.query_docs(query),
+                    move |bookmarks| {
+                        let items = bookmarks
+                            .docs
+                            .into_iter()
+                            .map(|doc| BookmarkItem::new(doc.doc));
+                        PageTemplate::new_with_query(items, saved.query)
+                            .with_feed(feed_href)
+                    },
+                ))
+            }
+            Err(ServiceError::NotFound) => {
+                Either::B(ok(render_not_found("saved search")))
+            }
+            Err(err) => Either::B(ok(err.error_response())),
         })
 }
 
-fn login(
-    auth_data: web::Json<AuthData>,
+// Matches a feed picks its newest entries from, as the search index can't
+// sort by date
+const FEED_MATCHES: u32 = 1000;
+
+fn saved_search_feed(
+    slug: web::Path<String>,
+    audience: Audience,
     db: web::Data<Addr<DbExecutor>>,
+    search_client: web::Data<SearchClient>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
-    db.send(auth_data.into_inner())
+    db.send(QuerySavedSearch(slug.into_inner()))
         .from_err()
         .and_then(move |res| match res {
-            Ok(user) => {
-                let token = create_token(&user)?;
-                let res = json!({ "token": token, "user": user });
-                Ok(HttpResponse::Ok().json(res))
+            Ok(saved) => {
+                let query = visible_to(
+                    QueryParser::new(&saved.query).parse(),
+                    audience,
+                );
+                let path = format!("/s/{}/feed.atom", saved.slug);
+                let newest = search_client
+                    .query_docs_with_limit(query, FEED_MATCHES)
+                    .and_then(move |results| {
+                        let msg = QueryNewest {
+                            ids: results
+                                .docs
+                                .into_iter()
+                                .map(|doc| doc.doc.id)
+                                .collect(),
+                            limit: i64::from(CONFIG.search_limit),
+                            audience,
+                        };
+                        db.send(msg).from_err()
+                    });
+                Either::A(newest.map(move |res| match res {
+                    Ok(bookmarks) => render_feed(AtomFeed::new(
+                        saved.name,
+                        path,
+                        bookmarks.into_iter(),
+                    )),
+                    Err(err) => ServiceError::from(err).error_response(),
+                }))
             }
+            Err(ServiceError::NotFound) => {
+                Either::B(ok(render_not_found("saved search")))
+            }
+            Err(err) => Either::B(ok(err.error_response())),
+        })
+}
+
+fn saved_searches(
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    db.send(QuerySavedSearches)
+        .from_err()
+        .and_then(|res| match res {
+            Ok(searches) => Ok(HttpResponse::Ok().json(searches)),
             Err(err) => Ok(err.error_response()),
         })
 }
 
-fn whoami(user: Result<SlimUser, ServiceError>) -> Result<HttpResponse, Error> {
-    match user {
-        Ok(user) => Ok(HttpResponse::Ok().json(user)),
+fn create_saved_search(
+    data: web::Json<SavedSearchData>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let saved = NewSavedSearch::from(data.into_inner());
+    if saved.slug.is_empty() {
+        let err = ServiceError::BadRequest("Name is required".into());
+        return Either::B(ok(err.error_response()));
+    }
+    if QueryParser::new(&saved.query).parse().is_empty() {
+        let err = ServiceError::BadRequest("Query is empty".into());
+        return Either::B(ok(err.error_response()));
+    }
+    let msg = CreateSavedSearch {
+        search: saved,
+        actor: user.id,
+    };
+    Either::A(db.send(msg).from_err().and_then(|res| match res {
+        Ok(saved) => Ok(HttpResponse::Created().json(saved)),
         Err(err) => Ok(err.error_response()),
+    }))
+}
+
+fn render_html<T: RenderOnce>(page: T) -> HttpResponse {
+    match page.into_string() {
+        Ok(body) => HttpResponse::Ok().content_type("text/html").body(body),
+        _ => HttpResponse::InternalServerError().into(),
     }
 }
 
-fn db_migrations(pool: &r2d2::Pool<ConnectionManager<PgConnection>>) {
-    info!("Handling database migrations...");
-    let conn: &PgConnection = &pool.get().unwrap();
-    embedded_migrations::run_with_output(conn, &mut std::io::stdout())
-        .expect("Failed to run migrations");
+// Missing pages get a page too, rather than the json error of the api
+fn render_not_found(what: &str) -> HttpResponse {
+    let page = PageTemplate::new(iter::empty::<BookmarkItem>()).with_intro(
+        "Not found".to_owned(),
+        format!("There is no such {}.", what),
+    );
+    match page.into_string() {
+        Ok(body) => HttpResponse::NotFound()
+            .content_type("text/html")
+            .body(body),
+        _ => HttpResponse::InternalServerError().into(),
+    }
 }
 
-fn main() {
-    dotenv().ok();
-    env_logger::init();
+fn render_feed<T: RenderOnce>(feed: T) -> HttpResponse {
+    match feed.into_string() {
+        Ok(body) => HttpResponse::Ok()
+            .content_type("application/atom+xml")
+            .body(body),
+        _ => HttpResponse::InternalServerError().into(),
+    }
+}
 
-    let sys = actix_rt::System::new("bookmarks");
-    let pool = create_pool();
+// Search pages are kept once rendered, to be served from the cache, marked
+// stale, while search is down
+fn search_html<F, R, T>(
+    key: String,
+    results: F,
+    render: R,
+) -> impl Future<Item = HttpResponse, Error = Error>
+where
+    F: Future<Item = SearchResults, Error = Error>,
+    R: FnOnce(SearchResults) -> T,
+    T: RenderOnce,
+{
+    results.then(move |results| {
+        let results = match results {
+            Ok(results) => results,
+            Err(err) => {
+                error!("Search failed, serving the cached page: {}", err);
+                return Ok::<_, Error>(cached_html(&key));
+            }
+        };
+        Ok(match render(results).into_string() {
+            Ok(body) => {
+                page_cache::remember(key, body.clone());
+                HttpResponse::Ok().content_type("text/html").body(body)
+            }
+            _ => HttpResponse::InternalServerError().into(),
+        })
+    })
+}
 
-    db_migrations(&pool);
+fn cached_html(key: &str) -> HttpResponse {
+    match page_cache::recall(key) {
+        Some(body) => HttpResponse::Ok()
+            .content_type("text/html")
+            .header(header::WARNING, "110 - \"Response is Stale\"")
+            .body(body),
+        None => HttpResponse::ServiceUnavailable()
+            .content_type("text/html")
+            .body("Search is unavailable, please try again later"),
+    }
+}
 
-    // Start 4 parallel db executors
-    let addr: Addr<DbExecutor> =
-        SyncArbiter::start(4, move || DbExecutor(pool.clone()));
-    let host_port = CONFIG.host_port.parse::<u16>().unwrap_or(8080);
-    // Start http server
-    HttpServer::new(move || {
-        App::new()
-            .wrap(
-                cors::Cors::new()
-                    .allowed_origin(&CONFIG.allowed_origin)
-                    .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
-                    .allowed_headers(vec![
-                        header::AUTHORIZATION,
-                        header::ACCEPT,
-                    ])
-                    .allowed_header(header::CONTENT_TYPE)
-                    .max_age(3600),
-            )
-            .data(addr.clone())
-            .data(SearchClient::new())
-            .service(
-                web::scope("/api")
-                    .service(
-                        web::resource("auth")
-                            .route(web::get().to(whoami))
-                            .route(web::post().to_async(login)),
-                    )
-                    .service(
-                        web::resource("bookmarks:page/{page}")
-                            .route(web::get().to_async(recent_bookmarks)),
-                    )
-                    .service(
-                        web::resource("bookmarks").route(
-                            web::post()
-                                .guard(guard::fn_guard(admin_guard))
-                                .to_async(create_bookmark),
-                        ),
-                    )
-                    .service(
-                        web::resource("bookmarks/search")
-                            .route(web::get().to_async(search_bookmark)),
+// Who the page is for along with the url, as pages differ by audience
+fn page_key(audience: Audience, req: &HttpRequest) -> String {
+    format!("{:?} {}", audience, req.uri())
+}
+
+fn create_bookmark(
+    bookmark: web::Json<NewBookmark>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+    search_client: web::Data<SearchClient>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let bookmark = NewBookmark {
+        user_id: user.id,
+        ..bookmark.into_inner()
+    };
+    db.send(bookmark)
+        .from_err()
+        .and_then(move |created| match created {
+            Ok(created) => {
+                info!("Created database record for: {:?}", &created);
+                let db = db.get_ref().clone();
+                let search_client = search_client.get_ref().clone();
+                Either::A(
+                    index_bookmark(created, db.clone(), &search_client).map(
+                        move |created| {
+                            // fetching the page can be slow, so it is
+                            // archived after responding
+                            Arbiter::spawn(archive_bookmark(
+                                created.clone(),
+                                db,
+                                search_client,
+                            ));
+                            created
+                        },
                     ),
-            )
-            .service(
-                web::resource("/")
-                    .route(web::get().to_async(recent_bookmarks_html)),
-            )
-            .service(
-                web::resource("/search")
-                    .route(web::get().to_async(search_bookmark_html)),
-            )
+                )
+            }
+            Err(err) => Either::B(future::err(Error::from(err))),
+        })
+        .then(|res| match res {
+            Ok(created) => {
+                Ok::<_, Error>(HttpResponse::Created().json(created))
+            }
+            Err(err) => Ok(err.as_response_error().error_response()),
+        })
+}
+
+fn update_status(
+    id: web::Path<i32>,
+    data: web::Json<StatusData>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+    search_client: web::Data<SearchClient>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = UpdateStatus {
+        id: id.into_inner(),
+        status: data.status,
+        owner: user.id,
+    };
+    db.send(msg)
+        .from_err()
+        .and_then(move |updated| match updated {
+            Ok(updated) => {
+                info!("Bookmark(id={}) is now {}", updated.id, updated.status);
+                Either::A(index_bookmark(
+                    updated,
+                    db.get_ref().clone(),
+                    &search_client,
+                ))
+            }
+            Err(err) => Either::B(future::err(Error::from(err))),
+        })
+        .then(|res| match res {
+            Ok(updated) => Ok::<_, Error>(HttpResponse::Ok().json(updated)),
+            Err(err) => Ok(err.as_response_error().error_response()),
+        })
+}
+
+fn toggle_star(
+    id: web::Path<i32>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+    search_client: web::Data<SearchClient>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = ToggleStar {
+        id: id.into_inner(),
+        owner: user.id,
+    };
+    db.send(msg)
+        .from_err()
+        .and_then(move |res| match res {
+            Ok(bookmark) => Either::A(index_bookmark(
+                bookmark,
+                db.get_ref().clone(),
+                &search_client,
+            )),
+            Err(err) => Either::B(future::err(Error::from(err))),
+        })
+        .then(|res| match res {
+            Ok(bookmark) => Ok::<_, Error>(HttpResponse::Ok().json(bookmark)),
+            Err(err) => Ok(err.as_response_error().error_response()),
+        })
+}
+
+fn add_highlight(
+    id: web::Path<i32>,
+    data: web::Json<HighlightData>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+    search_client: web::Data<SearchClient>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let data = data.into_inner();
+    if data.quote.trim().is_empty() {
+        let err = ServiceError::BadRequest("Quote is required".into());
+        return Either::B(ok(err.error_response()));
+    }
+    let msg = AddHighlight {
+        bookmark_id: id.into_inner(),
+        quote: data.quote,
+        comment: data.comment.filter(|c| !c.trim().is_empty()),
+        owner: user.id,
+    };
+    Either::A(
+        db.send(msg)
+            .from_err()
+            .and_then(move |res| match res {
+                Ok(item) => {
+                    Either::A(reindex_highlighted(item, db, &search_client))
+                }
+                Err(err) => Either::B(future::err(Error::from(err))),
+            })
+            .then(|res| match res {
+                Ok(item) => Ok::<_, Error>(HttpResponse::Created().json(item)),
+                Err(err) => Ok(err.as_response_error().error_response()),
+            }),
+    )
+}
+
+fn delete_highlight(
+    id: web::Path<i32>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+    search_client: web::Data<SearchClient>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = DeleteHighlight {
+        id: id.into_inner(),
+        owner: user.id,
+    };
+    db.send(msg)
+        .from_err()
+        .and_then(move |res| match res {
+            Ok(item) => {
+                Either::A(reindex_highlighted(item, db, &search_client))
+            }
+            Err(err) => Either::B(future::err(Error::from(err))),
+        })
+        .then(|res| match res {
+            Ok(item) => Ok::<_, Error>(HttpResponse::Ok().json(item)),
+            Err(err) => Ok(err.as_response_error().error_response()),
+        })
+}
+
+fn reindex_highlighted(
+    item: HighlightedBookmark,
+    db: web::Data<Addr<DbExecutor>>,
+    search_client: &SearchClient,
+) -> impl Future<Item = HighlightedBookmark, Error = Error> {
+    let HighlightedBookmark {
+        bookmark,
+        highlights,
+    } = item;
+    index_bookmark(bookmark, db.get_ref().clone(), search_client).map(
+        move |bookmark| HighlightedBookmark {
+            bookmark,
+            highlights,
+        },
+    )
+}
+
+fn tags(
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    db.send(QueryTags(audience))
+        .from_err()
+        .and_then(|res| match res {
+            Ok(tags) => Ok(HttpResponse::Ok().json(tags)),
+            Err(err) => Ok(err.error_response()),
+        })
+}
+
+fn tags_html(
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    db.send(QueryTags(audience))
+        .from_err()
+        .and_then(|res| match res {
+            Ok(tags) => {
+                let cloud = TagCloud::new(tags);
+                Ok(render_html(PageTemplate::new(iter::once(cloud))))
+            }
+            Err(err) => Ok(err.error_response()),
+        })
+}
+
+fn tagged_bookmarks(
+    path: web::Path<(String, i64)>,
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let (tag, page) = path.into_inner();
+    db.send(QueryTagged {
+        tag,
+        page,
+        audience,
     })
-    .bind(("0.0.0.0", host_port))
-    .unwrap()
-    .start();
+    .from_err()
+    .and_then(|res| match res {
+        Ok(bookmarks) => {
+            let contents: Vec<_> =
+                bookmarks.data.iter().map(bookmark_jsonml).collect();
+            let res = PageData {
+                data: contents,
+                total_pages: bookmarks.total_pages,
+                next_page: bookmarks.next_page,
+            };
+            Ok(HttpResponse::Ok().json(res))
+        }
+        _ => Ok(HttpResponse::InternalServerError().into()),
+    })
+}
 
-    info!("Started http server: 0.0.0.0:{}", host_port);
-    let _ = sys.run();
+fn tagged_bookmarks_html(
+    tag: web::Path<String>,
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let tag = tag.into_inner();
+    let msg = QueryTagged {
+        tag: tag.clone(),
+        page: 1,
+        audience,
+    };
+    db.send(msg).from_err().and_then(move |res| match res {
+        Ok(bookmarks) => {
+            let items = bookmarks.data.into_iter().map(BookmarkItem::new);
+            let source = format!("/api{}/bookmarks:page", tag_href(&tag));
+            let page =
+                PageTemplate::new_with_next_page(bookmarks.next_page, items)
+                    .with_breadcrumbs(Breadcrumbs::for_tag(&tag))
+                    .with_source(source);
+            Ok(render_html(page))
+        }
+        _ => Ok(HttpResponse::InternalServerError().into()),
+    })
+}
+
+// Tag changes touch many bookmarks, all of them are indexed again before
+// responding, so that searching by the new tag works right away.
+fn reindex_retagged(
+    changed: Result<Vec<Bookmark>, ServiceError>,
+    db: web::Data<Addr<DbExecutor>>,
+    search_client: &SearchClient,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    match changed {
+        Ok(bookmarks) => Either::A(
+            index_bookmarks(bookmarks, db.get_ref().clone(), search_client)
+                .map(|updated| {
+                    HttpResponse::Ok().json(json!({ "updated": updated.len() }))
+                }),
+        ),
+        Err(err) => Either::B(ok(err.error_response())),
+    }
+}
+
+fn rename_tag(
+    tag: web::Path<String>,
+    data: web::Json<RenameTagData>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+    search_client: web::Data<SearchClient>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = RenameTag {
+        from: tag.into_inner(),
+        to: data.into_inner().name,
+        owner: Some(user.id),
+    };
+    db.send(msg)
+        .from_err()
+        .and_then(move |changed| reindex_retagged(changed, db, &search_client))
+}
+
+fn merge_tags(
+    data: web::Json<MergeTagsData>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+    search_client: web::Data<SearchClient>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let MergeTagsData { tags, into } = data.into_inner();
+    let msg = MergeTags {
+        from: tags,
+        into,
+        owner: Some(user.id),
+    };
+    db.send(msg)
+        .from_err()
+        .and_then(move |changed| reindex_retagged(changed, db, &search_client))
+}
+
+// Indexes every bookmark again in the background, as after changing the
+// index
+fn reindex(
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    db.send(ClearIndexed { actor: user.id })
+        .from_err()
+        .and_then(move |res| match res {
+            Ok(cleared) => {
+                Arbiter::spawn(index_pending(db.get_ref().clone()));
+                Ok(HttpResponse::Accepted()
+                    .json(json!({ "bookmarks": cleared })))
+            }
+            Err(err) => Ok(err.error_response()),
+        })
+}
+
+fn delete_tag(
+    tag: web::Path<String>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+    search_client: web::Data<SearchClient>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = DeleteTag {
+        tag: tag.into_inner(),
+        owner: Some(user.id),
+    };
+    db.send(msg)
+        .from_err()
+        .and_then(move |changed| reindex_retagged(changed, db, &search_client))
+}
+
+fn collection_html(
+    slug: web::Path<String>,
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = QueryCollection {
+        slug: slug.into_inner(),
+        audience,
+    };
+    db.send(msg).from_err().and_then(|res| match res {
+        Ok((collection, bookmarks)) => {
+            let items = bookmarks.into_iter().map(BookmarkItem::new);
+            let page = PageTemplate::new(items)
+                .with_intro(collection.title, collection.intro);
+            Ok(render_html(page))
+        }
+        Err(ServiceError::NotFound) => Ok(render_not_found("collection")),
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn collection(
+    slug: web::Path<String>,
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = QueryCollection {
+        slug: slug.into_inner(),
+        audience,
+    };
+    db.send(msg).from_err().and_then(|res| match res {
+        Ok((collection, bookmarks)) => {
+            let contents: Vec<_> =
+                bookmarks.iter().map(bookmark_jsonml).collect();
+            Ok(HttpResponse::Ok().json(json!({
+                "collection": collection,
+                "data": contents,
+            })))
+        }
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn collections(
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    db.send(QueryCollections)
+        .from_err()
+        .and_then(|res| match res {
+            Ok(collections) => Ok(HttpResponse::Ok().json(collections)),
+            Err(err) => Ok(err.error_response()),
+        })
+}
+
+fn create_collection(
+    data: web::Json<CollectionData>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let collection = NewCollection::from(data.into_inner());
+    if collection.slug.is_empty() {
+        let err = ServiceError::BadRequest("Title is required".into());
+        return Either::B(ok(err.error_response()));
+    }
+    let msg = CreateCollection {
+        collection,
+        actor: user.id,
+    };
+    Either::A(db.send(msg).from_err().and_then(|res| match res {
+        Ok(collection) => Ok(HttpResponse::Created().json(collection)),
+        Err(err) => Ok(err.error_response()),
+    }))
+}
+
+fn update_collection(
+    id: web::Path<i32>,
+    data: web::Json<CollectionData>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let data = data.into_inner();
+    let msg = UpdateCollection {
+        id: id.into_inner(),
+        title: data.title,
+        intro: data.intro,
+        actor: user.id,
+    };
+    db.send(msg).from_err().and_then(|res| match res {
+        Ok(collection) => Ok(HttpResponse::Ok().json(collection)),
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn delete_collection(
+    id: web::Path<i32>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = DeleteCollection {
+        id: id.into_inner(),
+        actor: user.id,
+    };
+    db.send(msg).from_err().and_then(|res| match res {
+        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn add_collection_item(
+    id: web::Path<i32>,
+    data: web::Json<CollectionItemData>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = AddCollectionItem {
+        collection_id: id.into_inner(),
+        bookmark_id: data.bookmark_id,
+        actor: user.id,
+    };
+    db.send(msg).from_err().and_then(|res| match res {
+        Ok(item) => Ok(HttpResponse::Created().json(item)),
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn remove_collection_item(
+    path: web::Path<(i32, i32)>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let (collection_id, bookmark_id) = path.into_inner();
+    let msg = RemoveCollectionItem {
+        collection_id,
+        bookmark_id,
+        actor: user.id,
+    };
+    db.send(msg).from_err().and_then(|res| match res {
+        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn reorder_collection(
+    id: web::Path<i32>,
+    data: web::Json<CollectionOrderData>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = ReorderCollection {
+        collection_id: id.into_inner(),
+        bookmark_ids: data.into_inner().bookmark_ids,
+        actor: user.id,
+    };
+    db.send(msg).from_err().and_then(|res| match res {
+        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+// Fetches the bookmarked page and keeps the extracted article text, which
+// is then indexed along with the bookmark.
+fn archive_bookmark(
+    bookmark: Bookmark,
+    db: Addr<DbExecutor>,
+    search_client: SearchClient,
+) -> impl Future<Item = (), Error = ()> {
+    let bookmark_id = bookmark.id;
+    let url = bookmark.url.clone();
+    let index_db = db.clone();
+
+    // failing to fetch is recorded as well, as a snapshot without status
+    fetch_page(&Client::default(), &bookmark.url)
+        .then(move |res| {
+            let snapshot = match res {
+                Ok(page) => {
+                    info!(
+                        "Fetched Bookmark(id={}) page: {} {}",
+                        bookmark_id, page.status, page.final_url
+                    );
+                    let extracted = if page.status.is_success() {
+                        let html = String::from_utf8_lossy(&page.body);
+                        readability::extract(&html)
+                    } else {
+                        Extracted::default()
+                    };
+                    NewSnapshot {
+                        bookmark_id,
+                        http_status: Some(i32::from(page.status.as_u16())),
+                        final_url: page.final_url,
+                        title: extracted.title,
+                        byline: extracted.byline,
+                        content: extracted.text,
+                    }
+                }
+                Err(err) => {
+                    warn!(
+                        "Failed to fetch Bookmark(id={}) page: {:?}",
+                        bookmark_id, err
+                    );
+                    NewSnapshot {
+                        bookmark_id,
+                        http_status: None,
+                        final_url: url,
+                        title: None,
+                        byline: None,
+                        content: String::new(),
+                    }
+                }
+            };
+            db.send(snapshot).from_err()
+        })
+        .and_then(|snapshot| snapshot.map_err(Error::from))
+        .and_then(move |_| index_bookmark(bookmark, index_db, &search_client))
+        .map(|_| ())
+        .map_err(move |err| {
+            error!("Failed to archive Bookmark(id={}): {:?}", bookmark_id, err)
+        })
+}
+
+// Number of related bookmarks shown with a bookmark
+const RELATED_LIMIT: u32 = 5;
+
+fn find_related(
+    bookmark: &Bookmark,
+    audience: Audience,
+    search_client: &SearchClient,
+) -> impl Future<Item = Vec<BookmarkDoc>, Error = Error> {
+    match more_like_this(bookmark) {
+        Some(query) => Either::A(
+            search_client
+                .query_docs_with_limit(
+                    visible_to(query, audience),
+                    RELATED_LIMIT,
+                )
+                .map(|results| {
+                    results.docs.into_iter().map(|doc| doc.doc).collect()
+                }),
+        ),
+        None => Either::B(ok(vec![])),
+    }
+}
+
+fn related_bookmarks(
+    id: web::Path<i32>,
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+    search_client: web::Data<SearchClient>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = QueryBookmark {
+        id: id.into_inner(),
+        audience,
+    };
+    db.send(msg).from_err().and_then(move |res| match res {
+        Ok(item) => Either::A(
+            find_related(&item.bookmark, audience, &search_client)
+                .map(|related| HttpResponse::Ok().json(related)),
+        ),
+        Err(err) => Either::B(ok(err.error_response())),
+    })
+}
+
+fn bookmark_html(
+    id: web::Path<i32>,
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+    search_client: web::Data<SearchClient>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = QueryBookmark {
+        id: id.into_inner(),
+        audience,
+    };
+    db.send(msg).from_err().and_then(move |res| match res {
+        Ok(item) => Either::A(
+            // the bookmark is still worth showing when search is down
+            find_related(&item.bookmark, audience, &search_client).then(
+                move |related| {
+                    let related = related.unwrap_or_else(|err| {
+                        error!("Failed to find related bookmarks: {:?}", err);
+                        vec![]
+                    });
+                    let item = Permalink::new(item, related);
+                    let page = PageTemplate::new(iter::once(item));
+                    Ok::<_, Error>(render_html(page))
+                },
+            ),
+        ),
+        Err(err) => Either::B(ok(err.error_response())),
+    })
+}
+
+fn bookmark_archive_html(
+    id: web::Path<i32>,
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = QuerySnapshot {
+        bookmark_id: id.into_inner(),
+        audience,
+    };
+    db.send(msg).from_err().and_then(|res| match res {
+        Ok((bookmark, snapshot)) => {
+            let item = ArchiveItem::new(bookmark, snapshot);
+            Ok(render_html(PageTemplate::new(iter::once(item))))
+        }
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn broken_links(
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    db.send(QueryBrokenLinks(user.id))
+        .from_err()
+        .and_then(|res| match res {
+            Ok(bookmarks) => Ok(HttpResponse::Ok().json(bookmarks)),
+            Err(err) => Ok(err.error_response()),
+        })
+}
+
+fn audit_log(
+    page: web::Path<i64>,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    db.send(QueryAuditLog {
+        page: page.into_inner(),
+    })
+    .from_err()
+    .and_then(|res| match res {
+        Ok(entries) => Ok(HttpResponse::Ok().json(entries)),
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn broken_links_html(
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    db.send(QueryBrokenLinks(user.id))
+        .from_err()
+        .and_then(|res| match res {
+            Ok(bookmarks) => {
+                let items = bookmarks.into_iter().map(BrokenLinkItem::new);
+                Ok(render_html(PageTemplate::new(items)))
+            }
+            Err(err) => Ok(err.error_response()),
+        })
+}
+
+fn user_bookmarks(
+    path: web::Path<(String, i64)>,
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let (name, page) = path.into_inner();
+    db.send(QueryUserBookmarks {
+        name,
+        page,
+        audience,
+    })
+    .from_err()
+    .and_then(|res| match res {
+        Ok(bookmarks) => {
+            let contents: Vec<_> =
+                bookmarks.data.iter().map(bookmark_jsonml).collect();
+            let res = PageData {
+                data: contents,
+                total_pages: bookmarks.total_pages,
+                next_page: bookmarks.next_page,
+            };
+            Ok(HttpResponse::Ok().json(res))
+        }
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+// Reading list of a single user, private bookmarks are only included for
+// users allowed to see private items.
+fn user_bookmarks_html(
+    name: web::Path<String>,
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let name = name.into_inner();
+    let msg = QueryUserBookmarks {
+        name: name.clone(),
+        page: 1,
+        audience,
+    };
+    db.send(msg).from_err().and_then(move |res| match res {
+        Ok(bookmarks) => {
+            let items = bookmarks.data.into_iter().map(BookmarkItem::new);
+            let href = user_href(&name);
+            let page =
+                PageTemplate::new_with_next_page(bookmarks.next_page, items)
+                    .with_breadcrumbs(Breadcrumbs::for_user(&name))
+                    .with_source(format!("/api{}/bookmarks:page", href))
+                    .with_feed(format!("{}/feed.atom", href))
+                    .with_search_action(format!("{}/search", href));
+            Ok(render_html(page))
+        }
+        Err(ServiceError::NotFound) => Ok(render_not_found("user")),
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn user_bookmarks_feed(
+    name: web::Path<String>,
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let name = name.into_inner();
+    let msg = QueryUserBookmarks {
+        name: name.clone(),
+        page: 1,
+        audience,
+    };
+    db.send(msg).from_err().and_then(move |res| match res {
+        Ok(bookmarks) => Ok(render_feed(AtomFeed::new(
+            format!("Insightful Reads: {}", name),
+            format!("{}/feed.atom", user_href(&name)),
+            bookmarks.data.into_iter(),
+        ))),
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn user_search_html(
+    req: HttpRequest,
+    name: web::Path<String>,
+    audience: Audience,
+    db: web::Data<Addr<DbExecutor>>,
+    search_client: web::Data<SearchClient>,
+    search: Option<web::Query<Search>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let name = name.into_inner();
+    let query_string = search.map(|s| s.into_inner().q).unwrap_or_default();
+    let query = QueryParser::new(&query_string).parse();
+    if query.is_empty() {
+        return Either::B(ok(HttpResponse::Found()
+            .header(http::header::LOCATION, user_href(&name))
+            .finish()));
+    }
+    Either::A(db.send(QueryUser(name)).from_err().and_then(
+        move |res| match res {
+            Ok(user) => {
+                let query = owned_by(visible_to(query, audience), user.id);
+                Either::A(search_html(
+                    page_key(audience, &req),
+                    search_client.query_docs(query),
+                    move |bookmarks| {
+                        let items = bookmarks
+                            .docs
+                            .into_iter()
+                            .map(|doc| BookmarkItem::new(doc.doc));
+                        let href = user_href(&user.name);
+                        PageTemplate::new_with_query(items, query_string)
+                            .with_breadcrumbs(Breadcrumbs::for_user(&user.name))
+                            .with_search_action(format!("{}/search", href))
+                    },
+                ))
+            }
+            Err(ServiceError::NotFound) => {
+                Either::B(ok(render_not_found("user")))
+            }
+            Err(err) => Either::B(ok(err.error_response())),
+        },
+    ))
+}
+
+// Cookie sessions get the tokens in cookies, along with a csrf token
+fn token_response(
+    user: SlimUser,
+    refresh_token: String,
+    cookie: bool,
+) -> Result<HttpResponse, Error> {
+    let token = create_token(&user)?;
+    if !cookie {
+        let res = json!({
+            "token": token,
+            "refresh_token": refresh_token,
+            "user": user,
+        });
+        return Ok(HttpResponse::Ok().json(res));
+    }
+
+    let (cookies, csrf_token) = session_cookies(token, refresh_token);
+    let mut res = HttpResponse::Ok();
+    for cookie in cookies {
+        res.cookie(cookie);
+    }
+    Ok(res.json(json!({ "csrf_token": csrf_token, "user": user })))
+}
+
+// Address of the client, see `client_ip`
+fn remote_ip(req: &HttpRequest) -> String {
+    let forwarded_for: Vec<&str> = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+    client_ip(req.peer_addr(), &forwarded_for, CONFIG.trusted_proxies)
+}
+
+fn user_agent(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+}
+
+// Returns the user along with the session, and its refresh token
+fn start_session(
+    user: SlimUser,
+    user_agent: Option<String>,
+    db: &Addr<DbExecutor>,
+) -> impl Future<Item = (SlimUser, String), Error = Error> {
+    let (refresh_token, refresh_hash) = new_refresh_token();
+    let session = NewSession {
+        user_id: user.id,
+        expires: refresh_expiry(),
+        refresh_hash,
+        user_agent,
+    };
+    db.send(session).from_err().and_then(move |res| {
+        let session = res?;
+        let user = SlimUser {
+            session_id: Some(session.id),
+            ..user
+        };
+        Ok((user, refresh_token))
+    })
+}
+
+fn login(
+    auth_data: web::Json<AuthData>,
+    req: HttpRequest,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let ip = remote_ip(&req);
+    let account = auth_data.email.trim().to_lowercase();
+    if let Err(err) = check_login(&ip, &account) {
+        return Either::B(ok(err.error_response()));
+    }
+
+    let user_agent = user_agent(&req);
+    let cookie = auth_data.cookie;
+    Either::A(
+        db.send(auth_data.into_inner()).from_err().and_then(
+            move |res| match res {
+                Ok(user) => {
+                    login_succeeded(&account);
+                    Either::A(start_session(user, user_agent, &db).and_then(
+                        move |(user, refresh_token)| {
+                            token_response(user, refresh_token, cookie)
+                        },
+                    ))
+                }
+                // wrong credentials, as opposed to the database failing
+                Err(err @ ServiceError::BadRequest(_)) => {
+                    Either::B(Either::A(failed_login(err, ip, account, &db)))
+                }
+                Err(err) => Either::B(Either::B(ok(err.error_response()))),
+            },
+        ),
+    )
+}
+
+// Counts a wrong password towards blocking further attempts, and records it
+fn failed_login(
+    err: ServiceError,
+    ip: String,
+    account: String,
+    db: &Addr<DbExecutor>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let blocked = login_failed(&ip, &account);
+    let msg = RecordFailedLogin {
+        email: account,
+        ip,
+        blocked_secs: blocked.map(|delay| delay.as_secs()),
+    };
+    db.send(msg).then(move |res| {
+        if let Ok(Err(record_err)) = res {
+            error!("Failed to record login: {}", record_err);
+        }
+        Ok::<_, Error>(err.error_response())
+    })
+}
+
+// Only sent back to the callback, and along with the redirect from the
+// provider, which is a cross site navigation
+fn flow_cookie(value: String) -> Cookie<'static> {
+    let secure = CONFIG
+        .oidc
+        .as_ref()
+        .map_or(false, |config| config.redirect_url.starts_with("https:"));
+    Cookie::build(FLOW_COOKIE, value)
+        .path("/api/auth/oidc")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .secure(secure)
+        .finish()
+}
+
+// Sends users to the identity provider, remembering the flow in a cookie
+fn oidc_login(
+    query: web::Query<LoginQuery>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let config = match CONFIG.oidc {
+        Some(ref config) => config,
+        None => return Either::B(ok(HttpResponse::NotFound().finish())),
+    };
+    let flow = Flow::start(query.cookie);
+    Either::A(discover(&Client::default(), config).then(move |res| {
+        let url =
+            res.and_then(|discovery| authorize_url(&discovery, config, &flow));
+        match url {
+            Ok(url) => Ok(HttpResponse::Found()
+                .header(header::LOCATION, url)
+                .cookie(flow_cookie(flow.to_cookie_value()))
+                .finish()),
+            Err(err) => Ok(err.error_response()),
+        }
+    }))
+}
+
+// Signs in the user the provider sent back, handing the tokens to the
+// admin ui in cookies, or in the fragment, which isn't sent to servers
+fn oidc_callback(
+    callback: web::Query<Callback>,
+    req: HttpRequest,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let config = match CONFIG.oidc {
+        Some(ref config) => config,
+        None => return Either::B(ok(HttpResponse::NotFound().finish())),
+    };
+    let flow = req
+        .cookie(FLOW_COOKIE)
+        .and_then(|cookie| Flow::from_cookie_value(cookie.value()))
+        .filter(|flow| flow.matches_state(&callback.state));
+    let flow = match flow {
+        Some(flow) => flow,
+        None => {
+            let res = ServiceError::Unauthorized.error_response();
+            return Either::B(ok(res));
+        }
+    };
+
+    let client = Client::default();
+    let user_agent = user_agent(&req);
+    let code = callback.into_inner().code;
+    let cookie_session = flow.cookie;
+    Either::A(
+        discover(&client, config)
+            .and_then(move |discovery| {
+                fetch_user_info(&client, &discovery, config, &code, &flow)
+            })
+            .and_then(move |info| login_of(&info, config))
+            .from_err()
+            .and_then(move |msg| {
+                db.send(msg)
+                    .from_err()
+                    .and_then(|res| res.map_err(Error::from))
+                    .and_then(move |user| start_session(user, user_agent, &db))
+            })
+            .and_then(move |(user, refresh_token)| {
+                let token = create_token(&user)?;
+                let mut res = HttpResponse::Found();
+                if cookie_session {
+                    let (cookies, _) = session_cookies(token, refresh_token);
+                    for cookie in cookies {
+                        res.cookie(cookie);
+                    }
+                    res.header(header::LOCATION, config.ui_url.as_str());
+                } else {
+                    let location = format!(
+                        "{}#token={}&refresh_token={}",
+                        config.ui_url, token, refresh_token
+                    );
+                    res.header(header::LOCATION, location);
+                }
+                Ok(res.del_cookie(&flow_cookie(String::new())).finish())
+            }),
+    )
+}
+
+// Cookie sessions send the refresh token in a cookie instead of the body
+fn refresh(
+    data: Option<web::Json<RefreshData>>,
+    req: HttpRequest,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let (old_token, cookie) = match data {
+        Some(data) => (data.into_inner().refresh_token, false),
+        None => match cookie_value(req.head(), REFRESH_COOKIE) {
+            Some(token) if check_csrf(req.head()) => (token, true),
+            _ => {
+                let res = ServiceError::Unauthorized.error_response();
+                return Either::B(ok(res));
+            }
+        },
+    };
+
+    let (refresh_token, new_refresh_hash) = new_refresh_token();
+    let msg = RefreshSession {
+        refresh_hash: hash_token(&old_token),
+        new_refresh_hash,
+        expires: refresh_expiry(),
+    };
+    Either::A(db.send(msg).from_err().and_then(move |res| match res {
+        Ok(user) => token_response(user, refresh_token, cookie),
+        Err(err) => Ok(err.error_response()),
+    }))
+}
+
+fn logout(
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let session_id = match user.session_id {
+        Some(id) => id,
+        None => {
+            let res = ServiceError::Unauthorized.error_response();
+            return Either::B(ok(res));
+        }
+    };
+    let msg = RevokeSession {
+        id: session_id,
+        user_id: Some(user.id),
+        actor: user.id,
+    };
+    Either::A(db.send(msg).from_err().and_then(|res| match res {
+        Ok(session) => {
+            mark_revoked(session.id);
+            let mut res = HttpResponse::NoContent();
+            for cookie in cleared_session_cookies() {
+                res.del_cookie(&cookie);
+            }
+            Ok(res.finish())
+        }
+        Err(err) => Ok(err.error_response()),
+    }))
+}
+
+fn sessions(
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    db.send(QuerySessions).from_err().and_then(|res| match res {
+        Ok(sessions) => Ok(HttpResponse::Ok().json(sessions)),
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn revoke_session(
+    id: web::Path<i32>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = RevokeSession {
+        id: id.into_inner(),
+        user_id: None,
+        actor: user.id,
+    };
+    db.send(msg).from_err().and_then(|res| match res {
+        Ok(session) => {
+            mark_revoked(session.id);
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn whoami(user: Result<SlimUser, ServiceError>) -> Result<HttpResponse, Error> {
+    match user {
+        Ok(user) => Ok(HttpResponse::Ok().json(user)),
+        Err(err) => Ok(err.error_response()),
+    }
+}
+
+// The current password is throttled like signing in with it
+fn change_password(
+    data: web::Json<PasswordData>,
+    user: SlimUser,
+    req: HttpRequest,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let PasswordData {
+        old_password,
+        new_password,
+    } = data.into_inner();
+    let ip = remote_ip(&req);
+    let account = user.email.to_lowercase();
+    let checked = check_login(&ip, &account).and_then(|_| {
+        check_password(
+            &new_password,
+            &[user.email.as_str(), user.name.as_str()],
+        )
+    });
+    if let Err(err) = checked {
+        return Either::B(ok(err.error_response()));
+    }
+
+    let msg = ChangePassword {
+        user_id: user.id,
+        session_id: user.session_id,
+        old_password,
+        new_password,
+    };
+    Either::A(db.send(msg).from_err().and_then(move |res| match res {
+        Ok(()) => {
+            login_succeeded(&account);
+            Either::A(ok(HttpResponse::NoContent().finish()))
+        }
+        Err(err @ ServiceError::BadRequest(_)) => {
+            Either::B(Either::A(failed_login(err, ip, account, &db)))
+        }
+        Err(err) => Either::B(Either::B(ok(err.error_response()))),
+    }))
+}
+
+fn users(
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    db.send(QueryUsers).from_err().and_then(|res| match res {
+        Ok(users) => Ok(HttpResponse::Ok().json(users)),
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn create_user(
+    data: web::Json<UserData>,
+    admin: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = CreateUser {
+        data: data.into_inner(),
+        by: admin.id,
+    };
+    db.send(msg).from_err().and_then(|res| match res {
+        Ok(user) => Ok(HttpResponse::Created().json(user)),
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn update_user(
+    id: web::Path<i32>,
+    data: web::Json<UserChanges>,
+    admin: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = UpdateUser {
+        id: id.into_inner(),
+        changes: data.into_inner(),
+        by: admin.id,
+    };
+    db.send(msg).from_err().and_then(|res| match res {
+        Ok(user) => Ok(HttpResponse::Ok().json(user)),
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn api_tokens(
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    db.send(QueryApiTokens(user.id))
+        .from_err()
+        .and_then(|res| match res {
+            Ok(tokens) => Ok(HttpResponse::Ok().json(tokens)),
+            Err(err) => Ok(err.error_response()),
+        })
+}
+
+fn create_api_token(
+    data: web::Json<ApiTokenData>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let ApiTokenData { name, scope } = data.into_inner();
+    let (token, token_hash) = new_api_token();
+    let msg = NewApiToken {
+        user_id: user.id,
+        name,
+        scope,
+        token_hash,
+    };
+    db.send(msg).from_err().and_then(move |res| match res {
+        Ok(api_token) => {
+            add_token(&api_token, user);
+            // the token itself is only ever shown here
+            let res = json!({ "token": token, "api_token": api_token });
+            Ok(HttpResponse::Created().json(res))
+        }
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn revoke_api_token(
+    id: web::Path<i32>,
+    user: SlimUser,
+    db: web::Data<Addr<DbExecutor>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let msg = RevokeApiToken {
+        id: id.into_inner(),
+        user_id: user.id,
+    };
+    db.send(msg).from_err().and_then(|res| match res {
+        Ok(api_token) => {
+            remove_token(&api_token);
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(err) => Ok(err.error_response()),
+    })
+}
+
+fn db_migrations(pool: &r2d2::Pool<ConnectionManager<PgConnection>>) {
+    info!("Handling database migrations...");
+    let conn: &PgConnection = &pool.get().unwrap();
+    embedded_migrations::run_with_output(conn, &mut std::io::stdout())
+        .expect("Failed to run migrations");
+}
+
+// Up as long as the process serves requests
+fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+// Whether the server can take traffic, it still can with search down
+fn readyz(
+    db: web::Data<Addr<DbExecutor>>,
+    search_client: web::Data<SearchClient>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let started = Instant::now();
+    let database = db.send(CheckDatabase).then(move |res| {
+        let latency = started.elapsed();
+        let checks = match res {
+            Ok(Ok(applied)) => (
+                Check::up(latency),
+                check_migrations(applied.as_ref().map(String::as_str), latency),
+            ),
+            _ => (
+                Check::down(latency, "unavailable"),
+                Check::down(latency, "unknown"),
+            ),
+        };
+        Ok::<_, Error>(checks)
+    });
+    let started = Instant::now();
+    let search = search_client.ping().then(move |res| {
+        let latency = started.elapsed();
+        Ok::<_, Error>(match res {
+            Ok(()) => Check::up(latency),
+            Err(err) => {
+                error!("Search is unavailable: {}", err);
+                Check::down(latency, "unavailable")
+            }
+        })
+    });
+    database
+        .join(search)
+        .map(|((database, migrations), search)| {
+            let report = Report::new(database, migrations, search);
+            if report.is_ready() {
+                HttpResponse::Ok().json(report)
+            } else {
+                HttpResponse::ServiceUnavailable().json(report)
+            }
+        })
+}
+
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/healthz", web::get().to(healthz))
+        .route("/readyz", web::get().to_async(readyz));
+    cfg.service(
+        web::scope("/api")
+            .service(
+                web::resource("auth")
+                    .route(web::get().to(whoami))
+                    .route(web::post().to_async(login)),
+            )
+            .service(
+                web::resource("auth/password")
+                    .route(web::post().to_async(change_password)),
+            )
+            .service(
+                web::resource("auth/refresh")
+                    .route(web::post().to_async(refresh)),
+            )
+            .service(
+                web::resource("auth/logout")
+                    .route(web::post().to_async(logout)),
+            )
+            .service(
+                web::resource("auth/oidc/login")
+                    .route(web::get().to_async(oidc_login)),
+            )
+            .service(
+                web::resource("auth/oidc/callback")
+                    .route(web::get().to_async(oidc_callback)),
+            )
+            .service(
+                web::resource("sessions")
+                    .wrap(Require::new(Permission::ManageUsers))
+                    .route(web::get().to_async(sessions)),
+            )
+            .service(
+                web::resource("sessions/{id}")
+                    .wrap(Require::new(Permission::ManageUsers))
+                    .route(web::delete().to_async(revoke_session)),
+            )
+            .service(
+                web::resource("users")
+                    .wrap(Require::new(Permission::ManageUsers))
+                    .route(web::get().to_async(users))
+                    .route(web::post().to_async(create_user)),
+            )
+            .service(
+                web::resource("users/{id}")
+                    .wrap(Require::new(Permission::ManageUsers))
+                    .route(web::put().to_async(update_user)),
+            )
+            .service(
+                web::resource("tokens")
+                    .wrap(Require::new(Permission::ViewPrivate))
+                    .route(web::get().to_async(api_tokens))
+                    .route(web::post().to_async(create_api_token)),
+            )
+            .service(
+                web::resource("tokens/{id}")
+                    .wrap(Require::new(Permission::ViewPrivate))
+                    .route(web::delete().to_async(revoke_api_token)),
+            )
+            .service(
+                web::resource("bookmarks:page/{page}")
+                    .route(web::get().to_async(recent_bookmarks)),
+            )
+            .service(
+                web::resource("bookmarks")
+                    .wrap(Require::new(Permission::EditBookmarks))
+                    .route(web::post().to_async(create_bookmark)),
+            )
+            .service(
+                web::resource("bookmarks/{id}/status")
+                    .wrap(Require::new(Permission::EditBookmarks))
+                    .route(web::put().to_async(update_status)),
+            )
+            .service(
+                web::resource("bookmarks/{id}/related")
+                    .route(web::get().to_async(related_bookmarks)),
+            )
+            .service(
+                web::resource("bookmarks/{id}/star")
+                    .wrap(Require::new(Permission::EditBookmarks))
+                    .route(web::post().to_async(toggle_star)),
+            )
+            .service(
+                web::resource("bookmarks/{id}/highlights")
+                    .wrap(Require::new(Permission::EditBookmarks))
+                    .route(web::post().to_async(add_highlight)),
+            )
+            .service(
+                web::resource("highlights/{id}")
+                    .wrap(Require::new(Permission::EditBookmarks))
+                    .route(web::delete().to_async(delete_highlight)),
+            )
+            .service(
+                web::resource("best:page/{page}")
+                    .route(web::get().to_async(starred_bookmarks)),
+            )
+            .service(
+                web::resource("queue:page/{page}")
+                    .wrap(Require::new(Permission::ViewPrivate))
+                    .route(web::get().to_async(queued_bookmarks)),
+            )
+            .service(
+                web::resource("bookmarks/search")
+                    .route(web::get().to_async(search_bookmark)),
+            )
+            .service(
+                web::resource("saved_searches")
+                    .wrap(
+                        Require::new(Permission::ViewPrivate)
+                            .only(http::Method::GET),
+                    )
+                    .wrap(
+                        Require::new(Permission::EditBookmarks)
+                            .only(http::Method::POST),
+                    )
+                    .route(web::get().to_async(saved_searches))
+                    .route(web::post().to_async(create_saved_search)),
+            )
+            .service(
+                web::resource("collections")
+                    .wrap(
+                        Require::new(Permission::ViewPrivate)
+                            .only(http::Method::GET),
+                    )
+                    .wrap(
+                        Require::new(Permission::EditBookmarks)
+                            .only(http::Method::POST),
+                    )
+                    .route(web::get().to_async(collections))
+                    .route(web::post().to_async(create_collection)),
+            )
+            .service(
+                web::resource("collections/{id}")
+                    .wrap(Require::new(Permission::EditBookmarks))
+                    .route(web::put().to_async(update_collection))
+                    .route(web::delete().to_async(delete_collection)),
+            )
+            .service(
+                web::resource("collections/{id}/items")
+                    .wrap(Require::new(Permission::EditBookmarks))
+                    .route(web::post().to_async(add_collection_item)),
+            )
+            .service(
+                web::resource("collections/{id}/items/{bookmark_id}")
+                    .wrap(Require::new(Permission::EditBookmarks))
+                    .route(web::delete().to_async(remove_collection_item)),
+            )
+            .service(
+                web::resource("collections/{id}/order")
+                    .wrap(Require::new(Permission::EditBookmarks))
+                    .route(web::put().to_async(reorder_collection)),
+            )
+            .service(
+                web::resource("c/{slug}")
+                    .route(web::get().to_async(collection)),
+            )
+            .service(web::resource("tags").route(web::get().to_async(tags)))
+            .service(
+                web::resource("reindex")
+                    .wrap(Require::new(Permission::ManageIndex))
+                    .route(web::post().to_async(reindex)),
+            )
+            .service(
+                web::resource("tags/merge")
+                    .wrap(Require::new(Permission::ManageTags))
+                    .route(web::post().to_async(merge_tags)),
+            )
+            .service(
+                web::resource("tags/{tag:.+}/bookmarks:page/{page}")
+                    .route(web::get().to_async(tagged_bookmarks)),
+            )
+            .service(
+                web::resource("tags/{tag:.+}")
+                    .wrap(Require::new(Permission::ManageTags))
+                    .route(web::put().to_async(rename_tag))
+                    .route(web::delete().to_async(delete_tag)),
+            )
+            .service(
+                web::resource("broken_links")
+                    .wrap(Require::new(Permission::ViewPrivate))
+                    .route(web::get().to_async(broken_links)),
+            )
+            .service(
+                web::resource("audit:page/{page}")
+                    .wrap(Require::new(Permission::ManageUsers))
+                    .route(web::get().to_async(audit_log)),
+            )
+            .service(
+                web::resource("u/{name}/bookmarks:page/{page}")
+                    .route(web::get().to_async(user_bookmarks)),
+            ),
+    )
+    .service(
+        web::resource("/").route(web::get().to_async(recent_bookmarks_html)),
+    )
+    .service(
+        web::resource("/best")
+            .route(web::get().to_async(starred_bookmarks_html)),
+    )
+    .service(
+        web::resource("/best/feed.atom")
+            .route(web::get().to_async(starred_bookmarks_feed)),
+    )
+    .service(
+        web::resource("/queue")
+            .wrap(Require::new(Permission::ViewPrivate))
+            .route(web::get().to_async(queued_bookmarks_html)),
+    )
+    .service(web::resource("/tags").route(web::get().to_async(tags_html)))
+    .service(
+        web::resource("/tags/{tag:.+}")
+            .route(web::get().to_async(tagged_bookmarks_html)),
+    )
+    .service(
+        web::resource("/search")
+            .route(web::get().to_async(search_bookmark_html)),
+    )
+    .service(
+        web::resource("/s/{slug}")
+            .route(web::get().to_async(saved_search_html)),
+    )
+    .service(
+        web::resource("/s/{slug}/feed.atom")
+            .route(web::get().to_async(saved_search_feed)),
+    )
+    .service(
+        web::resource("/bookmarks/{id}")
+            .route(web::get().to_async(bookmark_html)),
+    )
+    .service(
+        web::resource("/bookmarks/{id}/archive")
+            .route(web::get().to_async(bookmark_archive_html)),
+    )
+    .service(
+        web::resource("/c/{slug}").route(web::get().to_async(collection_html)),
+    )
+    .service(
+        web::resource("/u/{name}")
+            .route(web::get().to_async(user_bookmarks_html)),
+    )
+    .service(
+        web::resource("/u/{name}/feed.atom")
+            .route(web::get().to_async(user_bookmarks_feed)),
+    )
+    .service(
+        web::resource("/u/{name}/search")
+            .route(web::get().to_async(user_search_html)),
+    )
+    .service(
+        web::resource("/broken-links")
+            .wrap(Require::new(Permission::ViewPrivate))
+            .route(web::get().to_async(broken_links_html)),
+    );
+}
+
+fn main() {
+    dotenv().ok();
+    env_logger::init();
+
+    let opt = Opt::from_args();
+    if let Some(path) = opt.config {
+        std::env::set_var("CONFIG_FILE", path);
+    }
+    let config = match config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Invalid configuration:\n{}", err);
+            std::process::exit(1);
+        }
+    };
+    if opt.check_config {
+        print!("{}", config.redacted());
+        return;
+    }
+
+    let sys = actix_rt::System::new("bookmarks");
+    let pool = create_pool();
+
+    db_migrations(&pool);
+
+    // Start parallel db executors
+    let addr: Addr<DbExecutor> =
+        SyncArbiter::start(CONFIG.executors, move || DbExecutor(pool.clone()));
+    Arbiter::spawn(index_pending(addr.clone()));
+    if let Some(interval) = CONFIG.check_links_interval {
+        let db = addr.clone();
+        LinkCheckScheduler::create(move |_| {
+            LinkCheckScheduler::new(db, interval)
+        });
+    }
+    let db = addr.clone();
+    RevocationSync::create(move |_| RevocationSync::new(db));
+    let db = addr.clone();
+    ApiTokenSync::create(move |_| ApiTokenSync::new(db));
+    let host_port = CONFIG.host_port;
+    // Start http server
+    HttpServer::new(move || {
+        App::new()
+            .wrap(
+                cors::Cors::new()
+                    .allowed_origin(&CONFIG.allowed_origin)
+                    .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
+                    .allowed_headers(vec![
+                        header::AUTHORIZATION,
+                        header::ACCEPT,
+                    ])
+                    .allowed_header(header::CONTENT_TYPE)
+                    .allowed_header(CSRF_HEADER)
+                    .supports_credentials()
+                    .max_age(CONFIG.cors_max_age),
+            )
+            .data(addr.clone())
+            .data(SearchClient::new())
+            .configure(routes)
+    })
+    .bind(("0.0.0.0", host_port))
+    .unwrap()
+    .start();
+
+    info!("Started http server: 0.0.0.0:{}", host_port);
+    let _ = sys.run();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::Method;
+    use actix_web::test::{self, TestRequest};
+    use common::models::Role;
+
+    // Guarded routes along with the least role allowed to use them, routes
+    // open to everyone are listed for contrast
+    const ROUTES: &[(&str, &str, Option<Role>)] = &[
+        ("GET", "/healthz", None),
+        ("GET", "/api/sessions", Some(Role::Admin)),
+        ("DELETE", "/api/sessions/1", Some(Role::Admin)),
+        ("GET", "/api/users", Some(Role::Admin)),
+        ("POST", "/api/users", Some(Role::Admin)),
+        ("PUT", "/api/users/1", Some(Role::Admin)),
+        ("GET", "/api/audit:page/1", Some(Role::Admin)),
+        ("POST", "/api/tags/merge", Some(Role::Admin)),
+        ("POST", "/api/reindex", Some(Role::Admin)),
+        ("PUT", "/api/tags/rust", Some(Role::Admin)),
+        ("DELETE", "/api/tags/rust", Some(Role::Admin)),
+        ("POST", "/api/bookmarks", Some(Role::Editor)),
+        ("PUT", "/api/bookmarks/1/status", Some(Role::Editor)),
+        ("POST", "/api/bookmarks/1/star", Some(Role::Editor)),
+        ("POST", "/api/bookmarks/1/highlights", Some(Role::Editor)),
+        ("DELETE", "/api/highlights/1", Some(Role::Editor)),
+        ("POST", "/api/saved_searches", Some(Role::Editor)),
+        ("POST", "/api/collections", Some(Role::Editor)),
+        ("PUT", "/api/collections/1", Some(Role::Editor)),
+        ("DELETE", "/api/collections/1", Some(Role::Editor)),
+        ("POST", "/api/collections/1/items", Some(Role::Editor)),
+        ("DELETE", "/api/collections/1/items/2", Some(Role::Editor)),
+        ("PUT", "/api/collections/1/order", Some(Role::Editor)),
+        ("GET", "/api/tokens", Some(Role::Viewer)),
+        ("POST", "/api/tokens", Some(Role::Viewer)),
+        ("DELETE", "/api/tokens/1", Some(Role::Viewer)),
+        ("GET", "/api/saved_searches", Some(Role::Viewer)),
+        ("GET", "/api/collections", Some(Role::Viewer)),
+        ("GET", "/api/broken_links", Some(Role::Viewer)),
+        ("GET", "/api/queue:page/1", Some(Role::Viewer)),
+        ("GET", "/queue", Some(Role::Viewer)),
+        ("GET", "/broken-links", Some(Role::Viewer)),
+        ("GET", "/api/bookmarks:page/1", None),
+        ("GET", "/api/best:page/1", None),
+        ("GET", "/api/tags", None),
+        ("GET", "/api/u/ann/bookmarks:page/1", None),
+        ("GET", "/", None),
+    ];
+
+    fn access_token(role: Role) -> String {
+        // the configuration is read on first use, the routes don't need
+        // these to be valid
+        for var in &["DATABASE_URL", "TOSHI_URL", "TOSHI_INDEX"] {
+            if std::env::var_os(var).is_none() {
+                std::env::set_var(var, "unused");
+            }
+        }
+        let user = SlimUser {
+            id: 1,
+            email: "ann@example.com".into(),
+            name: "ann".into(),
+            roles: vec![role],
+            session_id: Some(1),
+        };
+        create_token(&user).unwrap()
+    }
+
+    #[test]
+    fn test_route_permissions() {
+        // there is no database, requests let through fail further on
+        let mut app = test::init_service(App::new().configure(routes));
+        let roles = [
+            None,
+            Some(Role::Viewer),
+            Some(Role::Editor),
+            Some(Role::Admin),
+        ];
+
+        for &(method, path, least) in ROUTES {
+            for &role in &roles {
+                let mut req = TestRequest::with_uri(path)
+                    .method(Method::from_bytes(method.as_bytes()).unwrap());
+                if let Some(role) = role {
+                    let token = access_token(role);
+                    req = req.header(
+                        header::AUTHORIZATION,
+                        format!("Bearer {}", token),
+                    );
+                }
+                let status =
+                    test::call_service(&mut app, req.to_request()).status();
+
+                let rejected = match (least, role) {
+                    (Some(_), None) => Some(http::StatusCode::UNAUTHORIZED),
+                    (Some(least), Some(role)) if role < least => {
+                        Some(http::StatusCode::FORBIDDEN)
+                    }
+                    _ => None,
+                };
+                let denied = [
+                    http::StatusCode::UNAUTHORIZED,
+                    http::StatusCode::FORBIDDEN,
+                    http::StatusCode::NOT_FOUND,
+                    http::StatusCode::METHOD_NOT_ALLOWED,
+                ];
+                match rejected {
+                    Some(rejected) => assert_eq!(
+                        status, rejected,
+                        "{} {} as {:?}",
+                        method, path, role
+                    ),
+                    None => assert!(
+                        !denied.contains(&status),
+                        "{} {} as {:?}: {}",
+                        method,
+                        path,
+                        role,
+                        status
+                    ),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_cookie_session_pages() {
+        let mut app = test::init_service(App::new().configure(routes));
+        let (cookies, _) =
+            session_cookies(access_token(Role::Viewer), String::new());
+
+        let mut req = TestRequest::with_uri("/broken-links");
+        for cookie in cookies {
+            req = req.cookie(cookie);
+        }
+        let status = test::call_service(&mut app, req.to_request()).status();
+        assert_ne!(status, http::StatusCode::UNAUTHORIZED);
+
+        let req = TestRequest::with_uri("/broken-links").to_request();
+        let status = test::call_service(&mut app, req).status();
+        assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+    }
 }
@@ -0,0 +1,78 @@
+use std::error::Error;
+
+use actix::prelude::*;
+use diesel::{r2d2::ConnectionManager, PgConnection};
+use dotenv::dotenv;
+use futures::future::lazy;
+use structopt::StructOpt;
+
+use common::config;
+use common::db::{DbExecutor, DeleteTag, MergeTags, RenameTag};
+use common::indexing::index_bookmarks;
+use common::search::SearchClient;
+
+// Changes tags on the bookmarks of every user
+#[derive(StructOpt, Debug)]
+#[structopt(name = "tags")]
+enum Opt {
+    /// Renames a tag on every bookmark
+    #[structopt(name = "rename")]
+    Rename { from: String, to: String },
+
+    /// Replaces several tags with a single one
+    #[structopt(name = "merge")]
+    Merge {
+        #[structopt(short = "i", long = "into")]
+        into: String,
+        #[structopt(name = "TAG", raw(required = "true"))]
+        tags: Vec<String>,
+    },
+
+    /// Removes a tag from every bookmark
+    #[structopt(name = "delete")]
+    Delete { tag: String },
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    dotenv().ok();
+    env_logger::init();
+
+    let opt = Opt::from_args();
+
+    let config = config::load()
+        .map_err(|err| format!("Invalid configuration:\n{}", err))?;
+    let manager =
+        ConnectionManager::<PgConnection>::new(config.database_url.clone());
+    let pool = r2d2::Pool::builder()
+        .max_size(config.pool_size)
+        .build(manager)?;
+
+    let mut sys = System::new("tags");
+    let addr = SyncArbiter::start(1, move || DbExecutor(pool.clone()));
+
+    let changed = match opt {
+        Opt::Rename { from, to } => sys.block_on(addr.send(RenameTag {
+            from,
+            to,
+            owner: None,
+        })),
+        Opt::Merge { into, tags } => sys.block_on(addr.send(MergeTags {
+            from: tags,
+            into,
+            owner: None,
+        })),
+        Opt::Delete { tag } => {
+            sys.block_on(addr.send(DeleteTag { tag, owner: None }))
+        }
+    }
+    .map_err(|err| format!("Database executor error: {}", err))?
+    .map_err(|err| format!("Failed to update tags: {}", err))?;
+    println!("Updated {} bookmark(s), reindexing...", changed.len());
+
+    sys.block_on(lazy(move || {
+        index_bookmarks(changed, addr, &SearchClient::new())
+    }))
+    .map_err(|err| format!("Failed to reindex: {}", err))?;
+
+    Ok(())
+}
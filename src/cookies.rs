@@ -0,0 +1,137 @@
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::dev::RequestHead;
+use actix_web::http::{header, Method};
+use chrono::Duration;
+use ring::constant_time::verify_slices_are_equal;
+
+use crate::config::CONFIG;
+use crate::sessions::{random_token, ACCESS_TOKEN_MINUTES, REFRESH_TOKEN_DAYS};
+
+// Cookie sessions keep the tokens out of reach of scripts, in place of the
+// Authorization header.
+pub const ACCESS_COOKIE: &str = "access_token";
+pub const REFRESH_COOKIE: &str = "refresh_token";
+// Readable by the admin ui, which sends it back in `CSRF_HEADER`. Other
+// sites can neither read it nor set the header.
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+// Sent to the pages as well, which show private items to those allowed
+// to view them
+const ACCESS_PATH: &str = "/";
+const REFRESH_PATH: &str = "/api/auth/refresh";
+
+fn build(
+    name: &'static str,
+    value: String,
+    path: &'static str,
+) -> Cookie<'static> {
+    Cookie::build(name, value)
+        .path(path)
+        .http_only(name != CSRF_COOKIE)
+        .same_site(SameSite::Strict)
+        .secure(CONFIG.secure_cookies)
+        .finish()
+}
+
+// Cookies of a session, along with the csrf token set in them
+pub fn session_cookies(
+    token: String,
+    refresh_token: String,
+) -> (Vec<Cookie<'static>>, String) {
+    let csrf_token = random_token();
+    let mut access = build(ACCESS_COOKIE, token, ACCESS_PATH);
+    access.set_max_age(Duration::minutes(ACCESS_TOKEN_MINUTES));
+    let mut refresh = build(REFRESH_COOKIE, refresh_token, REFRESH_PATH);
+    refresh.set_max_age(Duration::days(REFRESH_TOKEN_DAYS));
+    let mut csrf = build(CSRF_COOKIE, csrf_token.clone(), "/");
+    csrf.set_max_age(Duration::days(REFRESH_TOKEN_DAYS));
+    (vec![access, refresh, csrf], csrf_token)
+}
+
+// Cookies removing those of a session, on logout
+pub fn cleared_session_cookies() -> Vec<Cookie<'static>> {
+    vec![
+        build(ACCESS_COOKIE, String::new(), ACCESS_PATH),
+        build(REFRESH_COOKIE, String::new(), REFRESH_PATH),
+        build(CSRF_COOKIE, String::new(), "/"),
+    ]
+}
+
+pub fn cookie_value(req: &RequestHead, name: &str) -> Option<String> {
+    req.headers()
+        .get_all(header::COOKIE)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| Cookie::parse(pair.trim()).ok())
+        .find(|cookie| cookie.name() == name)
+        .map(|cookie| cookie.value().to_owned())
+}
+
+// Requests authenticated by cookie which may change anything must carry
+// the csrf token in a header as well
+pub fn check_csrf(req: &RequestHead) -> bool {
+    if is_safe(&req.method) {
+        return true;
+    }
+    let header = req
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok());
+    match (cookie_value(req, CSRF_COOKIE), header) {
+        (Some(ref cookie), Some(header)) if !cookie.is_empty() => {
+            verify_slices_are_equal(cookie.as_bytes(), header.as_bytes())
+                .is_ok()
+        }
+        _ => false,
+    }
+}
+
+fn is_safe(method: &Method) -> bool {
+    *method == Method::GET
+        || *method == Method::HEAD
+        || *method == Method::OPTIONS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn head(req: TestRequest) -> RequestHead {
+        req.to_http_request().head().clone()
+    }
+
+    #[test]
+    fn test_cookie_value() {
+        let req = head(
+            TestRequest::default()
+                .header(header::COOKIE, "a=1; access_token=abc.def"),
+        );
+
+        assert_eq!(cookie_value(&req, ACCESS_COOKIE), Some("abc.def".into()));
+        assert_eq!(cookie_value(&req, "a"), Some("1".into()));
+        assert_eq!(cookie_value(&req, CSRF_COOKIE), None);
+    }
+
+    #[test]
+    fn test_check_csrf() {
+        let cookie = "csrf_token=t0ken";
+        let req = || TestRequest::default().header(header::COOKIE, cookie);
+
+        assert!(check_csrf(&head(req())));
+        assert!(!check_csrf(&head(req().method(Method::POST))));
+        assert!(check_csrf(&head(
+            req().method(Method::POST).header(CSRF_HEADER, "t0ken")
+        )));
+        assert!(!check_csrf(&head(
+            req().method(Method::DELETE).header(CSRF_HEADER, "other")
+        )));
+        assert!(!check_csrf(&head(
+            TestRequest::default()
+                .method(Method::PUT)
+                .header(CSRF_HEADER, "")
+                .header(header::COOKIE, "csrf_token=")
+        )));
+    }
+}
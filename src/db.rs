@@ -1,14 +1,47 @@
 use actix::prelude::*;
-use bcrypt::verify;
-use diesel::pg::PgConnection;
+use bcrypt::{hash, verify, DEFAULT_COST};
+use chrono::Utc;
+use diesel::pg::{Pg, PgConnection};
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::sql_types::Bool;
+use lazy_static::lazy_static;
 use log::*;
 use serde_derive::*;
+use serde_json::json;
 
 use crate::config::CONFIG;
 use crate::error::ServiceError;
-use crate::models::{Bookmark, NewBookmark, PageData, SlimUser, User};
+use crate::models::{
+    Audience, Bookmark, BookmarkDoc, HighlightedBookmark, NewBookmark,
+    NewSavedSearch, PageData, ReadingStatus, SavedSearch, SlimUser, User,
+    Visibility,
+};
+use crate::sessions::random_token;
+
+mod api_tokens;
+mod audit;
+mod collections;
+mod health;
+mod highlights;
+mod links;
+mod sessions;
+mod snapshots;
+mod tags;
+mod users;
+
+pub use self::api_tokens::*;
+use self::audit::record;
+pub use self::audit::{QueryAuditLog, RecordFailedLogin};
+pub use self::collections::*;
+pub use self::health::CheckDatabase;
+pub use self::highlights::*;
+use self::highlights::{page_with_highlights, with_highlights};
+pub use self::links::*;
+pub use self::sessions::*;
+pub use self::snapshots::*;
+pub use self::tags::*;
+pub use self::users::*;
 
 pub struct DbExecutor(pub Pool<ConnectionManager<PgConnection>>);
 
@@ -16,8 +49,74 @@ impl Actor for DbExecutor {
     type Context = SyncContext<Self>;
 }
 
+type BookmarkFilter = Box<
+    dyn BoxableExpression<crate::schema::bookmarks::table, Pg, SqlType = Bool>,
+>;
+
+// Public bookmarks, along with the private ones when the audience may see
+// them
+pub(crate) fn visible_to(audience: Audience) -> BookmarkFilter {
+    use crate::schema::bookmarks::dsl::*;
+
+    let public = visibility.eq(Visibility::Public);
+    Box::new(public.or(audience.sees_private().into_sql::<Bool>()))
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct QueryRecent(pub i64, pub Audience);
+
+// Unread bookmarks of a user, oldest first
+#[derive(Debug, Copy, Clone)]
+pub struct QueryQueue {
+    pub page: i64,
+    pub owner: i32,
+    pub audience: Audience,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct QueryStarred(pub i64, pub Audience);
+
+// The newest of the given bookmarks, for ordering search matches by date
+#[derive(Debug, Clone)]
+pub struct QueryNewest {
+    pub ids: Vec<i32>,
+    pub limit: i64,
+    pub audience: Audience,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ToggleStar {
+    pub id: i32,
+    pub owner: i32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct QueryBookmark {
+    pub id: i32,
+    pub audience: Audience,
+}
+
+// Bookmarks which are not (yet) in the current toshi index
+#[derive(Debug, Copy, Clone)]
+pub struct QueryUnindexed;
+
+// Marks every bookmark as not indexed, for `index_pending` to index them
+// all again
+#[derive(Debug, Copy, Clone)]
+pub struct ClearIndexed {
+    pub actor: i32,
+}
+
+// Everything that goes into the search index for a bookmark
+#[derive(Debug, Copy, Clone)]
+pub struct QueryBookmarkDoc(pub i32);
+
 #[derive(Debug, Copy, Clone)]
-pub struct QueryRecent(pub i64);
+pub struct UpdateStatus {
+    pub id: i32,
+    pub status: ReadingStatus,
+    pub owner: i32,
+}
 
 #[derive(Debug, Copy, Clone)]
 pub struct BookmarkIndexed {
@@ -33,18 +132,33 @@ impl Message for BookmarkIndexed {
     type Result = Result<Bookmark, diesel::result::Error>;
 }
 
+#[derive(Debug, Clone)]
+pub struct CreateSavedSearch {
+    pub search: NewSavedSearch,
+    pub actor: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct QuerySavedSearch(pub String);
+
+#[derive(Debug, Copy, Clone)]
+pub struct QuerySavedSearches;
+
 #[derive(Debug, Deserialize)]
 pub struct AuthData {
     pub email: String,
     pub password: String,
+    // sets the tokens in cookies instead of returning them
+    #[serde(default)]
+    pub cookie: bool,
 }
 
 impl Message for QueryRecent {
-    type Result = Result<PageData<Bookmark>, diesel::result::Error>;
+    type Result = Result<PageData<HighlightedBookmark>, diesel::result::Error>;
 }
 
 impl Handler<QueryRecent> for DbExecutor {
-    type Result = Result<PageData<Bookmark>, diesel::result::Error>;
+    type Result = Result<PageData<HighlightedBookmark>, diesel::result::Error>;
 
     fn handle(
         &mut self,
@@ -57,14 +171,320 @@ impl Handler<QueryRecent> for DbExecutor {
         let conn: &PgConnection = &self.0.get().unwrap();
 
         bookmarks
+            .filter(visible_to(msg.1))
+            .order_by(created.desc())
+            .paginate(msg.0)
+            .per_page(CONFIG.per_page)
+            .load_and_count_pages::<Bookmark>(conn)
+            .and_then(|page| page_with_highlights(page, conn))
+    }
+}
+
+impl Message for QueryQueue {
+    type Result = Result<PageData<HighlightedBookmark>, diesel::result::Error>;
+}
+
+impl Handler<QueryQueue> for DbExecutor {
+    type Result = Result<PageData<HighlightedBookmark>, diesel::result::Error>;
+
+    fn handle(
+        &mut self,
+        msg: QueryQueue,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::pagination::*;
+        use crate::schema::bookmarks::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        bookmarks
+            .filter(user_id.eq(msg.owner))
+            .filter(status.eq(ReadingStatus::Unread))
+            .filter(visible_to(msg.audience))
+            .order_by(created.asc())
+            .paginate(msg.page)
+            .per_page(CONFIG.per_page)
+            .load_and_count_pages::<Bookmark>(conn)
+            .and_then(|page| page_with_highlights(page, conn))
+    }
+}
+
+impl Message for QueryStarred {
+    type Result = Result<PageData<HighlightedBookmark>, diesel::result::Error>;
+}
+
+impl Handler<QueryStarred> for DbExecutor {
+    type Result = Result<PageData<HighlightedBookmark>, diesel::result::Error>;
+
+    fn handle(
+        &mut self,
+        msg: QueryStarred,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::pagination::*;
+        use crate::schema::bookmarks::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        bookmarks
+            .filter(starred.eq(true))
+            .filter(visible_to(msg.1))
             .order_by(created.desc())
             .paginate(msg.0)
-            .per_page(20)
+            .per_page(CONFIG.per_page)
             .load_and_count_pages::<Bookmark>(conn)
+            .and_then(|page| page_with_highlights(page, conn))
+    }
+}
+
+impl Message for QueryNewest {
+    type Result = Result<Vec<Bookmark>, diesel::result::Error>;
+}
+
+impl Handler<QueryNewest> for DbExecutor {
+    type Result = Result<Vec<Bookmark>, diesel::result::Error>;
+
+    fn handle(
+        &mut self,
+        msg: QueryNewest,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::bookmarks::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        bookmarks
+            .filter(id.eq_any(msg.ids))
+            .filter(visible_to(msg.audience))
+            .order_by(created.desc())
+            .limit(msg.limit)
+            .load::<Bookmark>(conn)
+    }
+}
+
+impl Message for ToggleStar {
+    type Result = Result<Bookmark, ServiceError>;
+}
+
+impl Handler<ToggleStar> for DbExecutor {
+    type Result = Result<Bookmark, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: ToggleStar,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::bookmarks::dsl::*;
+        use diesel::dsl::not;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        // starred is part of the indexed doc, which is now stale
+        conn.transaction(|| {
+            let bookmark = diesel::update(
+                bookmarks.find(msg.id).filter(user_id.eq(msg.owner)),
+            )
+            .set((starred.eq(not(starred)), toshi_index.eq(None::<String>)))
+            .get_result::<Bookmark>(conn)?;
+            record(
+                Some(msg.owner),
+                "bookmark.star",
+                Some(bookmark.id),
+                &json!({ "starred": !bookmark.starred }),
+                &json!({ "starred": bookmark.starred }),
+                conn,
+            )?;
+            Ok(bookmark)
+        })
+    }
+}
+
+impl Message for QueryBookmark {
+    type Result = Result<HighlightedBookmark, ServiceError>;
+}
+
+impl Handler<QueryBookmark> for DbExecutor {
+    type Result = Result<HighlightedBookmark, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: QueryBookmark,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::bookmarks::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let bookmark = bookmarks
+            .find(msg.id)
+            .filter(visible_to(msg.audience))
+            .first::<Bookmark>(conn)?;
+
+        with_highlights(vec![bookmark], conn)?
+            .pop()
+            .ok_or(ServiceError::InternalServerError)
+    }
+}
+
+impl Message for ClearIndexed {
+    type Result = Result<usize, ServiceError>;
+}
+
+impl Handler<ClearIndexed> for DbExecutor {
+    type Result = Result<usize, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: ClearIndexed,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::bookmarks::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        conn.transaction(|| {
+            let cleared = diesel::update(bookmarks)
+                .set(toshi_index.eq(None::<String>))
+                .execute(conn)?;
+            record(
+                Some(msg.actor),
+                "index.rebuild",
+                None,
+                &json!(null),
+                &json!({ "bookmarks": cleared }),
+                conn,
+            )?;
+            Ok(cleared)
+        })
+    }
+}
+
+impl Message for QueryUnindexed {
+    type Result = Result<Vec<Bookmark>, ServiceError>;
+}
+
+impl Handler<QueryUnindexed> for DbExecutor {
+    type Result = Result<Vec<Bookmark>, ServiceError>;
+
+    fn handle(
+        &mut self,
+        _: QueryUnindexed,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::bookmarks::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        bookmarks
+            .filter(
+                toshi_index
+                    .is_null()
+                    .or(toshi_index.ne(&CONFIG.toshi_index)),
+            )
+            .order_by(created.asc())
+            .load::<Bookmark>(conn)
             .map_err(Into::into)
     }
 }
 
+impl Message for QueryBookmarkDoc {
+    type Result = Result<BookmarkDoc, ServiceError>;
+}
+
+impl Handler<QueryBookmarkDoc> for DbExecutor {
+    type Result = Result<BookmarkDoc, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: QueryBookmarkDoc,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::{bookmarks, snapshots};
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let bookmark = bookmarks::table.find(msg.0).first::<Bookmark>(conn)?;
+        let item = with_highlights(vec![bookmark], conn)?
+            .pop()
+            .ok_or(ServiceError::InternalServerError)?;
+        let archive = snapshots::table
+            .filter(snapshots::bookmark_id.eq(msg.0))
+            .order_by(snapshots::fetched.desc())
+            .select(snapshots::content)
+            .first::<String>(conn)
+            .optional()?;
+
+        Ok(BookmarkDoc {
+            archive: archive.unwrap_or_default(),
+            ..item.into()
+        })
+    }
+}
+
+impl Message for UpdateStatus {
+    type Result = Result<Bookmark, ServiceError>;
+}
+
+impl Handler<UpdateStatus> for DbExecutor {
+    type Result = Result<Bookmark, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: UpdateStatus,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::bookmarks::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        conn.transaction(|| {
+            let current = bookmarks
+                .find(msg.id)
+                .filter(user_id.eq(msg.owner))
+                .for_update()
+                .first::<Bookmark>(conn)?;
+
+            if !current.status.can_transition_to(msg.status) {
+                return Err(ServiceError::BadRequest(format!(
+                    "Cannot change status from {} to {}",
+                    current.status, msg.status
+                )));
+            }
+            let read_time = match msg.status {
+                ReadingStatus::Read => Some(Utc::now().naive_utc()),
+                ReadingStatus::Archived => current.read_at,
+                ReadingStatus::Unread | ReadingStatus::Reading => None,
+            };
+
+            // status is part of the indexed doc, which is now stale
+            let updated = diesel::update(bookmarks.find(msg.id))
+                .set((
+                    status.eq(msg.status),
+                    read_at.eq(read_time),
+                    toshi_index.eq(None::<String>),
+                ))
+                .get_result::<Bookmark>(conn)?;
+            let before = json!({
+                "status": current.status,
+                "read_at": current.read_at,
+            });
+            let after = json!({
+                "status": updated.status,
+                "read_at": updated.read_at,
+            });
+            record(
+                Some(msg.owner),
+                "bookmark.status",
+                Some(updated.id),
+                &before,
+                &after,
+                conn,
+            )?;
+            Ok(updated)
+        })
+    }
+}
+
 impl Handler<BookmarkIndexed> for DbExecutor {
     type Result = Result<Bookmark, diesel::result::Error>;
 
@@ -88,18 +508,32 @@ impl Message for AuthData {
     type Result = Result<SlimUser, ServiceError>;
 }
 
+lazy_static! {
+    // Checked against for unknown emails, which then take as long to sign
+    // in with as known ones
+    static ref DUMMY_HASH: String =
+        hash(random_token(), DEFAULT_COST).expect("Failed to hash");
+}
+
 impl Handler<AuthData> for DbExecutor {
     type Result = Result<SlimUser, ServiceError>;
     fn handle(&mut self, msg: AuthData, _: &mut Self::Context) -> Self::Result {
         use crate::schema::users::dsl::*;
         let conn: &PgConnection = &self.0.get().unwrap();
 
-        let mut items =
-            users.filter(email.eq(&msg.email)).load::<User>(conn)?;
+        let mut items = users
+            .filter(email.eq(&msg.email))
+            .filter(active.eq(true))
+            .load::<User>(conn)?;
 
-        if let Some(user) = items.pop() {
-            if let Ok(true) = verify(&msg.password, &user.password) {
-                return Ok(user.into());
+        match items.pop() {
+            Some(user) => {
+                if let Ok(true) = verify(&msg.password, &user.password) {
+                    return Ok(slim_user(user, conn)?);
+                }
+            }
+            None => {
+                let _ = verify(&msg.password, DUMMY_HASH.as_str());
             }
         }
         Err(ServiceError::BadRequest(
@@ -122,14 +556,100 @@ impl Handler<NewBookmark> for DbExecutor {
         use crate::schema::bookmarks::dsl::*;
         let conn: &PgConnection = &self.0.get().unwrap();
 
-        let mut items = diesel::insert_into(bookmarks)
-            .values(&msg)
-            .get_results(conn)
-            .map_err(|err| {
-                error!("Create bookmark error: {:?}", err);
-                err
-            })?;
+        conn.transaction(|| {
+            let bookmark = diesel::insert_into(bookmarks)
+                .values(&msg)
+                .get_result::<Bookmark>(conn)
+                .map_err(|err| {
+                    error!("Create bookmark error: {:?}", err);
+                    err
+                })?;
+            record(
+                Some(bookmark.user_id),
+                "bookmark.create",
+                Some(bookmark.id),
+                &json!(null),
+                &json!(bookmark),
+                conn,
+            )?;
+            Ok(bookmark)
+        })
+    }
+}
+
+impl Message for CreateSavedSearch {
+    type Result = Result<SavedSearch, ServiceError>;
+}
+
+impl Handler<CreateSavedSearch> for DbExecutor {
+    type Result = Result<SavedSearch, ServiceError>;
+    fn handle(
+        &mut self,
+        msg: CreateSavedSearch,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::saved_searches::dsl::*;
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        conn.transaction(|| {
+            let saved = diesel::insert_into(saved_searches)
+                .values(&msg.search)
+                .get_result::<SavedSearch>(conn)
+                .map_err(|err| {
+                    error!("Create saved search error: {:?}", err);
+                    err
+                })?;
+            record(
+                Some(msg.actor),
+                "saved_search.create",
+                Some(saved.id),
+                &json!(null),
+                &json!(saved),
+                conn,
+            )?;
+            Ok(saved)
+        })
+    }
+}
+
+impl Message for QuerySavedSearch {
+    type Result = Result<SavedSearch, ServiceError>;
+}
+
+impl Handler<QuerySavedSearch> for DbExecutor {
+    type Result = Result<SavedSearch, ServiceError>;
+    fn handle(
+        &mut self,
+        msg: QuerySavedSearch,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::saved_searches::dsl::*;
+        let conn: &PgConnection = &self.0.get().unwrap();
 
-        items.pop().ok_or_else(|| ServiceError::InternalServerError)
+        saved_searches
+            .filter(slug.eq(&msg.0))
+            .first::<SavedSearch>(conn)
+            .map_err(Into::into)
+    }
+}
+
+impl Message for QuerySavedSearches {
+    type Result = Result<Vec<SavedSearch>, ServiceError>;
+}
+
+impl Handler<QuerySavedSearches> for DbExecutor {
+    type Result = Result<Vec<SavedSearch>, ServiceError>;
+    fn handle(
+        &mut self,
+        _: QuerySavedSearches,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::saved_searches::dsl::*;
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        saved_searches
+            .order_by(name.asc())
+            .load::<SavedSearch>(conn)
+            .map_err(Into::into)
     }
 }
@@ -0,0 +1,197 @@
+use actix::prelude::*;
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use log::*;
+use serde_json::json;
+
+use super::{record, roles_by_user, DbExecutor};
+use crate::error::ServiceError;
+use crate::models::{ApiToken, NewApiToken, SlimUser, User};
+
+// Tokens of a user which are not revoked
+#[derive(Debug, Copy, Clone)]
+pub struct QueryApiTokens(pub i32);
+
+// Every token which can be used, along with its user
+#[derive(Debug, Copy, Clone)]
+pub struct QueryActiveApiTokens;
+
+#[derive(Debug, Copy, Clone)]
+pub struct RevokeApiToken {
+    pub id: i32,
+    pub user_id: i32,
+}
+
+// Last time each token (by id) was used
+#[derive(Debug, Clone)]
+pub struct RecordApiTokenUse(pub Vec<(i32, NaiveDateTime)>);
+
+impl Message for NewApiToken {
+    type Result = Result<ApiToken, ServiceError>;
+}
+
+impl Handler<NewApiToken> for DbExecutor {
+    type Result = Result<ApiToken, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: NewApiToken,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::api_tokens::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        if msg.name.trim().is_empty() {
+            return Err(ServiceError::BadRequest("Name is required".into()));
+        }
+        conn.transaction(|| {
+            let token = diesel::insert_into(api_tokens)
+                .values(&msg)
+                .get_result::<ApiToken>(conn)
+                .map_err(|err| {
+                    error!("Create api token error: {:?}", err);
+                    err
+                })?;
+            record(
+                Some(token.user_id),
+                "api_token.create",
+                Some(token.id),
+                &json!(null),
+                &json!({ "name": token.name, "scope": token.scope }),
+                conn,
+            )?;
+            Ok(token)
+        })
+    }
+}
+
+impl Message for QueryApiTokens {
+    type Result = Result<Vec<ApiToken>, ServiceError>;
+}
+
+impl Handler<QueryApiTokens> for DbExecutor {
+    type Result = Result<Vec<ApiToken>, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: QueryApiTokens,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::api_tokens::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        api_tokens
+            .filter(user_id.eq(msg.0))
+            .filter(revoked.is_null())
+            .order_by(created.desc())
+            .load::<ApiToken>(conn)
+            .map_err(Into::into)
+    }
+}
+
+impl Message for QueryActiveApiTokens {
+    type Result = Result<Vec<(ApiToken, SlimUser)>, ServiceError>;
+}
+
+impl Handler<QueryActiveApiTokens> for DbExecutor {
+    type Result = Result<Vec<(ApiToken, SlimUser)>, ServiceError>;
+
+    fn handle(
+        &mut self,
+        _: QueryActiveApiTokens,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::{api_tokens, users};
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let active = api_tokens::table
+            .inner_join(users::table)
+            .filter(api_tokens::revoked.is_null())
+            .filter(users::active.eq(true))
+            .load::<(ApiToken, User)>(conn)?;
+        let ids: Vec<_> = active.iter().map(|(_, user)| user.id).collect();
+        let roles = roles_by_user(&ids, conn)?;
+        Ok(active
+            .into_iter()
+            .map(|(token, user)| {
+                let user_roles =
+                    roles.get(&user.id).cloned().unwrap_or_default();
+                (token, SlimUser::new(user, user_roles))
+            })
+            .collect())
+    }
+}
+
+impl Message for RevokeApiToken {
+    type Result = Result<ApiToken, ServiceError>;
+}
+
+impl Handler<RevokeApiToken> for DbExecutor {
+    type Result = Result<ApiToken, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: RevokeApiToken,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::api_tokens::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        conn.transaction(|| {
+            let token = diesel::update(
+                api_tokens
+                    .find(msg.id)
+                    .filter(user_id.eq(msg.user_id))
+                    .filter(revoked.is_null()),
+            )
+            .set(revoked.eq(Utc::now().naive_utc()))
+            .get_result::<ApiToken>(conn)?;
+            record(
+                Some(msg.user_id),
+                "api_token.revoke",
+                Some(token.id),
+                &json!({ "revoked": null }),
+                &json!({ "revoked": token.revoked }),
+                conn,
+            )?;
+            Ok(token)
+        })
+    }
+}
+
+impl Message for RecordApiTokenUse {
+    type Result = Result<(), ServiceError>;
+}
+
+impl Handler<RecordApiTokenUse> for DbExecutor {
+    type Result = Result<(), ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: RecordApiTokenUse,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::api_tokens::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        conn.transaction(|| {
+            for (token_id, used) in msg.0 {
+                // other replicas may have recorded a later use already
+                diesel::update(
+                    api_tokens
+                        .find(token_id)
+                        .filter(last_used.is_null().or(last_used.lt(used))),
+                )
+                .set(last_used.eq(used))
+                .execute(conn)?;
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,169 @@
+use actix::prelude::*;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde_json::{json, Map, Value};
+
+use super::DbExecutor;
+use crate::config::CONFIG;
+use crate::error::ServiceError;
+use crate::models::{AuditDiff, AuditEntry, NewAuditEntry, PageData};
+
+// Changes made without signing in, by the command line tools
+const COMMAND_LINE: &str = "command line";
+
+// Most recent first
+#[derive(Debug, Copy, Clone)]
+pub struct QueryAuditLog {
+    pub page: i64,
+}
+
+// A failed sign in, recorded as made by the account tried, along with the
+// address and for how long it got further attempts blocked
+#[derive(Debug, Clone)]
+pub struct RecordFailedLogin {
+    pub email: String,
+    pub ip: String,
+    pub blocked_secs: Option<u64>,
+}
+
+// Fields which differ between two versions of a record, either of which
+// may be null (before creating, after deleting).
+fn json_diff(before: &Value, after: &Value) -> Value {
+    let empty = Map::new();
+    let (before, after) = match (before, after) {
+        (Value::Object(before), Value::Object(after)) => (before, after),
+        (Value::Null, Value::Object(after)) => (&empty, after),
+        (Value::Object(before), Value::Null) => (before, &empty),
+        (Value::Null, Value::Null) => (&empty, &empty),
+        _ => return json!({ "value": { "before": before, "after": after } }),
+    };
+
+    let mut diff = Map::new();
+    for key in before.keys().chain(after.keys()) {
+        let old = before.get(key).unwrap_or(&Value::Null);
+        let new = after.get(key).unwrap_or(&Value::Null);
+        if old != new && !diff.contains_key(key) {
+            diff.insert(key.clone(), json!({ "before": old, "after": new }));
+        }
+    }
+    Value::Object(diff)
+}
+
+// Records a change made by `actor` (a user id), to be called within the
+// transaction making it
+pub(crate) fn record(
+    actor: Option<i32>,
+    action: &str,
+    target_id: Option<i32>,
+    before: &Value,
+    after: &Value,
+    conn: &PgConnection,
+) -> QueryResult<()> {
+    use crate::schema::{audit_log, users};
+
+    let actor = match actor {
+        Some(id) => users::table
+            .find(id)
+            .select(users::email)
+            .first::<String>(conn)?,
+        None => COMMAND_LINE.to_owned(),
+    };
+    diesel::insert_into(audit_log::table)
+        .values(&NewAuditEntry {
+            actor: &actor,
+            action,
+            target_id,
+            diff: AuditDiff(json_diff(before, after)),
+        })
+        .execute(conn)?;
+    Ok(())
+}
+
+impl Message for RecordFailedLogin {
+    type Result = Result<(), ServiceError>;
+}
+
+impl Handler<RecordFailedLogin> for DbExecutor {
+    type Result = Result<(), ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: RecordFailedLogin,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::audit_log;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let action = match msg.blocked_secs {
+            Some(_) => "auth.login_blocked",
+            None => "auth.login_failed",
+        };
+        let after = json!({ "ip": msg.ip, "blocked_secs": msg.blocked_secs });
+        diesel::insert_into(audit_log::table)
+            .values(&NewAuditEntry {
+                actor: &msg.email,
+                action,
+                target_id: None,
+                diff: AuditDiff(json_diff(&Value::Null, &after)),
+            })
+            .execute(conn)?;
+        Ok(())
+    }
+}
+
+impl Message for QueryAuditLog {
+    type Result = Result<PageData<AuditEntry>, ServiceError>;
+}
+
+impl Handler<QueryAuditLog> for DbExecutor {
+    type Result = Result<PageData<AuditEntry>, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: QueryAuditLog,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::pagination::*;
+        use crate::schema::audit_log::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        audit_log
+            .order_by(created.desc())
+            .paginate(msg.page)
+            .per_page(CONFIG.per_page)
+            .load_and_count_pages::<AuditEntry>(conn)
+            .map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_diff() {
+        let before = json!({ "id": 1, "status": "unread", "starred": false });
+        let after = json!({ "id": 1, "status": "read", "read_at": "now" });
+
+        assert_eq!(
+            json_diff(&before, &after),
+            json!({
+                "status": { "before": "unread", "after": "read" },
+                "starred": { "before": false, "after": null },
+                "read_at": { "before": null, "after": "now" },
+            })
+        );
+        assert_eq!(
+            json_diff(&Value::Null, &json!({ "id": 1 })),
+            json!({ "id": { "before": null, "after": 1 } })
+        );
+        assert_eq!(json_diff(&before, &before), json!({}));
+        assert_eq!(json_diff(&Value::Null, &Value::Null), json!({}));
+        assert_eq!(
+            json_diff(&json!(["a"]), &json!(["b"])),
+            json!({ "value": { "before": ["a"], "after": ["b"] } })
+        );
+    }
+}
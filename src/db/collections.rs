@@ -0,0 +1,376 @@
+use actix::prelude::*;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use log::*;
+use serde_json::json;
+
+use super::{record, visible_to, with_highlights, DbExecutor};
+use crate::error::ServiceError;
+use crate::models::{
+    Audience, Bookmark, Collection, CollectionItem, HighlightedBookmark,
+    NewCollection,
+};
+
+#[derive(Debug, Copy, Clone)]
+pub struct QueryCollections;
+
+#[derive(Debug, Clone)]
+pub struct QueryCollection {
+    pub slug: String,
+    pub audience: Audience,
+}
+
+// The collection is owned by the actor, who alone may change it
+#[derive(Debug, Clone)]
+pub struct CreateCollection {
+    pub collection: NewCollection,
+    pub actor: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct UpdateCollection {
+    pub id: i32,
+    pub title: String,
+    pub intro: String,
+    pub actor: i32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct DeleteCollection {
+    pub id: i32,
+    pub actor: i32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct AddCollectionItem {
+    pub collection_id: i32,
+    pub bookmark_id: i32,
+    pub actor: i32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct RemoveCollectionItem {
+    pub collection_id: i32,
+    pub bookmark_id: i32,
+    pub actor: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReorderCollection {
+    pub collection_id: i32,
+    pub bookmark_ids: Vec<i32>,
+    pub actor: i32,
+}
+
+// A collection the user may change, as its owner. The row stays locked
+// until the end of the transaction, so that changes to the items of a
+// collection are made one at a time.
+fn owned_collection(
+    collection_id: i32,
+    owner: i32,
+    conn: &PgConnection,
+) -> QueryResult<Collection> {
+    use crate::schema::collections::dsl::*;
+
+    collections
+        .find(collection_id)
+        .filter(user_id.eq(owner))
+        .for_update()
+        .first::<Collection>(conn)
+}
+
+impl Message for QueryCollections {
+    type Result = Result<Vec<Collection>, ServiceError>;
+}
+
+impl Handler<QueryCollections> for DbExecutor {
+    type Result = Result<Vec<Collection>, ServiceError>;
+
+    fn handle(
+        &mut self,
+        _: QueryCollections,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::collections::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        collections
+            .order_by(title.asc())
+            .load::<Collection>(conn)
+            .map_err(Into::into)
+    }
+}
+
+impl Message for QueryCollection {
+    type Result = Result<(Collection, Vec<HighlightedBookmark>), ServiceError>;
+}
+
+impl Handler<QueryCollection> for DbExecutor {
+    type Result = Result<(Collection, Vec<HighlightedBookmark>), ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: QueryCollection,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::{bookmarks, collection_items, collections};
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let collection = collections::table
+            .filter(collections::slug.eq(&msg.slug))
+            .first::<Collection>(conn)?;
+        let items = collection_items::table
+            .inner_join(bookmarks::table)
+            .filter(collection_items::collection_id.eq(collection.id))
+            .filter(visible_to(msg.audience))
+            .order_by(collection_items::position.asc())
+            .select(bookmarks::all_columns)
+            .load::<Bookmark>(conn)?;
+
+        Ok((collection, with_highlights(items, conn)?))
+    }
+}
+
+impl Message for CreateCollection {
+    type Result = Result<Collection, ServiceError>;
+}
+
+impl Handler<CreateCollection> for DbExecutor {
+    type Result = Result<Collection, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: CreateCollection,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::collections::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        conn.transaction(|| {
+            let collection = diesel::insert_into(collections)
+                .values((&msg.collection, user_id.eq(msg.actor)))
+                .get_result::<Collection>(conn)
+                .map_err(|err| {
+                    error!("Create collection error: {:?}", err);
+                    err
+                })?;
+            record(
+                Some(msg.actor),
+                "collection.create",
+                Some(collection.id),
+                &json!(null),
+                &json!(collection),
+                conn,
+            )?;
+            Ok(collection)
+        })
+    }
+}
+
+impl Message for UpdateCollection {
+    type Result = Result<Collection, ServiceError>;
+}
+
+impl Handler<UpdateCollection> for DbExecutor {
+    type Result = Result<Collection, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: UpdateCollection,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::collections::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        // slug is left alone, so that links to the collection keep working
+        conn.transaction(|| {
+            let current = collections
+                .find(msg.id)
+                .filter(user_id.eq(msg.actor))
+                .for_update()
+                .first::<Collection>(conn)?;
+            let updated = diesel::update(collections.find(msg.id))
+                .set((title.eq(&msg.title), intro.eq(&msg.intro)))
+                .get_result::<Collection>(conn)?;
+            record(
+                Some(msg.actor),
+                "collection.update",
+                Some(updated.id),
+                &json!(current),
+                &json!(updated),
+                conn,
+            )?;
+            Ok(updated)
+        })
+    }
+}
+
+impl Message for DeleteCollection {
+    type Result = Result<(), ServiceError>;
+}
+
+impl Handler<DeleteCollection> for DbExecutor {
+    type Result = Result<(), ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: DeleteCollection,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::collections::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        conn.transaction(|| {
+            let deleted = diesel::delete(
+                collections.find(msg.id).filter(user_id.eq(msg.actor)),
+            )
+            .get_result::<Collection>(conn)?;
+            record(
+                Some(msg.actor),
+                "collection.delete",
+                Some(deleted.id),
+                &json!(deleted),
+                &json!(null),
+                conn,
+            )?;
+            Ok(())
+        })
+    }
+}
+
+impl Message for AddCollectionItem {
+    type Result = Result<CollectionItem, ServiceError>;
+}
+
+impl Handler<AddCollectionItem> for DbExecutor {
+    type Result = Result<CollectionItem, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: AddCollectionItem,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::collection_items::dsl::*;
+        use diesel::dsl::max;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        // new items go to the end of the list
+        conn.transaction(|| {
+            owned_collection(msg.collection_id, msg.actor, conn)?;
+            let last = collection_items
+                .filter(collection_id.eq(msg.collection_id))
+                .select(max(position))
+                .first::<Option<i32>>(conn)?;
+            let item = CollectionItem {
+                collection_id: msg.collection_id,
+                bookmark_id: msg.bookmark_id,
+                position: last.map(|p| p + 1).unwrap_or(0),
+            };
+
+            let item = diesel::insert_into(collection_items)
+                .values(&item)
+                .get_result::<CollectionItem>(conn)?;
+            record(
+                Some(msg.actor),
+                "collection.add_item",
+                Some(item.collection_id),
+                &json!(null),
+                &json!(item),
+                conn,
+            )?;
+            Ok(item)
+        })
+    }
+}
+
+impl Message for RemoveCollectionItem {
+    type Result = Result<(), ServiceError>;
+}
+
+impl Handler<RemoveCollectionItem> for DbExecutor {
+    type Result = Result<(), ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: RemoveCollectionItem,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::collection_items::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let item = collection_items.find((msg.collection_id, msg.bookmark_id));
+        conn.transaction(|| {
+            owned_collection(msg.collection_id, msg.actor, conn)?;
+            let removed =
+                diesel::delete(item).get_result::<CollectionItem>(conn)?;
+            record(
+                Some(msg.actor),
+                "collection.remove_item",
+                Some(removed.collection_id),
+                &json!(removed),
+                &json!(null),
+                conn,
+            )?;
+            Ok(())
+        })
+    }
+}
+
+impl Message for ReorderCollection {
+    type Result = Result<(), ServiceError>;
+}
+
+impl Handler<ReorderCollection> for DbExecutor {
+    type Result = Result<(), ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: ReorderCollection,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::collection_items::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        conn.transaction(|| {
+            owned_collection(msg.collection_id, msg.actor, conn)?;
+            let current = collection_items
+                .filter(collection_id.eq(msg.collection_id))
+                .order_by(position.asc())
+                .select(bookmark_id)
+                .for_update()
+                .load::<i32>(conn)?;
+            let mut existing = current.clone();
+            let mut requested = msg.bookmark_ids.clone();
+            existing.sort();
+            requested.sort();
+            if existing != requested {
+                return Err(ServiceError::BadRequest(
+                    "Order must list every item in the collection once".into(),
+                ));
+            }
+
+            for (i, id) in msg.bookmark_ids.iter().enumerate() {
+                diesel::update(collection_items.find((msg.collection_id, *id)))
+                    .set(position.eq(i as i32))
+                    .execute(conn)?;
+            }
+            record(
+                Some(msg.actor),
+                "collection.reorder",
+                Some(msg.collection_id),
+                &json!({ "bookmark_ids": current }),
+                &json!({ "bookmark_ids": msg.bookmark_ids }),
+                conn,
+            )?;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+use actix::prelude::*;
+use diesel::pg::PgConnection;
+use diesel_migrations::MigrationConnection;
+use log::*;
+
+use super::DbExecutor;
+use crate::error::ServiceError;
+
+// Readiness checks don't wait long on a busy pool
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Checks a pooled connection works, returns the newest migration applied
+#[derive(Debug, Copy, Clone)]
+pub struct CheckDatabase;
+
+impl Message for CheckDatabase {
+    type Result = Result<Option<String>, ServiceError>;
+}
+
+impl Handler<CheckDatabase> for DbExecutor {
+    type Result = Result<Option<String>, ServiceError>;
+
+    fn handle(
+        &mut self,
+        _: CheckDatabase,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let conn = self.0.get_timeout(CONNECTION_TIMEOUT).map_err(|err| {
+            error!("No database connection: {}", err);
+            ServiceError::InternalServerError
+        })?;
+        let conn: &PgConnection = &conn;
+
+        conn.latest_run_migration_version().map_err(Into::into)
+    }
+}
@@ -0,0 +1,174 @@
+use actix::prelude::*;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use log::*;
+use serde_json::json;
+
+use super::{record, DbExecutor};
+use crate::error::ServiceError;
+use crate::models::{
+    Bookmark, Highlight, HighlightedBookmark, NewHighlight, PageData,
+};
+
+#[derive(Debug, Clone)]
+pub struct AddHighlight {
+    pub bookmark_id: i32,
+    pub quote: String,
+    pub comment: Option<String>,
+    pub owner: i32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct DeleteHighlight {
+    pub id: i32,
+    pub owner: i32,
+}
+
+// Loads highlights of all bookmarks with a single query
+pub(crate) fn with_highlights(
+    items: Vec<Bookmark>,
+    conn: &PgConnection,
+) -> QueryResult<Vec<HighlightedBookmark>> {
+    use crate::schema::highlights::dsl::*;
+
+    let grouped = Highlight::belonging_to(&items)
+        .order_by(position.asc())
+        .load::<Highlight>(conn)?
+        .grouped_by(&items);
+
+    Ok(items
+        .into_iter()
+        .zip(grouped)
+        .map(|(bookmark, highlights)| HighlightedBookmark {
+            bookmark,
+            highlights,
+        })
+        .collect())
+}
+
+pub(crate) fn page_with_highlights(
+    page: PageData<Bookmark>,
+    conn: &PgConnection,
+) -> QueryResult<PageData<HighlightedBookmark>> {
+    Ok(PageData {
+        data: with_highlights(page.data, conn)?,
+        total_pages: page.total_pages,
+        next_page: page.next_page,
+    })
+}
+
+// Highlights are part of the indexed doc, changing them makes it stale
+fn highlights_changed(
+    id: i32,
+    conn: &PgConnection,
+) -> Result<HighlightedBookmark, ServiceError> {
+    use crate::schema::bookmarks::dsl::*;
+
+    let bookmark = diesel::update(bookmarks.find(id))
+        .set(toshi_index.eq(None::<String>))
+        .get_result::<Bookmark>(conn)?;
+    let mut items = with_highlights(vec![bookmark], conn)?;
+
+    items.pop().ok_or(ServiceError::InternalServerError)
+}
+
+// Only the owner of a bookmark may change its highlights
+fn check_owner(
+    bookmark: i32,
+    owner: i32,
+    conn: &PgConnection,
+) -> QueryResult<()> {
+    use crate::schema::bookmarks::dsl::*;
+
+    bookmarks
+        .find(bookmark)
+        .filter(user_id.eq(owner))
+        .select(id)
+        .first::<i32>(conn)
+        .map(|_| ())
+}
+
+impl Message for AddHighlight {
+    type Result = Result<HighlightedBookmark, ServiceError>;
+}
+
+impl Handler<AddHighlight> for DbExecutor {
+    type Result = Result<HighlightedBookmark, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: AddHighlight,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::highlights::dsl::*;
+        use diesel::dsl::max;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        // new highlights go after existing ones
+        conn.transaction(|| {
+            check_owner(msg.bookmark_id, msg.owner, conn)?;
+            let last = highlights
+                .filter(bookmark_id.eq(msg.bookmark_id))
+                .select(max(position))
+                .first::<Option<i32>>(conn)?;
+            let highlight = NewHighlight {
+                bookmark_id: msg.bookmark_id,
+                quote: msg.quote,
+                comment: msg.comment,
+                position: last.map(|p| p + 1).unwrap_or(0),
+            };
+
+            let highlight = diesel::insert_into(highlights)
+                .values(&highlight)
+                .get_result::<Highlight>(conn)
+                .map_err(|err| {
+                    error!("Create highlight error: {:?}", err);
+                    err
+                })?;
+            record(
+                Some(msg.owner),
+                "highlight.create",
+                Some(highlight.id),
+                &json!(null),
+                &json!(highlight),
+                conn,
+            )?;
+            highlights_changed(msg.bookmark_id, conn)
+        })
+    }
+}
+
+impl Message for DeleteHighlight {
+    type Result = Result<HighlightedBookmark, ServiceError>;
+}
+
+impl Handler<DeleteHighlight> for DbExecutor {
+    type Result = Result<HighlightedBookmark, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: DeleteHighlight,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::highlights::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        conn.transaction(|| {
+            let highlight = highlights.find(msg.id).first::<Highlight>(conn)?;
+            check_owner(highlight.bookmark_id, msg.owner, conn)?;
+
+            diesel::delete(highlights.find(msg.id)).execute(conn)?;
+            record(
+                Some(msg.owner),
+                "highlight.delete",
+                Some(highlight.id),
+                &json!(highlight),
+                &json!(null),
+                conn,
+            )?;
+            highlights_changed(highlight.bookmark_id, conn)
+        })
+    }
+}
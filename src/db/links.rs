@@ -0,0 +1,169 @@
+use actix::prelude::*;
+use chrono::NaiveDateTime;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use url::Url;
+
+use super::DbExecutor;
+use crate::error::ServiceError;
+use crate::models::{Bookmark, LinkCheck};
+
+// Bookmarks never checked, or last checked before the given time, the
+// longest unchecked first.
+#[derive(Debug, Copy, Clone)]
+pub struct QueryLinksToCheck {
+    pub checked_before: NaiveDateTime,
+    pub limit: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordLinkChecks(pub Vec<LinkCheck>);
+
+// Broken links among the bookmarks of a user
+#[derive(Debug, Copy, Clone)]
+pub struct QueryBrokenLinks(pub i32);
+
+impl Message for QueryLinksToCheck {
+    type Result = Result<Vec<(i32, String)>, ServiceError>;
+}
+
+impl Handler<QueryLinksToCheck> for DbExecutor {
+    type Result = Result<Vec<(i32, String)>, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: QueryLinksToCheck,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::bookmarks::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        bookmarks
+            .filter(
+                last_checked
+                    .is_null()
+                    .or(last_checked.lt(msg.checked_before)),
+            )
+            .order_by((last_checked.is_not_null(), last_checked.asc()))
+            .select((id, url))
+            .limit(msg.limit)
+            .load::<(i32, String)>(conn)
+            .map_err(Into::into)
+    }
+}
+
+impl Message for RecordLinkChecks {
+    type Result = Result<usize, ServiceError>;
+}
+
+impl Handler<RecordLinkChecks> for DbExecutor {
+    type Result = Result<usize, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: RecordLinkChecks,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::bookmarks::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        conn.transaction(|| {
+            let mut updated = 0;
+            for check in &msg.0 {
+                updated += diesel::update(bookmarks.find(check.bookmark_id))
+                    .set((
+                        last_checked.eq(check.checked),
+                        last_status.eq(check.status),
+                        final_url.eq(&check.final_url),
+                    ))
+                    .execute(conn)?;
+            }
+            Ok(updated)
+        })
+    }
+}
+
+impl Message for QueryBrokenLinks {
+    type Result = Result<Vec<Bookmark>, ServiceError>;
+}
+
+impl Handler<QueryBrokenLinks> for DbExecutor {
+    type Result = Result<Vec<Bookmark>, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: QueryBrokenLinks,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::bookmarks::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        // links which moved are told apart by host, see `moved_elsewhere`
+        bookmarks
+            .filter(user_id.eq(msg.0))
+            .filter(last_checked.is_not_null())
+            .filter(
+                last_status
+                    .is_null()
+                    .or(last_status.ge(400))
+                    .or(final_url.ne(url)),
+            )
+            .order_by(last_checked.desc())
+            .load::<Bookmark>(conn)
+            .map(|found| found.into_iter().filter(is_broken).collect())
+            .map_err(Into::into)
+    }
+}
+
+fn is_broken(bookmark: &Bookmark) -> bool {
+    match (bookmark.last_status, &bookmark.final_url) {
+        (Some(status), _) if status >= 400 => true,
+        (Some(_), Some(final_url)) => moved_elsewhere(&bookmark.url, final_url),
+        (Some(_), None) => false,
+        (None, _) => true,
+    }
+}
+
+// A redirect to another host is most likely a parking page, while one
+// to the same host with or without `www.` is not
+fn moved_elsewhere(url: &str, final_url: &str) -> bool {
+    fn host(url: &str) -> Option<String> {
+        let url = Url::parse(url).ok()?;
+        let host = url.host_str()?.to_lowercase();
+        if host.starts_with("www.") {
+            Some(host[4..].to_owned())
+        } else {
+            Some(host)
+        }
+    }
+
+    host(url) != host(final_url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_moved_elsewhere() {
+        let same = [
+            ("http://example.com/a", "https://www.example.com/b"),
+            ("http://Example.com/", "http://example.com:8080/"),
+            ("http://user@example.com/", "http://example.com/"),
+        ];
+        let moved = [
+            ("http://example.com/", "http://parked.example.net/"),
+            ("http://example.com/", "http://example.com.evil/"),
+        ];
+
+        for (url, final_url) in &same {
+            assert!(!moved_elsewhere(url, final_url), "{}", final_url);
+        }
+        for (url, final_url) in &moved {
+            assert!(moved_elsewhere(url, final_url), "{}", final_url);
+        }
+    }
+}
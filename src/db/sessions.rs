@@ -0,0 +1,228 @@
+use actix::prelude::*;
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use log::*;
+use serde_json::json;
+
+use super::{record, slim_user, DbExecutor};
+use crate::error::ServiceError;
+use crate::models::{NewSession, Session, SlimUser, User};
+
+// Swaps the refresh token of a session for a new one, the old token can't
+// be used again.
+#[derive(Debug, Clone)]
+pub struct RefreshSession {
+    pub refresh_hash: String,
+    pub new_refresh_hash: String,
+    pub expires: NaiveDateTime,
+}
+
+// Revokes a session, of any user when `user_id` is None
+#[derive(Debug, Copy, Clone)]
+pub struct RevokeSession {
+    pub id: i32,
+    pub user_id: Option<i32>,
+    pub actor: i32,
+}
+
+// Sessions which are neither revoked nor expired
+#[derive(Debug, Copy, Clone)]
+pub struct QuerySessions;
+
+// Ids of sessions revoked since the given time
+#[derive(Debug, Copy, Clone)]
+pub struct QueryRevokedSessions {
+    pub since: NaiveDateTime,
+}
+
+// Revokes every session of a user, but the one given in `except`
+pub(crate) fn revoke_user_sessions(
+    user: i32,
+    except: Option<i32>,
+    conn: &PgConnection,
+) -> QueryResult<usize> {
+    use crate::schema::sessions::dsl::*;
+
+    let now = Utc::now().naive_utc();
+    let kept: Vec<i32> = except.into_iter().collect();
+    diesel::update(
+        sessions
+            .filter(user_id.eq(user))
+            .filter(id.ne_all(kept))
+            .filter(revoked.is_null()),
+    )
+    .set(revoked.eq(now))
+    .execute(conn)
+}
+
+impl Message for NewSession {
+    type Result = Result<Session, ServiceError>;
+}
+
+impl Handler<NewSession> for DbExecutor {
+    type Result = Result<Session, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: NewSession,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::sessions::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        diesel::insert_into(sessions)
+            .values(&msg)
+            .get_result::<Session>(conn)
+            .map_err(|err| {
+                error!("Create session error: {:?}", err);
+                err.into()
+            })
+    }
+}
+
+impl Message for RefreshSession {
+    type Result = Result<SlimUser, ServiceError>;
+}
+
+impl Handler<RefreshSession> for DbExecutor {
+    type Result = Result<SlimUser, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: RefreshSession,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::{sessions, users};
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let now = Utc::now().naive_utc();
+        conn.transaction(|| {
+            let (session, user) = sessions::table
+                .inner_join(users::table)
+                .filter(sessions::refresh_hash.eq(&msg.refresh_hash))
+                .filter(sessions::revoked.is_null())
+                .filter(sessions::expires.gt(now))
+                .filter(users::active.eq(true))
+                .first::<(Session, User)>(conn)
+                .optional()?
+                .ok_or(ServiceError::Unauthorized)?;
+
+            // matching the old hash again makes concurrent refreshes with
+            // the same token fail, but one
+            let updated = diesel::update(
+                sessions::table
+                    .find(session.id)
+                    .filter(sessions::refresh_hash.eq(&msg.refresh_hash)),
+            )
+            .set((
+                sessions::refresh_hash.eq(&msg.new_refresh_hash),
+                sessions::last_used.eq(now),
+                sessions::expires.eq(msg.expires),
+            ))
+            .execute(conn)?;
+            if updated == 0 {
+                return Err(ServiceError::Unauthorized);
+            }
+
+            // roles changed since signing in are picked up here
+            Ok(SlimUser {
+                session_id: Some(session.id),
+                ..slim_user(user, conn)?
+            })
+        })
+    }
+}
+
+impl Message for RevokeSession {
+    type Result = Result<Session, ServiceError>;
+}
+
+impl Handler<RevokeSession> for DbExecutor {
+    type Result = Result<Session, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: RevokeSession,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::sessions::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let now = Utc::now().naive_utc();
+        let session = sessions.find(msg.id).filter(revoked.is_null());
+        conn.transaction(|| {
+            let session = match msg.user_id {
+                Some(owner) => {
+                    diesel::update(session.filter(user_id.eq(owner)))
+                        .set(revoked.eq(now))
+                        .get_result::<Session>(conn)?
+                }
+                None => diesel::update(session)
+                    .set(revoked.eq(now))
+                    .get_result::<Session>(conn)?,
+            };
+            record(
+                Some(msg.actor),
+                "session.revoke",
+                Some(session.id),
+                &json!({ "revoked": null }),
+                &json!({ "revoked": session.revoked }),
+                conn,
+            )?;
+            Ok(session)
+        })
+    }
+}
+
+impl Message for QuerySessions {
+    type Result = Result<Vec<Session>, ServiceError>;
+}
+
+impl Handler<QuerySessions> for DbExecutor {
+    type Result = Result<Vec<Session>, ServiceError>;
+
+    fn handle(
+        &mut self,
+        _: QuerySessions,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::sessions::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        sessions
+            .filter(revoked.is_null())
+            .filter(expires.gt(Utc::now().naive_utc()))
+            .order_by(last_used.desc())
+            .load::<Session>(conn)
+            .map_err(Into::into)
+    }
+}
+
+impl Message for QueryRevokedSessions {
+    type Result = Result<Vec<i32>, ServiceError>;
+}
+
+impl Handler<QueryRevokedSessions> for DbExecutor {
+    type Result = Result<Vec<i32>, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: QueryRevokedSessions,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::sessions::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        sessions
+            .filter(revoked.gt(msg.since))
+            .select(id)
+            .load::<i32>(conn)
+            .map_err(Into::into)
+    }
+}
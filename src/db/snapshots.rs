@@ -0,0 +1,76 @@
+use actix::prelude::*;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use log::*;
+
+use super::{visible_to, DbExecutor};
+use crate::error::ServiceError;
+use crate::models::{Audience, Bookmark, NewSnapshot, Snapshot};
+
+#[derive(Debug, Copy, Clone)]
+pub struct QuerySnapshot {
+    pub bookmark_id: i32,
+    pub audience: Audience,
+}
+
+impl Message for QuerySnapshot {
+    type Result = Result<(Bookmark, Snapshot), ServiceError>;
+}
+
+impl Handler<QuerySnapshot> for DbExecutor {
+    type Result = Result<(Bookmark, Snapshot), ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: QuerySnapshot,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::{bookmarks, snapshots};
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let bookmark = bookmarks::table
+            .find(msg.bookmark_id)
+            .filter(visible_to(msg.audience))
+            .first::<Bookmark>(conn)?;
+        let snapshot = Snapshot::belonging_to(&bookmark)
+            .order_by(snapshots::fetched.desc())
+            .first::<Snapshot>(conn)?;
+
+        Ok((bookmark, snapshot))
+    }
+}
+
+impl Message for NewSnapshot {
+    type Result = Result<Snapshot, ServiceError>;
+}
+
+impl Handler<NewSnapshot> for DbExecutor {
+    type Result = Result<Snapshot, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: NewSnapshot,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::{bookmarks, snapshots};
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        // archived text is part of the indexed doc, which is now stale
+        conn.transaction(|| {
+            let snapshot = diesel::insert_into(snapshots::table)
+                .values(&msg)
+                .get_result::<Snapshot>(conn)
+                .map_err(|err| {
+                    error!("Create snapshot error: {:?}", err);
+                    err
+                })?;
+            diesel::update(bookmarks::table.find(msg.bookmark_id))
+                .set(bookmarks::toshi_index.eq(None::<String>))
+                .execute(conn)?;
+
+            Ok(snapshot)
+        })
+    }
+}
@@ -0,0 +1,245 @@
+use actix::prelude::*;
+use diesel::dsl::sql;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_types::{Array, Bool, Int4, Nullable, Text};
+use serde_json::json;
+
+use super::page_with_highlights;
+use super::{record, visible_to, DbExecutor};
+use crate::config::CONFIG;
+use crate::error::ServiceError;
+use crate::models::{
+    Audience, Bookmark, HighlightedBookmark, PageData, TagCount,
+};
+
+// Messages below return the bookmarks that were changed, their indexed
+// docs are stale and need to be sent to toshi again. Only bookmarks of
+// `owner` are changed, or those of every user when it is None.
+
+#[derive(Debug, Clone)]
+pub struct RenameTag {
+    pub from: String,
+    pub to: String,
+    pub owner: Option<i32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MergeTags {
+    pub from: Vec<String>,
+    pub into: String,
+    pub owner: Option<i32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeleteTag {
+    pub tag: String,
+    pub owner: Option<i32>,
+}
+
+// Every tag in use, most used first
+#[derive(Debug, Copy, Clone)]
+pub struct QueryTags(pub Audience);
+
+// Bookmarks tagged with a tag or any of its children
+#[derive(Debug, Clone)]
+pub struct QueryTagged {
+    pub tag: String,
+    pub page: i64,
+    pub audience: Audience,
+}
+
+#[derive(QueryableByName)]
+struct UpdatedId {
+    #[sql_type = "Int4"]
+    id: i32,
+}
+
+// Replaces any of the `from` tags with `to` in every bookmark, or drops
+// them when `to` is None. Tags are a set, so a bookmark which already has
+// `to` keeps a single copy of it.
+fn replace_tags(
+    action: &str,
+    from: &[String],
+    to: Option<&str>,
+    owner: Option<i32>,
+    conn: &PgConnection,
+) -> Result<Vec<Bookmark>, ServiceError> {
+    use crate::schema::bookmarks::dsl::*;
+
+    conn.transaction(|| {
+        let updated = diesel::sql_query(
+            "UPDATE bookmarks SET toshi_index = NULL, tags = ( \
+               SELECT COALESCE(jsonb_agg(DISTINCT tag), '[]'::jsonb) \
+               FROM ( \
+                 SELECT CASE WHEN t = ANY($1) THEN $2 ELSE t END AS tag \
+                 FROM jsonb_array_elements_text(tags) AS t \
+               ) replaced \
+               WHERE tag IS NOT NULL \
+             ) \
+             WHERE tags ?| $1 AND ($3 IS NULL OR user_id = $3) \
+             RETURNING id",
+        )
+        .bind::<Array<Text>, _>(from)
+        .bind::<Nullable<Text>, _>(to)
+        .bind::<Nullable<Int4>, _>(owner)
+        .load::<UpdatedId>(conn)?;
+        let ids: Vec<i32> = updated.into_iter().map(|row| row.id).collect();
+
+        record(
+            owner,
+            action,
+            None,
+            &json!({ "tags": from, "bookmarks": null }),
+            &json!({ "tags": to, "bookmarks": ids }),
+            conn,
+        )?;
+
+        bookmarks
+            .filter(id.eq_any(ids))
+            .order_by(id.asc())
+            .load::<Bookmark>(conn)
+            .map_err(Into::into)
+    })
+}
+
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn validate_tag(tag: &str) -> Result<&str, ServiceError> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        Err(ServiceError::BadRequest("Tag must not be empty".into()))
+    } else {
+        Ok(tag)
+    }
+}
+
+impl Message for QueryTags {
+    type Result = Result<Vec<TagCount>, ServiceError>;
+}
+
+impl Handler<QueryTags> for DbExecutor {
+    type Result = Result<Vec<TagCount>, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: QueryTags,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        diesel::sql_query(
+            "SELECT tag, count(*) AS count, max(created) AS last_used \
+             FROM bookmarks, jsonb_array_elements_text(tags) AS tag \
+             WHERE (visibility = 'public' OR $1) \
+             GROUP BY tag \
+             ORDER BY count DESC, tag ASC",
+        )
+        .bind::<Bool, _>(msg.0.sees_private())
+        .load::<TagCount>(conn)
+        .map_err(Into::into)
+    }
+}
+
+impl Message for QueryTagged {
+    type Result = Result<PageData<HighlightedBookmark>, diesel::result::Error>;
+}
+
+impl Handler<QueryTagged> for DbExecutor {
+    type Result = Result<PageData<HighlightedBookmark>, diesel::result::Error>;
+
+    fn handle(
+        &mut self,
+        msg: QueryTagged,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::pagination::*;
+        use crate::schema::bookmarks::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let children = format!("{}/%", escape_like(&msg.tag));
+        let tagged = sql::<Bool>(
+            "EXISTS (SELECT 1 FROM jsonb_array_elements_text(tags) AS tag \
+             WHERE tag = ",
+        )
+        .bind::<Text, _>(msg.tag)
+        .sql(" OR tag LIKE ")
+        .bind::<Text, _>(children)
+        .sql(")");
+
+        bookmarks
+            .filter(tagged)
+            .filter(visible_to(msg.audience))
+            .order_by(created.desc())
+            .paginate(msg.page)
+            .per_page(CONFIG.per_page)
+            .load_and_count_pages::<Bookmark>(conn)
+            .and_then(|page| page_with_highlights(page, conn))
+    }
+}
+
+impl Message for RenameTag {
+    type Result = Result<Vec<Bookmark>, ServiceError>;
+}
+
+impl Handler<RenameTag> for DbExecutor {
+    type Result = Result<Vec<Bookmark>, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: RenameTag,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let to = validate_tag(&msg.to)?;
+        replace_tags("tag.rename", &[msg.from], Some(to), msg.owner, conn)
+    }
+}
+
+impl Message for MergeTags {
+    type Result = Result<Vec<Bookmark>, ServiceError>;
+}
+
+impl Handler<MergeTags> for DbExecutor {
+    type Result = Result<Vec<Bookmark>, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: MergeTags,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let into = validate_tag(&msg.into)?;
+        if msg.from.is_empty() {
+            return Err(ServiceError::BadRequest(
+                "No tags to merge".to_owned(),
+            ));
+        }
+        replace_tags("tag.merge", &msg.from, Some(into), msg.owner, conn)
+    }
+}
+
+impl Message for DeleteTag {
+    type Result = Result<Vec<Bookmark>, ServiceError>;
+}
+
+impl Handler<DeleteTag> for DbExecutor {
+    type Result = Result<Vec<Bookmark>, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: DeleteTag,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        replace_tags("tag.delete", &[msg.tag], None, msg.owner, conn)
+    }
+}
@@ -0,0 +1,488 @@
+use std::collections::HashMap;
+
+use actix::prelude::*;
+use bcrypt::{hash, verify, DEFAULT_COST};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use log::*;
+use serde_json::json;
+
+use super::{
+    page_with_highlights, record, revoke_user_sessions, visible_to, DbExecutor,
+};
+use crate::config::CONFIG;
+use crate::error::ServiceError;
+use crate::models::{
+    Audience, Bookmark, HighlightedBookmark, NewUser, PageData, Role, SlimUser,
+    User, UserChanges, UserData, UserDetails, UserRole,
+};
+use crate::sessions::random_token;
+use crate::utils::check_password;
+
+// A user by their public name, as in profile urls
+#[derive(Debug, Clone)]
+pub struct QueryUser(pub String);
+
+// Bookmarks of a single user, most recent first
+#[derive(Debug, Clone)]
+pub struct QueryUserBookmarks {
+    pub name: String,
+    pub page: i64,
+    pub audience: Audience,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct QueryUsers;
+
+// A user created by an admin (`by`)
+#[derive(Debug, Clone)]
+pub struct CreateUser {
+    pub data: UserData,
+    pub by: i32,
+}
+
+// Changes made by an admin (`by`) to another user
+#[derive(Debug, Clone)]
+pub struct UpdateUser {
+    pub id: i32,
+    pub changes: UserChanges,
+    pub by: i32,
+}
+
+// Other sessions than `session_id` of the user are signed out
+#[derive(Debug, Clone)]
+pub struct ChangePassword {
+    pub user_id: i32,
+    pub session_id: Option<i32>,
+    pub old_password: String,
+    pub new_password: String,
+}
+
+// A user signed in by the identity provider, with a verified email.
+// `is_admin` is only set when admins are given by a group, and then adds
+// or removes the admin role.
+#[derive(Debug, Clone)]
+pub struct OidcLogin {
+    pub email: String,
+    pub name: Option<String>,
+    pub is_admin: Option<bool>,
+    // creates users signing in for the first time
+    pub provision: bool,
+}
+
+// `base`, or `base` followed by the first number making it unique
+fn unique_name(base: &str, conn: &PgConnection) -> QueryResult<String> {
+    use crate::schema::users::dsl::*;
+
+    let mut candidate = base.to_owned();
+    for n in 2.. {
+        let taken = users
+            .filter(name.eq(&candidate))
+            .count()
+            .get_result::<i64>(conn)?;
+        if taken == 0 {
+            break;
+        }
+        candidate = format!("{}{}", base, n);
+    }
+    Ok(candidate)
+}
+
+// Roles of each of `ids`, users without any are left out
+pub(crate) fn roles_by_user(
+    ids: &[i32],
+    conn: &PgConnection,
+) -> QueryResult<HashMap<i32, Vec<Role>>> {
+    use crate::schema::user_roles::dsl::*;
+
+    let mut roles = HashMap::new();
+    for (id, user_role) in user_roles
+        .filter(user_id.eq_any(ids))
+        .load::<(i32, Role)>(conn)?
+    {
+        roles.entry(id).or_insert_with(Vec::new).push(user_role);
+    }
+    for list in roles.values_mut() {
+        list.sort();
+    }
+    Ok(roles)
+}
+
+fn roles_of(id: i32, conn: &PgConnection) -> QueryResult<Vec<Role>> {
+    Ok(roles_by_user(&[id], conn)?.remove(&id).unwrap_or_default())
+}
+
+pub(crate) fn slim_user(
+    user: User,
+    conn: &PgConnection,
+) -> QueryResult<SlimUser> {
+    let roles = roles_of(user.id, conn)?;
+    Ok(SlimUser::new(user, roles))
+}
+
+fn user_details(user: User, conn: &PgConnection) -> QueryResult<UserDetails> {
+    let roles = roles_of(user.id, conn)?;
+    Ok(UserDetails { user, roles })
+}
+
+fn set_roles(id: i32, roles: &[Role], conn: &PgConnection) -> QueryResult<()> {
+    use crate::schema::user_roles::dsl::*;
+
+    diesel::delete(user_roles.filter(user_id.eq(id))).execute(conn)?;
+    let mut roles = roles.to_vec();
+    roles.sort();
+    roles.dedup();
+    if !roles.is_empty() {
+        let rows: Vec<_> = roles
+            .into_iter()
+            .map(|user_role| UserRole {
+                user_id: id,
+                role: user_role,
+            })
+            .collect();
+        diesel::insert_into(user_roles)
+            .values(&rows)
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+impl Message for QueryUser {
+    type Result = Result<User, ServiceError>;
+}
+
+impl Handler<QueryUser> for DbExecutor {
+    type Result = Result<User, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: QueryUser,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::users::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        users
+            .filter(name.eq(&msg.0))
+            .first::<User>(conn)
+            .map_err(Into::into)
+    }
+}
+
+impl Message for QueryUserBookmarks {
+    type Result = Result<PageData<HighlightedBookmark>, ServiceError>;
+}
+
+impl Handler<QueryUserBookmarks> for DbExecutor {
+    type Result = Result<PageData<HighlightedBookmark>, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: QueryUserBookmarks,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::pagination::*;
+        use crate::schema::{bookmarks, users};
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let owner = users::table
+            .filter(users::name.eq(&msg.name))
+            .select(users::id)
+            .first::<i32>(conn)?;
+
+        bookmarks::table
+            .filter(bookmarks::user_id.eq(owner))
+            .filter(visible_to(msg.audience))
+            .order_by(bookmarks::created.desc())
+            .paginate(msg.page)
+            .per_page(CONFIG.per_page)
+            .load_and_count_pages::<Bookmark>(conn)
+            .and_then(|page| page_with_highlights(page, conn))
+            .map_err(Into::into)
+    }
+}
+
+impl Message for OidcLogin {
+    type Result = Result<SlimUser, ServiceError>;
+}
+
+impl Handler<OidcLogin> for DbExecutor {
+    type Result = Result<SlimUser, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: OidcLogin,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::users::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        conn.transaction(|| {
+            let existing = users
+                .filter(email.eq(&msg.email))
+                .for_update()
+                .first::<User>(conn)
+                .optional()?;
+            let user = match existing {
+                Some(ref user) if !user.active => {
+                    return Err(ServiceError::Unauthorized)
+                }
+                Some(user) => {
+                    let before = user_details(user, conn)?;
+                    let was_admin = before.roles.contains(&Role::Admin);
+                    let change = msg.is_admin.filter(|&a| a != was_admin);
+                    if let Some(admin) = change {
+                        let mut roles = before.roles.clone();
+                        if admin {
+                            roles.push(Role::Admin);
+                        } else {
+                            roles.retain(|&r| r != Role::Admin);
+                        }
+                        let id = before.user.id;
+                        set_roles(id, &roles, conn)?;
+                        // other sessions keep the old roles otherwise
+                        revoke_user_sessions(id, None, conn)?;
+                        let after = user_details(before.user.clone(), conn)?;
+                        record(
+                            Some(id),
+                            "user.update",
+                            Some(id),
+                            &json!(before),
+                            &json!(after),
+                            conn,
+                        )?;
+                    }
+                    before.user
+                }
+                None if msg.provision => {
+                    let local_part = msg.email.split('@').next().unwrap_or("");
+                    let base = msg
+                        .name
+                        .as_ref()
+                        .map(String::as_str)
+                        .filter(|s| !s.trim().is_empty())
+                        .unwrap_or(local_part);
+                    let new_name = unique_name(base.trim(), conn)?;
+                    // signing in with a password takes setting one first
+                    let hashed = hash(&random_token(), DEFAULT_COST)?;
+                    let user = diesel::insert_into(users)
+                        .values(&NewUser {
+                            email: &msg.email,
+                            password: &hashed,
+                            name: &new_name,
+                        })
+                        .get_result::<User>(conn)?;
+                    let role = match msg.is_admin {
+                        Some(true) => Role::Admin,
+                        _ => Role::Viewer,
+                    };
+                    set_roles(user.id, &[role], conn)?;
+                    record(
+                        Some(user.id),
+                        "user.create",
+                        Some(user.id),
+                        &json!(null),
+                        &json!(user_details(user.clone(), conn)?),
+                        conn,
+                    )?;
+                    user
+                }
+                None => return Err(ServiceError::Unauthorized),
+            };
+            Ok(slim_user(user, conn)?)
+        })
+    }
+}
+
+impl Message for QueryUsers {
+    type Result = Result<Vec<UserDetails>, ServiceError>;
+}
+
+impl Handler<QueryUsers> for DbExecutor {
+    type Result = Result<Vec<UserDetails>, ServiceError>;
+
+    fn handle(&mut self, _: QueryUsers, _: &mut Self::Context) -> Self::Result {
+        use crate::schema::users::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let all = users.order_by(id.asc()).load::<User>(conn)?;
+        let ids: Vec<_> = all.iter().map(|user| user.id).collect();
+        let mut roles = roles_by_user(&ids, conn)?;
+        Ok(all
+            .into_iter()
+            .map(|user| UserDetails {
+                roles: roles.remove(&user.id).unwrap_or_default(),
+                user,
+            })
+            .collect())
+    }
+}
+
+impl Message for CreateUser {
+    type Result = Result<UserDetails, ServiceError>;
+}
+
+impl Handler<CreateUser> for DbExecutor {
+    type Result = Result<UserDetails, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: CreateUser,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::users::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let data = msg.data;
+        let new_email = data.email.trim();
+        let new_name = data.name.trim();
+        if !new_email.contains('@') {
+            return Err(ServiceError::BadRequest("Invalid email".into()));
+        }
+        if new_name.is_empty() {
+            return Err(ServiceError::BadRequest("Name is required".into()));
+        }
+        check_password(&data.password, &[new_email, new_name])?;
+        let hashed = hash(&data.password, DEFAULT_COST)?;
+
+        conn.transaction(|| {
+            let user = diesel::insert_into(users)
+                .values(&NewUser {
+                    email: new_email,
+                    password: &hashed,
+                    name: new_name,
+                })
+                .get_result::<User>(conn)
+                .map_err(|err| {
+                    error!("Create user error: {:?}", err);
+                    err
+                })?;
+            set_roles(user.id, &data.roles, conn)?;
+            let user = user_details(user, conn)?;
+            record(
+                Some(msg.by),
+                "user.create",
+                Some(user.user.id),
+                &json!(null),
+                &json!(user),
+                conn,
+            )?;
+            Ok(user)
+        })
+    }
+}
+
+impl Message for UpdateUser {
+    type Result = Result<UserDetails, ServiceError>;
+}
+
+impl Handler<UpdateUser> for DbExecutor {
+    type Result = Result<UserDetails, ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: UpdateUser,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::users::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let UserChanges {
+            roles,
+            active: is_active,
+        } = msg.changes;
+        if roles.is_none() && is_active.is_none() {
+            return Err(ServiceError::BadRequest("Nothing to change".into()));
+        }
+        // an admin locking themselves out leaves nobody to undo it
+        let demoted = roles
+            .as_ref()
+            .map_or(false, |roles| !roles.contains(&Role::Admin));
+        if msg.id == msg.by && (demoted || is_active == Some(false)) {
+            return Err(ServiceError::BadRequest(
+                "Admins can't demote or deactivate themselves".into(),
+            ));
+        }
+
+        conn.transaction(|| {
+            let user = users.find(msg.id).for_update().first::<User>(conn)?;
+            let before = user_details(user, conn)?;
+            if let Some(is_active) = is_active {
+                diesel::update(users.find(msg.id))
+                    .set(active.eq(is_active))
+                    .execute(conn)?;
+            }
+            if let Some(ref roles) = roles {
+                set_roles(msg.id, roles, conn)?;
+            }
+            let user = users.find(msg.id).first::<User>(conn)?;
+            let after = user_details(user, conn)?;
+            // access tokens carry the roles, they can't be changed
+            if !after.user.active || after.roles != before.roles {
+                revoke_user_sessions(msg.id, None, conn)?;
+            }
+            record(
+                Some(msg.by),
+                "user.update",
+                Some(msg.id),
+                &json!(before),
+                &json!(after),
+                conn,
+            )?;
+            Ok(after)
+        })
+    }
+}
+
+impl Message for ChangePassword {
+    type Result = Result<(), ServiceError>;
+}
+
+impl Handler<ChangePassword> for DbExecutor {
+    type Result = Result<(), ServiceError>;
+
+    fn handle(
+        &mut self,
+        msg: ChangePassword,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        use crate::schema::users::dsl::*;
+
+        let conn: &PgConnection = &self.0.get().unwrap();
+
+        let user = users
+            .find(msg.user_id)
+            .filter(active.eq(true))
+            .first::<User>(conn)?;
+        // the only bad request, the new password is checked beforehand
+        if !verify(&msg.old_password, &user.password)? {
+            return Err(ServiceError::BadRequest(
+                "Current password is wrong".into(),
+            ));
+        }
+        let hashed = hash(&msg.new_password, DEFAULT_COST)?;
+
+        conn.transaction(|| {
+            diesel::update(users.find(user.id))
+                .set(password.eq(hashed))
+                .execute(conn)?;
+            revoke_user_sessions(user.id, msg.session_id, conn)?;
+            // the password itself is never recorded, changed or not
+            record(
+                Some(user.id),
+                "user.password",
+                Some(user.id),
+                &json!(null),
+                &json!(null),
+                conn,
+            )?;
+            Ok(())
+        })
+    }
+}
@@ -1,6 +1,6 @@
 use std::convert::From;
 
-use actix_web::{error::ResponseError, HttpResponse};
+use actix_web::{error::ResponseError, http::header, HttpResponse};
 use bcrypt::BcryptError;
 use derive_more::Display;
 use diesel::result::{DatabaseErrorKind, Error};
@@ -15,6 +15,17 @@ pub enum ServiceError {
 
     #[display(fmt = "Unauthorized")]
     Unauthorized,
+
+    // signed in, but not allowed to
+    #[display(fmt = "Forbidden")]
+    Forbidden,
+
+    #[display(fmt = "Not Found")]
+    NotFound,
+
+    // seconds until the request may be retried
+    #[display(fmt = "Too Many Requests")]
+    TooManyRequests(u64),
 }
 
 impl ResponseError for ServiceError {
@@ -30,25 +41,38 @@ impl ResponseError for ServiceError {
             ServiceError::Unauthorized => {
                 HttpResponse::Unauthorized().json("Unauthorized")
             }
+            ServiceError::Forbidden => {
+                HttpResponse::Forbidden().json("Forbidden")
+            }
+            ServiceError::NotFound => {
+                HttpResponse::NotFound().json("Not Found")
+            }
+            ServiceError::TooManyRequests(secs) => {
+                HttpResponse::TooManyRequests()
+                    .header(header::RETRY_AFTER, secs.to_string())
+                    .json("Too many attempts, please try later")
+            }
         }
     }
 }
 
 impl From<Error> for ServiceError {
     fn from(error: Error) -> ServiceError {
-        // Right now we just care about UniqueViolation from diesel
-        // But this would be helpful to easily map errors as our app grows
+        // Violated constraints come from what the client sent: an existing
+        // name or a reference to a missing row
         match error {
-            Error::DatabaseError(kind, info) => {
-                if let DatabaseErrorKind::UniqueViolation = kind {
+            Error::DatabaseError(kind, info) => match kind {
+                DatabaseErrorKind::UniqueViolation
+                | DatabaseErrorKind::ForeignKeyViolation => {
                     let message = info
                         .details()
                         .unwrap_or_else(|| info.message())
                         .to_string();
-                    return ServiceError::BadRequest(message);
+                    ServiceError::BadRequest(message)
                 }
-                ServiceError::InternalServerError
-            }
+                _ => ServiceError::InternalServerError,
+            },
+            Error::NotFound => ServiceError::NotFound,
             _ => ServiceError::InternalServerError,
         }
     }
@@ -0,0 +1,292 @@
+use std::io;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::time::Duration;
+
+use actix_web::client::Client;
+use actix_web::http::{header, HeaderMap, Method, StatusCode};
+use actix_web::web::{self, Bytes, BytesMut};
+use actix_web::Error;
+use futures::future::{self, Either, Future, Loop};
+use futures::Stream;
+use url::{Host, Url};
+
+const MAX_REDIRECTS: usize = 5;
+const MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+const TIMEOUT: Duration = Duration::from_secs(20);
+const USER_AGENT: &str = "Mozilla/5.0 (compatible; InsightfulReads)";
+
+#[derive(Debug)]
+pub struct Fetched {
+    pub status: StatusCode,
+    // where the page ended up after following redirects
+    pub final_url: String,
+    // cut off after the limit, the rest isn't read
+    pub body: Bytes,
+}
+
+// GETs a page, following redirects, which the client doesn't do by itself.
+// Urls come from users, who mustn't reach the network of the server through
+// them: only http(s) urls of public addresses are fetched, at every hop.
+pub fn fetch_page(
+    client: &Client,
+    url: &str,
+) -> impl Future<Item = Fetched, Error = Error> {
+    fetch(client, Method::GET, url, TIMEOUT, MAX_BODY_SIZE, true)
+}
+
+// Like fetch_page, but only the status and final url are of interest,
+// the body is not read.
+pub fn fetch_status(
+    client: &Client,
+    method: Method,
+    url: &str,
+    timeout: Duration,
+) -> impl Future<Item = Fetched, Error = Error> {
+    fetch(client, method, url, timeout, 0, false)
+}
+
+fn fetch(
+    client: &Client,
+    method: Method,
+    url: &str,
+    timeout: Duration,
+    max_body_size: usize,
+    public_only: bool,
+) -> impl Future<Item = Fetched, Error = Error> {
+    let client = client.clone();
+
+    future::loop_fn((url.to_owned(), 0), move |(url, redirects)| {
+        let checked = if public_only {
+            Either::A(check_public(&url))
+        } else {
+            Either::B(future::ok(()))
+        };
+        let request = client
+            .request(method.clone(), url.as_str())
+            .header(header::USER_AGENT, USER_AGENT)
+            .timeout(timeout);
+        checked
+            .and_then(move |_| request.send().from_err())
+            .and_then(move |mut resp| {
+                let status = resp.status();
+                let next = redirect_target(&url, status, resp.headers());
+                match next {
+                    Some(next) if redirects < MAX_REDIRECTS => Either::A(
+                        future::ok(Loop::Continue((next, redirects + 1))),
+                    ),
+                    _ if max_body_size == 0 => {
+                        Either::B(Either::A(future::ok(Loop::Break(Fetched {
+                            status,
+                            final_url: url,
+                            body: Bytes::new(),
+                        }))))
+                    }
+                    _ => Either::B(Either::B(
+                        read_body(resp, max_body_size).from_err().map(
+                            move |body| {
+                                Loop::Break(Fetched {
+                                    status,
+                                    final_url: url,
+                                    body,
+                                })
+                            },
+                        ),
+                    )),
+                }
+            })
+    })
+}
+
+// Reads up to `limit` bytes of a body, dropping the connection after
+fn read_body<S>(
+    body: S,
+    limit: usize,
+) -> impl Future<Item = Bytes, Error = S::Error>
+where
+    S: Stream<Item = Bytes>,
+{
+    let mut read = 0;
+    body.take_while(move |chunk| {
+        let more = read < limit;
+        read += chunk.len();
+        Ok(more)
+    })
+    .fold(BytesMut::new(), move |mut body, chunk| {
+        let room = limit - body.len();
+        body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        Ok::<_, S::Error>(body)
+    })
+    .map(BytesMut::freeze)
+}
+
+// Fails unless the url is http(s) and its host only resolves to public
+// addresses
+fn check_public(url: &str) -> impl Future<Item = (), Error = Error> {
+    let target = host_and_port(url);
+    web::block(move || {
+        let not_public = || {
+            io::Error::new(io::ErrorKind::PermissionDenied, "Not a public url")
+        };
+        let (host, port) = target.ok_or_else(not_public)?;
+        let addrs: Vec<_> = (host.as_str(), port).to_socket_addrs()?.collect();
+        if addrs.is_empty() || !addrs.iter().all(|addr| is_public(addr.ip())) {
+            return Err(not_public());
+        }
+        Ok(())
+    })
+    .from_err()
+}
+
+fn host_and_port(url: &str) -> Option<(String, u16)> {
+    let url = Url::parse(url).ok()?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+    let host = match url.host()? {
+        Host::Domain(domain) => domain.to_owned(),
+        Host::Ipv4(ip) => ip.to_string(),
+        Host::Ipv6(ip) => ip.to_string(),
+    };
+    Some((host, url.port_or_known_default()?))
+}
+
+// Not private, loopback or link-local, nor unspecified
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast())
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            // IPv4 addresses mapped to IPv6, ::ffff:a.b.c.d
+            if segments[..5] == [0; 5] && segments[5] == 0xffff {
+                let [a, b] = segments[6].to_be_bytes();
+                let [c, d] = segments[7].to_be_bytes();
+                return is_public(IpAddr::from([a, b, c, d]));
+            }
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                // unique local, fc00::/7
+                || segments[0] & 0xfe00 == 0xfc00
+                // link-local, fe80::/10
+                || segments[0] & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+fn redirect_target(
+    base: &str,
+    status: StatusCode,
+    headers: &HeaderMap,
+) -> Option<String> {
+    if !status.is_redirection() {
+        return None;
+    }
+    let location = headers.get(header::LOCATION)?.to_str().ok()?;
+
+    Url::parse(base)
+        .and_then(|base| base.join(location))
+        .map(Url::into_string)
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::HeaderValue;
+    use futures::stream;
+
+    #[test]
+    fn test_read_body() {
+        let chunks = || {
+            let chunks = vec!["abc", "def", "ghi"];
+            stream::iter_ok::<_, ()>(chunks.into_iter().map(Bytes::from))
+        };
+
+        assert_eq!(read_body(chunks(), 4).wait(), Ok(Bytes::from("abcd")));
+        assert_eq!(read_body(chunks(), 6).wait(), Ok(Bytes::from("abcdef")));
+        assert_eq!(
+            read_body(chunks(), 20).wait(),
+            Ok(Bytes::from("abcdefghi"))
+        );
+    }
+
+    #[test]
+    fn test_host_and_port() {
+        assert_eq!(
+            host_and_port("https://Example.com/a"),
+            Some(("example.com".to_owned(), 443))
+        );
+        assert_eq!(
+            host_and_port("http://[::1]:8080/"),
+            Some(("::1".to_owned(), 8080))
+        );
+        assert_eq!(host_and_port("file:///etc/passwd"), None);
+        assert_eq!(host_and_port("ftp://example.com/"), None);
+        assert_eq!(host_and_port("not a url"), None);
+    }
+
+    #[test]
+    fn test_is_public() {
+        let public = ["93.184.216.34", "2606:2800:220:1::1"];
+        let internal = [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ];
+
+        for ip in &public {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in &internal {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test_check_public() {
+        let mut sys = actix_rt::System::new("test");
+
+        assert!(sys
+            .block_on(check_public("http://127.0.0.1:8080/"))
+            .is_err());
+        assert!(sys.block_on(check_public("http://[::1]/")).is_err());
+        assert!(sys.block_on(check_public("file:///etc/passwd")).is_err());
+        assert!(sys.block_on(check_public("http://93.184.216.34/")).is_ok());
+    }
+
+    #[test]
+    fn test_redirect_target() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::LOCATION, HeaderValue::from_static("../b?c=d"));
+
+        assert_eq!(
+            redirect_target("http://ok/x/y/z", StatusCode::FOUND, &headers),
+            Some("http://ok/x/b?c=d".to_owned())
+        );
+        assert_eq!(
+            redirect_target("http://ok/x/y/z", StatusCode::OK, &headers),
+            None
+        );
+        assert_eq!(
+            redirect_target(
+                "http://ok/",
+                StatusCode::MOVED_PERMANENTLY,
+                &HeaderMap::new()
+            ),
+            None
+        );
+    }
+}
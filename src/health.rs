@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use serde_derive::Serialize;
+
+// The newest migration of this build, see `build.rs`
+pub const LATEST_MIGRATION: &str = env!("LATEST_MIGRATION");
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Up,
+    Down,
+}
+
+// How a dependency fared, errors are kept vague as `/readyz` is public
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub status: Status,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<&'static str>,
+}
+
+impl Check {
+    pub fn up(latency: Duration) -> Self {
+        Check {
+            status: Status::Up,
+            latency_ms: latency.as_millis() as u64,
+            error: None,
+        }
+    }
+
+    pub fn down(latency: Duration, error: &'static str) -> Self {
+        Check {
+            status: Status::Down,
+            latency_ms: latency.as_millis() as u64,
+            error: Some(error),
+        }
+    }
+}
+
+// The database is ready once it has every migration of this build, given
+// the newest one applied. A newer build may have applied more.
+pub fn check_migrations(applied: Option<&str>, latency: Duration) -> Check {
+    compare_migrations(applied, LATEST_MIGRATION, latency)
+}
+
+fn compare_migrations(
+    applied: Option<&str>,
+    expected: &str,
+    latency: Duration,
+) -> Check {
+    // versions are timestamps of the same length
+    if applied.map_or(false, |applied| applied >= expected) {
+        Check::up(latency)
+    } else {
+        Check::down(latency, "pending migrations")
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Readiness {
+    Ready,
+    // search is down, pages are served from the database, and search pages
+    // from the cache
+    Degraded,
+    Unavailable,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub status: Readiness,
+    pub database: Check,
+    pub migrations: Check,
+    pub search: Check,
+}
+
+impl Report {
+    pub fn new(database: Check, migrations: Check, search: Check) -> Self {
+        let status = if database.status == Status::Down
+            || migrations.status == Status::Down
+        {
+            Readiness::Unavailable
+        } else if search.status == Status::Down {
+            Readiness::Degraded
+        } else {
+            Readiness::Ready
+        };
+        Report {
+            status,
+            database,
+            migrations,
+            search,
+        }
+    }
+
+    // Degraded servers still take traffic
+    pub fn is_ready(&self) -> bool {
+        self.status != Readiness::Unavailable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn up() -> Check {
+        Check::up(Duration::from_millis(3))
+    }
+
+    fn down() -> Check {
+        Check::down(Duration::from_secs(2), "timed out")
+    }
+
+    #[test]
+    fn test_readiness() {
+        let report = Report::new(up(), up(), up());
+        assert_eq!(report.status, Readiness::Ready);
+
+        let report = Report::new(up(), up(), down());
+        assert_eq!(report.status, Readiness::Degraded);
+        assert!(report.is_ready());
+
+        let report = Report::new(up(), down(), up());
+        assert_eq!(report.status, Readiness::Unavailable);
+        let report = Report::new(down(), down(), down());
+        assert!(!report.is_ready());
+    }
+
+    #[test]
+    fn test_check_migrations() {
+        let latency = Duration::from_millis(1);
+        let check = |applied| {
+            compare_migrations(applied, "20191024092045", latency).status
+        };
+
+        assert_eq!(check(Some("20191024092045")), Status::Up);
+        assert_eq!(check(Some("20191101120000")), Status::Up);
+        // behind, the database misses the newest migration
+        assert_eq!(check(Some("20191023090412")), Status::Down);
+        assert_eq!(check(None), Status::Down);
+        assert_eq!(LATEST_MIGRATION.len(), 14);
+    }
+
+    #[test]
+    fn test_report_json() {
+        let report = Report::new(up(), up(), down());
+
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "status": "degraded",
+                "database": { "status": "up", "latency_ms": 3 },
+                "migrations": { "status": "up", "latency_ms": 3 },
+                "search": {
+                    "status": "down",
+                    "latency_ms": 2000,
+                    "error": "timed out",
+                },
+            })
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use actix::prelude::*;
+use actix_web::Error;
+use futures::future::{self, Either};
+use futures::{stream, Future, Stream};
+use log::*;
+
+use crate::db::{
+    BookmarkIndexed, DbExecutor, QueryBookmarkDoc, QueryUnindexed,
+};
+use crate::error::ServiceError;
+use crate::models::Bookmark;
+use crate::search::SearchClient;
+
+// Sends the doc of a bookmark to toshi, and marks it indexed. Failures are
+// only logged: the bookmark is left pending and retried on next startup.
+pub fn index_bookmark(
+    bookmark: Bookmark,
+    db: Addr<DbExecutor>,
+    search_client: &SearchClient,
+) -> impl Future<Item = Bookmark, Error = Error> {
+    let bookmark_id = bookmark.id;
+    let search_client = search_client.clone();
+
+    db.send(QueryBookmarkDoc(bookmark_id))
+        .from_err()
+        .and_then(|doc| doc.map_err(Error::from))
+        .and_then(move |doc| search_client.replace_doc(doc))
+        .and_then(move |indexed| match indexed {
+            Ok(_) => {
+                info!("Bookmark(id={}) indexed, updating db...", bookmark_id);
+                Either::A(db.send(BookmarkIndexed::new(bookmark_id)).from_err())
+            }
+            Err(err) => Either::B(future::err(Error::from(err))),
+        })
+        .and_then(|bm| match bm {
+            Ok(bm) => {
+                info!("Bookmark(id={}) updated: {:?}", bm.id, bm);
+                Ok(bm)
+            }
+            Err(err) => Err(Error::from(ServiceError::from(err))),
+        })
+        .or_else(move |err| {
+            error!("Failed to index doc: {:?}", err);
+            Ok::<_, Error>(bookmark)
+        })
+}
+
+// One after another, toshi commits on every insert.
+pub fn index_bookmarks(
+    bookmarks: Vec<Bookmark>,
+    db: Addr<DbExecutor>,
+    search_client: &SearchClient,
+) -> impl Future<Item = Vec<Bookmark>, Error = Error> {
+    let search_client = search_client.clone();
+
+    stream::iter_ok(bookmarks)
+        .and_then(move |bookmark| {
+            index_bookmark(bookmark, db.clone(), &search_client)
+        })
+        .collect()
+}
+
+pub fn index_pending(
+    db: Addr<DbExecutor>,
+) -> impl Future<Item = (), Error = ()> {
+    future::lazy(move || {
+        let search_client = SearchClient::new();
+
+        db.send(QueryUnindexed)
+            .from_err()
+            .and_then(|pending| pending.map_err(Error::from))
+            .and_then(move |pending| {
+                info!("Indexing {} pending bookmark(s)...", pending.len());
+                index_bookmarks(pending, db, &search_client).map(|_| ())
+            })
+    })
+    .map_err(|err: Error| {
+        error!("Failed to index pending bookmarks: {:?}", err)
+    })
+}
@@ -0,0 +1,337 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use jsonwebtoken::{sign, verify, Algorithm};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde_derive::Deserialize;
+
+// A keyset file lists keys oldest first, e.g.
+//
+// [[keys]]
+// kid = "2019-10"
+// secret = "..."
+// retired = true
+//
+// [[keys]]
+// kid = "2019-11"
+// algorithm = "RS256"
+// private_key = "/run/secrets/jwt-2019-11.der"
+// public_key = "/run/secrets/jwt-2019-11.pub.der"
+//
+// RS* keys are DER encoded PKCS#1 (RSAPrivateKey and RSAPublicKey), ES*
+// private keys DER encoded PKCS#8 and public keys an uncompressed point.
+#[derive(Debug, Deserialize)]
+struct KeySetFile {
+    keys: Vec<KeyEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyEntry {
+    kid: String,
+    #[serde(default)]
+    algorithm: Algorithm,
+    // HS* algorithms only
+    secret: Option<String>,
+    // RS* and ES* algorithms only
+    private_key: Option<PathBuf>,
+    public_key: Option<PathBuf>,
+    #[serde(default)]
+    retired: bool,
+}
+
+#[derive(Debug)]
+pub struct Key {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    // None for keys which only verify tokens signed elsewhere
+    signing: Option<Vec<u8>>,
+    verifying: Vec<u8>,
+    retired: bool,
+}
+
+impl Key {
+    pub fn signing_key(&self) -> Option<&[u8]> {
+        self.signing.as_ref().map(Vec::as_slice)
+    }
+
+    pub fn verifying_key(&self) -> &[u8] {
+        &self.verifying
+    }
+}
+
+// Keys tokens are signed and verified with. Tokens carry the `kid` of the
+// key which signed them, so keys can be rotated without signing everyone
+// out: add a new key, and retire the old one once its tokens expired.
+#[derive(Debug)]
+pub struct KeySet {
+    keys: Vec<Key>,
+}
+
+fn is_hmac(algorithm: Algorithm) -> bool {
+    match algorithm {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => true,
+        _ => false,
+    }
+}
+
+// Length of an uncompressed point of the curve of ES* algorithms
+fn ec_point_len(algorithm: Algorithm) -> Option<usize> {
+    match algorithm {
+        Algorithm::ES256 => Some(65),
+        Algorithm::ES384 => Some(97),
+        _ => None,
+    }
+}
+
+// Keys in the wrong format would otherwise only fail signing in
+fn check_key(key: &Key) -> Result<(), String> {
+    if let Some(len) = ec_point_len(key.algorithm) {
+        let point = &key.verifying;
+        if point.len() != len || point[0] != 0x04 {
+            return Err(format!(
+                "Public key of {} is not an uncompressed point",
+                key.kid
+            ));
+        }
+    }
+    let signing = match key.signing {
+        Some(ref signing) if !is_hmac(key.algorithm) => signing,
+        _ => return Ok(()),
+    };
+    const PROBE: &str = "probe";
+    let signature = sign(PROBE, signing, key.algorithm)
+        .map_err(|err| format!("Private key of {}: {}", key.kid, err))?;
+    match verify(&signature, PROBE, &key.verifying, key.algorithm) {
+        Ok(true) => Ok(()),
+        _ => Err(format!(
+            "Public key of {} doesn't match its private key",
+            key.kid
+        )),
+    }
+}
+
+fn read_key(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+impl KeySet {
+    // A single HS256 key, as given by `JWT_SECRET`
+    pub fn from_secret(secret: Vec<u8>) -> Self {
+        KeySet {
+            keys: vec![Key {
+                kid: "default".to_owned(),
+                algorithm: Algorithm::HS256,
+                signing: Some(secret.clone()),
+                verifying: secret,
+                retired: false,
+            }],
+        }
+    }
+
+    // Tokens signed with a random key don't outlive the process
+    pub fn random() -> Self {
+        let mut secret = vec![0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+
+        Self::from_secret(secret)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        Self::from_toml(&content, &read_key)
+    }
+
+    fn from_toml(
+        content: &str,
+        read_key: &dyn Fn(&Path) -> Result<Vec<u8>, String>,
+    ) -> Result<Self, String> {
+        let file: KeySetFile =
+            toml::from_str(content).map_err(|err| err.to_string())?;
+
+        let mut kids = HashSet::new();
+        let mut keys = Vec::with_capacity(file.keys.len());
+        for entry in file.keys {
+            if !kids.insert(entry.kid.clone()) {
+                return Err(format!("Duplicate key id {}", entry.kid));
+            }
+            let (signing, verifying) = if is_hmac(entry.algorithm) {
+                match (entry.secret, entry.private_key, entry.public_key) {
+                    (Some(secret), None, None) => {
+                        let secret = secret.into_bytes();
+                        (Some(secret.clone()), secret)
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Key {} needs a secret, and no key files",
+                            entry.kid
+                        ))
+                    }
+                }
+            } else {
+                match (entry.secret, entry.public_key) {
+                    (None, Some(public_key)) => {
+                        let signing = match entry.private_key {
+                            Some(path) => Some(read_key(&path)?),
+                            None => None,
+                        };
+                        (signing, read_key(&public_key)?)
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Key {} needs a public key, and no secret",
+                            entry.kid
+                        ))
+                    }
+                }
+            };
+            let key = Key {
+                kid: entry.kid,
+                algorithm: entry.algorithm,
+                signing,
+                verifying,
+                retired: entry.retired,
+            };
+            check_key(&key)?;
+            keys.push(key);
+        }
+
+        if keys.iter().all(|key| key.retired) {
+            return Err("No key which is not retired".to_owned());
+        }
+        Ok(KeySet { keys })
+    }
+
+    // The newest key which can sign
+    pub fn signing_key(&self) -> Option<&Key> {
+        self.keys
+            .iter()
+            .rev()
+            .find(|key| !key.retired && key.signing.is_some())
+    }
+
+    pub fn verifying_key(&self, kid: &str) -> Option<&Key> {
+        self.keys.iter().find(|key| key.kid == kid && !key.retired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+    fn fake_key(path: &Path) -> Result<Vec<u8>, String> {
+        Ok(path.to_string_lossy().into_owned().into_bytes())
+    }
+
+    // An ES256 key pair, the public key ends the PKCS#8 document
+    fn es256_keys() -> (Vec<u8>, Vec<u8>) {
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(
+            &ECDSA_P256_SHA256_FIXED_SIGNING,
+            &SystemRandom::new(),
+        )
+        .unwrap();
+        let pkcs8 = pkcs8.as_ref().to_vec();
+        let public = pkcs8[pkcs8.len() - 65..].to_vec();
+        (pkcs8, public)
+    }
+
+    #[test]
+    fn test_check_key() {
+        let (private, public) = es256_keys();
+        let (other, _) = es256_keys();
+        let key = |signing: Option<&Vec<u8>>, verifying: &Vec<u8>| Key {
+            kid: "es".to_owned(),
+            algorithm: Algorithm::ES256,
+            signing: signing.cloned(),
+            verifying: verifying.clone(),
+            retired: false,
+        };
+
+        assert!(check_key(&key(Some(&private), &public)).is_ok());
+        assert!(check_key(&key(None, &public)).is_ok());
+        assert!(check_key(&key(Some(&other), &public)).is_err());
+        assert!(check_key(&key(Some(&public), &public)).is_err());
+        assert!(check_key(&key(Some(&private), &private)).is_err());
+    }
+
+    #[test]
+    fn test_keyset() {
+        let keys = KeySet::from_toml(
+            r#"
+            [[keys]]
+            kid = "old"
+            secret = "old secret"
+            retired = true
+
+            [[keys]]
+            kid = "current"
+            secret = "current secret"
+
+            [[keys]]
+            kid = "elsewhere"
+            algorithm = "RS256"
+            public_key = "elsewhere.pub.der"
+            "#,
+            &fake_key,
+        )
+        .unwrap();
+
+        let signing = keys.signing_key().unwrap();
+        assert_eq!(signing.kid, "current");
+        assert_eq!(signing.signing_key(), Some(&b"current secret"[..]));
+        assert!(keys.verifying_key("old").is_none());
+        assert!(keys.verifying_key("unknown").is_none());
+
+        let elsewhere = keys.verifying_key("elsewhere").unwrap();
+        assert_eq!(elsewhere.algorithm, Algorithm::RS256);
+        assert_eq!(elsewhere.verifying_key(), b"elsewhere.pub.der");
+        assert!(elsewhere.signing_key().is_none());
+    }
+
+    #[test]
+    fn test_invalid_keyset() {
+        let invalid = [
+            "keys = []",
+            r#"
+            [[keys]]
+            kid = "a"
+            secret = "secret"
+            retired = true
+            "#,
+            r#"
+            [[keys]]
+            kid = "a"
+            secret = "secret"
+
+            [[keys]]
+            kid = "a"
+            secret = "other secret"
+            "#,
+            r#"
+            [[keys]]
+            kid = "a"
+            algorithm = "RS256"
+            private_key = "a.der"
+            "#,
+            r#"
+            [[keys]]
+            kid = "a"
+            public_key = "a.pub.der"
+            "#,
+            r#"
+            [[keys]]
+            kid = "a"
+            algorithm = "RS256"
+            private_key = "a.der"
+            public_key = "a.pub.der"
+            "#,
+        ];
+        for content in invalid.iter() {
+            assert!(KeySet::from_toml(content, &fake_key).is_err());
+        }
+    }
+}
@@ -4,13 +4,26 @@ extern crate diesel;
 #[macro_use]
 pub mod macros;
 
+pub mod api_tokens;
 pub mod config;
+pub mod cookies;
 pub mod db;
 pub mod error;
+pub mod fetch;
+pub mod health;
+pub mod indexing;
 pub mod jsonml;
+pub mod keys;
+pub mod links;
+pub mod mock_oidc;
 pub mod models;
+pub mod oidc;
+pub mod page_cache;
 pub mod pagination;
+pub mod readability;
 pub mod schema;
 pub mod search;
+pub mod sessions;
 pub mod templates;
+pub mod throttle;
 pub mod utils;
@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_web::client::Client;
+use actix_web::http::Method;
+use actix_web::Error;
+use chrono::Utc;
+use futures::future::{self, Either};
+use futures::{stream, Future, Stream};
+use log::*;
+use tokio_timer::Delay;
+use url::Url;
+
+use crate::db::{DbExecutor, QueryLinksToCheck, RecordLinkChecks};
+use crate::fetch::fetch_status;
+use crate::models::LinkCheck;
+
+#[derive(Debug, Clone)]
+pub struct CheckOptions {
+    // number of hosts requested at the same time
+    pub concurrency: usize,
+    // pause between two requests to the same host
+    pub host_delay: Duration,
+    pub timeout: Duration,
+    // bookmarks checked per run
+    pub batch_size: i64,
+    pub recheck_after: chrono::Duration,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        CheckOptions {
+            concurrency: 8,
+            host_delay: Duration::from_secs(2),
+            timeout: Duration::from_secs(20),
+            batch_size: 100,
+            recheck_after: chrono::Duration::days(7),
+        }
+    }
+}
+
+// Checks the links of bookmarks that are due, and records the results.
+pub fn check_pending(
+    db: Addr<DbExecutor>,
+    options: CheckOptions,
+) -> impl Future<Item = usize, Error = Error> {
+    let msg = QueryLinksToCheck {
+        checked_before: Utc::now().naive_utc() - options.recheck_after,
+        limit: options.batch_size,
+    };
+    let record_db = db.clone();
+
+    db.send(msg)
+        .from_err()
+        .and_then(|targets| targets.map_err(Error::from))
+        .and_then(move |targets| {
+            info!("Checking {} link(s)...", targets.len());
+            check_links(targets, &options)
+        })
+        .and_then(move |checks| {
+            record_db.send(RecordLinkChecks(checks)).from_err()
+        })
+        .and_then(|recorded| recorded.map_err(Error::from))
+}
+
+// Requests to different hosts run concurrently, those to the same host
+// one after another, so that no site gets hammered.
+pub fn check_links(
+    targets: Vec<(i32, String)>,
+    options: &CheckOptions,
+) -> impl Future<Item = Vec<LinkCheck>, Error = Error> {
+    let CheckOptions {
+        concurrency,
+        host_delay,
+        timeout,
+        ..
+    } = options.clone();
+
+    future::lazy(move || {
+        let client = Client::default();
+
+        stream::iter_ok(group_by_host(targets))
+            .map(move |queue| check_host(&client, queue, host_delay, timeout))
+            .buffer_unordered(concurrency.max(1))
+            .concat2()
+    })
+}
+
+fn check_host(
+    client: &Client,
+    queue: Vec<(i32, String)>,
+    delay: Duration,
+    timeout: Duration,
+) -> impl Future<Item = Vec<LinkCheck>, Error = Error> {
+    let client = client.clone();
+
+    stream::iter_ok(queue.into_iter().enumerate())
+        .and_then(move |(i, (id, url))| {
+            let client = client.clone();
+            let wait = if i == 0 {
+                Duration::from_secs(0)
+            } else {
+                delay
+            };
+            Delay::new(Instant::now() + wait)
+                .then(move |_| check_link(&client, id, url, timeout))
+        })
+        .collect()
+}
+
+fn check_link(
+    client: &Client,
+    id: i32,
+    url: String,
+    timeout: Duration,
+) -> impl Future<Item = LinkCheck, Error = Error> {
+    let get_client = client.clone();
+
+    fetch_status(client, Method::HEAD, &url, timeout)
+        .and_then(move |head| {
+            // plenty of servers don't implement HEAD, make sure with a GET
+            if head.status.is_client_error() || head.status.is_server_error() {
+                Either::A(fetch_status(&get_client, Method::GET, &url, timeout))
+            } else {
+                Either::B(future::ok(head))
+            }
+        })
+        .then(move |fetched| {
+            let checked = Utc::now().naive_utc();
+            let check = match fetched {
+                Ok(fetched) => LinkCheck {
+                    bookmark_id: id,
+                    checked,
+                    status: Some(i32::from(fetched.status.as_u16())),
+                    final_url: Some(fetched.final_url),
+                },
+                Err(err) => {
+                    info!("Bookmark(id={}) link is unreachable: {}", id, err);
+                    LinkCheck {
+                        bookmark_id: id,
+                        checked,
+                        status: None,
+                        final_url: None,
+                    }
+                }
+            };
+            Ok(check)
+        })
+}
+
+fn group_by_host(targets: Vec<(i32, String)>) -> Vec<Vec<(i32, String)>> {
+    let mut hosts: HashMap<String, Vec<_>> = HashMap::new();
+    for (id, url) in targets {
+        let host = Url::parse(&url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_lowercase))
+            .unwrap_or_default();
+        hosts.entry(host).or_default().push((id, url));
+    }
+
+    hosts.into_iter().map(|(_, queue)| queue).collect()
+}
+
+// Checks a batch of links every so often, in the background
+pub struct LinkCheckScheduler {
+    db: Addr<DbExecutor>,
+    interval: Duration,
+    options: CheckOptions,
+    running: bool,
+}
+
+impl LinkCheckScheduler {
+    pub fn new(db: Addr<DbExecutor>, interval: Duration) -> Self {
+        LinkCheckScheduler {
+            db,
+            interval,
+            options: CheckOptions::default(),
+            running: false,
+        }
+    }
+
+    fn run(&mut self, ctx: &mut Context<Self>) {
+        // the previous batch is still being checked
+        if self.running {
+            return;
+        }
+        self.running = true;
+
+        let checks = check_pending(self.db.clone(), self.options.clone());
+        ctx.spawn(checks.into_actor(self).then(|res, act, _| {
+            act.running = false;
+            match res {
+                Ok(checked) => info!("Checked {} link(s)", checked),
+                Err(err) => error!("Failed to check links: {:?}", err),
+            }
+            fut::ok(())
+        }));
+    }
+}
+
+impl Actor for LinkCheckScheduler {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.run(ctx);
+        ctx.run_interval(self.interval, |act, ctx| act.run(ctx));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use std::sync::mpsc;
+    use std::thread;
+
+    // serves pages for each of the outcomes a check can have
+    fn serve_test_pages() -> String {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let sys = actix_rt::System::new("test-pages");
+            let server = HttpServer::new(|| {
+                App::new()
+                    .route("/ok", web::route().to(|| HttpResponse::Ok()))
+                    .route(
+                        "/moved",
+                        web::route().to(|| {
+                            HttpResponse::MovedPermanently()
+                                .header(header::LOCATION, "/ok")
+                                .finish()
+                        }),
+                    )
+                    .route(
+                        "/gone",
+                        web::route().to(|| HttpResponse::NotFound()),
+                    )
+                    .route(
+                        "/slow",
+                        web::route().to_async(|| {
+                            let wait = Instant::now() + Duration::from_secs(5);
+                            Delay::new(wait).then(|_| {
+                                Ok::<_, Error>(HttpResponse::Ok().finish())
+                            })
+                        }),
+                    )
+            })
+            .bind("127.0.0.1:0")
+            .unwrap();
+
+            tx.send(server.addrs()[0]).unwrap();
+            server.start();
+            let _ = sys.run();
+        });
+
+        format!("http://{}", rx.recv().unwrap())
+    }
+
+    #[test]
+    fn test_check_links() {
+        let base = serve_test_pages();
+        let targets = vec![
+            (1, format!("{}/ok", base)),
+            (2, format!("{}/moved", base)),
+            (3, format!("{}/gone", base)),
+            (4, format!("{}/slow", base)),
+        ];
+        let options = CheckOptions {
+            host_delay: Duration::from_millis(10),
+            timeout: Duration::from_millis(500),
+            ..CheckOptions::default()
+        };
+
+        let mut checks = actix_rt::System::new("test")
+            .block_on(check_links(targets, &options))
+            .unwrap();
+        checks.sort_by_key(|check| check.bookmark_id);
+        let results: Vec<_> = checks
+            .into_iter()
+            .map(|check| (check.bookmark_id, check.status, check.final_url))
+            .collect();
+
+        assert_eq!(
+            results,
+            vec![
+                (1, Some(200), Some(format!("{}/ok", base))),
+                (2, Some(200), Some(format!("{}/ok", base))),
+                (3, Some(404), Some(format!("{}/gone", base))),
+                (4, None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_by_host() {
+        let targets = vec![
+            (1, "https://a.com/x".to_owned()),
+            (2, "https://b.com/x".to_owned()),
+            (3, "http://A.com/y".to_owned()),
+        ];
+
+        let mut groups = group_by_host(targets);
+        groups.sort();
+
+        assert_eq!(
+            groups,
+            vec![
+                vec![
+                    (1, "https://a.com/x".to_owned()),
+                    (3, "http://A.com/y".to_owned())
+                ],
+                vec![(2, "https://b.com/x".to_owned())],
+            ]
+        );
+    }
+}
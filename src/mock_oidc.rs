@@ -0,0 +1,141 @@
+// An identity provider signing everyone in as the same user, to try out
+// OpenID Connect logins without setting up a real one.
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use serde_derive::Deserialize;
+use serde_json::json;
+use url::Url;
+
+use crate::oidc::pkce_challenge;
+use crate::sessions::random_token;
+
+pub struct Provider {
+    issuer: String,
+    email: String,
+    groups: Vec<String>,
+    // code challenges by code
+    codes: Mutex<HashMap<String, String>>,
+    access_tokens: Mutex<Vec<String>>,
+}
+
+impl Provider {
+    pub fn new(issuer: String, email: String, groups: Vec<String>) -> Self {
+        Provider {
+            issuer,
+            email,
+            groups,
+            codes: Mutex::new(HashMap::new()),
+            access_tokens: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthorizeQuery {
+    redirect_uri: String,
+    state: String,
+    code_challenge: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenForm {
+    code: String,
+    code_verifier: String,
+}
+
+fn discovery(provider: web::Data<Provider>) -> HttpResponse {
+    let issuer = &provider.issuer;
+    HttpResponse::Ok().json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{}/authorize", issuer),
+        "token_endpoint": format!("{}/token", issuer),
+        "userinfo_endpoint": format!("{}/userinfo", issuer),
+    }))
+}
+
+// Approves right away, sending the user back with a code
+fn authorize(
+    query: web::Query<AuthorizeQuery>,
+    provider: web::Data<Provider>,
+) -> HttpResponse {
+    let code = random_token();
+    let mut location = match Url::parse(&query.redirect_uri) {
+        Ok(url) => url,
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+    location
+        .query_pairs_mut()
+        .append_pair("code", &code)
+        .append_pair("state", &query.state);
+    provider
+        .codes
+        .lock()
+        .unwrap()
+        .insert(code, query.code_challenge.clone());
+    HttpResponse::Found()
+        .header(header::LOCATION, location.as_str())
+        .finish()
+}
+
+fn token(
+    form: web::Form<TokenForm>,
+    provider: web::Data<Provider>,
+) -> HttpResponse {
+    let challenge = provider.codes.lock().unwrap().remove(&form.code);
+    if challenge != Some(pkce_challenge(&form.code_verifier)) {
+        return HttpResponse::BadRequest()
+            .json(json!({ "error": "invalid_grant" }));
+    }
+    let access_token = random_token();
+    provider
+        .access_tokens
+        .lock()
+        .unwrap()
+        .push(access_token.clone());
+    HttpResponse::Ok().json(json!({
+        "access_token": access_token,
+        "token_type": "Bearer",
+    }))
+}
+
+fn userinfo(req: HttpRequest, provider: web::Data<Provider>) -> HttpResponse {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.splitn(2, ' ').nth(1))
+        .unwrap_or("");
+    if !provider
+        .access_tokens
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|t| t == token)
+    {
+        return HttpResponse::Unauthorized().finish();
+    }
+    HttpResponse::Ok().json(json!({
+        "sub": provider.email,
+        "email": provider.email,
+        "email_verified": true,
+        "preferred_username": provider.email.split('@').next(),
+        "groups": provider.groups,
+    }))
+}
+
+// Endpoints of the provider, which needs to be registered as app data
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.route(
+        "/.well-known/openid-configuration",
+        web::get().to(discovery),
+    )
+    .route("/authorize", web::get().to(authorize))
+    .route("/token", web::post().to(token))
+    .route("/userinfo", web::get().to(userinfo));
+}
@@ -1,7 +1,8 @@
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::fmt;
 use std::io::Write;
 use std::marker::PhantomData;
+use std::str::FromStr;
 
 use chrono::naive::NaiveDateTime;
 use diesel::deserialize::{self, FromSql};
@@ -9,20 +10,27 @@ use diesel::not_none;
 use diesel::pg::types::sql_types::Jsonb;
 use diesel::pg::Pg;
 use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::{Int8, Text, Timestamp};
 use serde::de::{
     self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor,
 };
+use serde::ser::Serializer;
 use serde_derive::*;
 
-use crate::schema::{bookmarks, users};
+use crate::schema::{
+    api_tokens, audit_log, bookmarks, collection_items, collections,
+    highlights, saved_searches, sessions, snapshots, user_roles, users,
+};
 
-#[derive(Debug, Clone, Queryable)]
+#[derive(Debug, Clone, Queryable, Serialize)]
 pub struct User {
     pub id: i32,
     pub created: NaiveDateTime,
     pub email: String,
+    #[serde(skip_serializing)]
     pub password: String,
-    pub is_admin: bool,
+    pub name: String,
+    pub active: bool,
 }
 
 #[derive(Insertable, AsChangeset)]
@@ -30,20 +38,258 @@ pub struct User {
 pub struct NewUser<'a> {
     pub email: &'a str,
     pub password: &'a str,
-    pub is_admin: bool,
+    pub name: &'a str,
+}
+
+// A user along with their roles, as listed to admins
+#[derive(Debug, Clone, Serialize)]
+pub struct UserDetails {
+    #[serde(flatten)]
+    pub user: User,
+    pub roles: Vec<Role>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "user_roles"]
+pub struct UserRole {
+    pub user_id: i32,
+    pub role: Role,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct SlimUser {
+    pub id: i32,
     pub email: String,
-    pub is_admin: bool,
+    pub name: String,
+    pub roles: Vec<Role>,
+    // session the access token was issued for, None before signing in
+    #[serde(skip_serializing)]
+    pub session_id: Option<i32>,
 }
 
-impl From<User> for SlimUser {
-    fn from(user: User) -> Self {
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserData {
+    pub email: String,
+    pub name: String,
+    pub password: String,
+    #[serde(default)]
+    pub roles: Vec<Role>,
+}
+
+// Fields of a user admins may change, missing ones are left as they are.
+// `roles` replaces all of them.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserChanges {
+    pub roles: Option<Vec<Role>>,
+    pub active: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasswordData {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+impl SlimUser {
+    pub fn new(user: User, roles: Vec<Role>) -> Self {
         SlimUser {
+            id: user.id,
             email: user.email,
-            is_admin: user.is_admin,
+            name: user.name,
+            roles,
+            session_id: None,
+        }
+    }
+
+    pub fn can(&self, permission: Permission) -> bool {
+        self.roles.iter().any(|role| role.grants(permission))
+    }
+}
+
+// What a user may do, each role allows everything the one before it does
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "Text"]
+pub enum Role {
+    // sees private items
+    Viewer,
+    // creates and edits bookmarks
+    Editor,
+    // manages users, sessions, tags and the search index
+    Admin,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Permission {
+    ViewPrivate,
+    EditBookmarks,
+    ManageUsers,
+    ManageTags,
+    ManageIndex,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn grants(self, permission: Permission) -> bool {
+        use Permission::*;
+
+        match self {
+            Role::Viewer => permission == ViewPrivate,
+            Role::Editor => {
+                permission == ViewPrivate || permission == EditBookmarks
+            }
+            Role::Admin => true,
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "editor" => Ok(Role::Editor),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("Unrecognized role: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct Session {
+    pub id: i32,
+    pub user_id: i32,
+    pub created: NaiveDateTime,
+    pub last_used: NaiveDateTime,
+    pub expires: NaiveDateTime,
+    #[serde(skip_serializing)]
+    pub refresh_hash: String,
+    pub revoked: Option<NaiveDateTime>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "sessions"]
+pub struct NewSession {
+    pub user_id: i32,
+    pub expires: NaiveDateTime,
+    pub refresh_hash: String,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RefreshData {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct ApiToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub scope: TokenScope,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub created: NaiveDateTime,
+    pub last_used: Option<NaiveDateTime>,
+    pub revoked: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "api_tokens"]
+pub struct NewApiToken {
+    pub user_id: i32,
+    pub name: String,
+    pub scope: TokenScope,
+    pub token_hash: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiTokenData {
+    pub name: String,
+    pub scope: TokenScope,
+}
+
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct AuditEntry {
+    pub id: i32,
+    pub created: NaiveDateTime,
+    pub actor: String,
+    pub action: String,
+    pub target_id: Option<i32>,
+    pub diff: AuditDiff,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "audit_log"]
+pub struct NewAuditEntry<'a> {
+    pub actor: &'a str,
+    pub action: &'a str,
+    pub target_id: Option<i32>,
+    pub diff: AuditDiff,
+}
+
+// Changed fields, as {"field": {"before": ..., "after": ...}}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditDiff(pub serde_json::Value);
+
+// What an api token may be used for, see `api_tokens::allows`
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "Text"]
+pub enum TokenScope {
+    Read,
+    WriteBookmarks,
+}
+
+impl TokenScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::WriteBookmarks => "write_bookmarks",
+        }
+    }
+}
+
+impl FromStr for TokenScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(TokenScope::Read),
+            "write_bookmarks" => Ok(TokenScope::WriteBookmarks),
+            _ => Err(format!("Unrecognized token scope: {}", s)),
         }
     }
 }
@@ -67,7 +313,9 @@ pub struct ScoredDoc {
     pub doc: BookmarkDoc,
 }
 
-#[derive(Debug, Clone, Queryable, Deserialize, Serialize, PartialEq)]
+#[derive(
+    Debug, Clone, Queryable, Identifiable, Deserialize, Serialize, PartialEq,
+)]
 pub struct Bookmark {
     pub id: i32,
     pub created: NaiveDateTime,
@@ -77,6 +325,17 @@ pub struct Bookmark {
     pub tags: TagSet,
     #[serde(skip_serializing)]
     pub toshi_index: Option<String>,
+    #[serde(default)]
+    pub status: ReadingStatus,
+    pub read_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub starred: bool,
+    #[serde(default)]
+    pub visibility: Visibility,
+    pub last_checked: Option<NaiveDateTime>,
+    pub last_status: Option<i32>,
+    pub final_url: Option<String>,
+    pub user_id: i32,
 }
 
 #[derive(Debug, Clone, Insertable, Deserialize)]
@@ -86,6 +345,339 @@ pub struct NewBookmark {
     pub url: String,
     pub body: String,
     pub tags: TagSet,
+    #[serde(default)]
+    pub visibility: Visibility,
+    // set to the signed in user, never taken from the request
+    #[serde(skip_deserializing)]
+    pub user_id: i32,
+}
+
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+)]
+#[serde(rename_all = "lowercase")]
+#[sql_type = "Text"]
+pub enum ReadingStatus {
+    Unread,
+    Reading,
+    Read,
+    Archived,
+}
+
+impl Default for ReadingStatus {
+    fn default() -> Self {
+        ReadingStatus::Unread
+    }
+}
+
+impl ReadingStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReadingStatus::Unread => "unread",
+            ReadingStatus::Reading => "reading",
+            ReadingStatus::Read => "read",
+            ReadingStatus::Archived => "archived",
+        }
+    }
+
+    pub fn can_transition_to(self, next: ReadingStatus) -> bool {
+        use ReadingStatus::*;
+
+        match (self, next) {
+            (Unread, Reading) | (Unread, Read) | (Unread, Archived) => true,
+            (Reading, Unread) | (Reading, Read) | (Reading, Archived) => true,
+            (Read, Unread) | (Read, Archived) => true,
+            (Archived, Unread) => true,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for ReadingStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unread" => Ok(ReadingStatus::Unread),
+            "reading" => Ok(ReadingStatus::Reading),
+            "read" => Ok(ReadingStatus::Read),
+            "archived" => Ok(ReadingStatus::Archived),
+            _ => Err(format!("Unrecognized reading status: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ReadingStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+)]
+#[serde(rename_all = "lowercase")]
+#[sql_type = "Text"]
+pub enum Visibility {
+    Public,
+    Private,
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Visibility::Public
+    }
+}
+
+impl Visibility {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Private => "private",
+        }
+    }
+}
+
+impl FromStr for Visibility {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "public" => Ok(Visibility::Public),
+            "private" => Ok(Visibility::Private),
+            _ => Err(format!("Unrecognized visibility: {}", s)),
+        }
+    }
+}
+
+// Who a page is rendered for, private bookmarks are only shown to users
+// allowed to view private items, whoever owns them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Audience {
+    Anonymous,
+    Private,
+}
+
+impl Audience {
+    pub fn sees_private(self) -> bool {
+        self == Audience::Private
+    }
+}
+
+// Outcome of requesting a bookmarked url, status is missing when no
+// response was received at all.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LinkCheck {
+    pub bookmark_id: i32,
+    pub checked: NaiveDateTime,
+    pub status: Option<i32>,
+    pub final_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StatusData {
+    pub status: ReadingStatus,
+}
+
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct SavedSearch {
+    pub id: i32,
+    pub created: NaiveDateTime,
+    pub name: String,
+    pub slug: String,
+    pub query: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SavedSearchData {
+    pub name: String,
+    pub query: String,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "saved_searches"]
+pub struct NewSavedSearch {
+    pub name: String,
+    pub slug: String,
+    pub query: String,
+}
+
+impl From<SavedSearchData> for NewSavedSearch {
+    fn from(data: SavedSearchData) -> Self {
+        NewSavedSearch {
+            slug: slugify(&data.name),
+            name: data.name,
+            query: data.query,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct Collection {
+    pub id: i32,
+    pub created: NaiveDateTime,
+    pub slug: String,
+    pub title: String,
+    pub intro: String,
+    pub user_id: i32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CollectionData {
+    pub title: String,
+    #[serde(default)]
+    pub intro: String,
+    pub slug: Option<String>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "collections"]
+pub struct NewCollection {
+    pub slug: String,
+    pub title: String,
+    pub intro: String,
+}
+
+impl From<CollectionData> for NewCollection {
+    fn from(data: CollectionData) -> Self {
+        let slug = match data.slug {
+            Some(ref slug) => slugify(slug),
+            None => slugify(&data.title),
+        };
+        NewCollection {
+            slug,
+            title: data.title,
+            intro: data.intro,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Insertable, Serialize)]
+#[table_name = "collection_items"]
+pub struct CollectionItem {
+    pub collection_id: i32,
+    pub bookmark_id: i32,
+    pub position: i32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CollectionItemData {
+    pub bookmark_id: i32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CollectionOrderData {
+    pub bookmark_ids: Vec<i32>,
+}
+
+#[derive(
+    Debug, Clone, Queryable, Identifiable, Associations, Serialize, PartialEq,
+)]
+#[belongs_to(Bookmark)]
+pub struct Highlight {
+    pub id: i32,
+    pub bookmark_id: i32,
+    pub created: NaiveDateTime,
+    pub quote: String,
+    pub comment: Option<String>,
+    pub position: i32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HighlightData {
+    pub quote: String,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RenameTagData {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MergeTagsData {
+    pub tags: Vec<String>,
+    pub into: String,
+}
+
+#[derive(Debug, Clone, QueryableByName, Serialize, PartialEq)]
+pub struct TagCount {
+    #[sql_type = "Text"]
+    pub tag: String,
+    #[sql_type = "Int8"]
+    pub count: i64,
+    // when the most recent bookmark with this tag was created
+    #[sql_type = "Timestamp"]
+    pub last_used: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "highlights"]
+pub struct NewHighlight {
+    pub bookmark_id: i32,
+    pub quote: String,
+    pub comment: Option<String>,
+    pub position: i32,
+}
+
+// A bookmark along with its highlights, in order
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct HighlightedBookmark {
+    #[serde(flatten)]
+    pub bookmark: Bookmark,
+    pub highlights: Vec<Highlight>,
+}
+
+impl From<Bookmark> for HighlightedBookmark {
+    fn from(bookmark: Bookmark) -> Self {
+        HighlightedBookmark {
+            bookmark,
+            highlights: vec![],
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Queryable, Identifiable, Associations, Serialize, PartialEq,
+)]
+#[belongs_to(Bookmark)]
+pub struct Snapshot {
+    pub id: i32,
+    pub bookmark_id: i32,
+    pub fetched: NaiveDateTime,
+    // none when the page could not be fetched
+    pub http_status: Option<i32>,
+    pub final_url: String,
+    pub title: Option<String>,
+    pub byline: Option<String>,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "snapshots"]
+pub struct NewSnapshot {
+    pub bookmark_id: i32,
+    pub http_status: Option<i32>,
+    pub final_url: String,
+    pub title: Option<String>,
+    pub byline: Option<String>,
+    pub content: String,
 }
 
 #[derive(Debug, Clone, Queryable, Serialize)]
@@ -95,21 +687,69 @@ pub struct BookmarkDoc {
     pub title: String,
     pub url: String,
     pub body: String,
-    pub tags: String,
-}
-
-pub fn normalize_tag<T: AsRef<str>>(tag: T) -> String {
-    tag.as_ref()
-        .trim()
-        .chars()
-        // Default Toshi/tantivy tokenizer split on whitespaces and non-alphanumeric
-        // chars, replace them with a uncommon alpha char so that a whole tag is indexed
-        // as a single token.
-        // https://doc.rust-lang.org/std/primitive.char.html#method.is_alphanumeric
-        .map(|c| if c.is_alphanumeric() { c } else { '¾' })
+    // indexed as keywords, each tag is a single term
+    pub tags: Vec<String>,
+    // every tag along with its ancestors, `tag:lang` matches "lang/rust"
+    pub tag_paths: Vec<String>,
+    pub highlights: String,
+    pub archive: String,
+    pub status: ReadingStatus,
+    pub visibility: Visibility,
+    // indexed as 0 or 1, the index has no boolean fields
+    #[serde(serialize_with = "serialize_flag")]
+    pub starred: bool,
+    pub user_id: i32,
+}
+
+fn serialize_flag<S: Serializer>(flag: &bool, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u64(*flag as u64)
+}
+
+// Tags form a hierarchy separated by `/`: "lang/rust" is a child of "lang".
+// Returns the path of every ancestor of a tag, followed by the tag itself.
+pub fn tag_ancestors(tag: &str) -> Vec<String> {
+    let segments: Vec<_> = tag
+        .split('/')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    (1..=segments.len())
+        .map(|depth| segments[..depth].join("/"))
         .collect()
 }
 
+// Tags are matched regardless of case and of spaces around segments: both
+// "Lang / Go" and "lang/go" are indexed and searched for as "lang/go".
+pub fn normalize_tag(tag: &str) -> String {
+    tag_ancestors(tag)
+        .pop()
+        .map(|tag| tag.to_lowercase())
+        .unwrap_or_default()
+}
+
+// Lowercase, dash-separated version of a name, suitable for use in urls.
+pub fn slugify<T: AsRef<str>>(name: T) -> String {
+    let words = name
+        .as_ref()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase);
+
+    itertools::join(words, "-")
+}
+
+impl From<HighlightedBookmark> for BookmarkDoc {
+    fn from(b: HighlightedBookmark) -> Self {
+        let quotes = b.highlights.iter().map(|h| h.quote.as_str());
+        let highlights = itertools::join(quotes, "\n\n");
+        BookmarkDoc {
+            highlights,
+            ..b.bookmark.into()
+        }
+    }
+}
+
 impl From<Bookmark> for BookmarkDoc {
     fn from(b: Bookmark) -> Self {
         let Bookmark {
@@ -119,6 +759,10 @@ impl From<Bookmark> for BookmarkDoc {
             url,
             body,
             tags,
+            status,
+            starred,
+            visibility,
+            user_id,
             ..
         } = b;
         BookmarkDoc {
@@ -127,7 +771,14 @@ impl From<Bookmark> for BookmarkDoc {
             title,
             url,
             body,
-            tags: tags.join(" "),
+            tags: tags.to_sorted_vec(),
+            tag_paths: tags.paths(),
+            highlights: String::new(),
+            archive: String::new(),
+            status,
+            visibility,
+            starred,
+            user_id,
         }
     }
 }
@@ -140,6 +791,11 @@ impl BookmarkDoc {
             title,
             url,
             body,
+            tags,
+            status,
+            visibility,
+            starred,
+            user_id,
             ..
         } = self;
         Bookmark {
@@ -149,7 +805,15 @@ impl BookmarkDoc {
             url,
             body,
             toshi_index: None,
-            tags: TagSet::default(),
+            tags: TagSet(tags.into_iter().collect()),
+            status,
+            read_at: None,
+            starred,
+            visibility,
+            last_checked: None,
+            last_status: None,
+            final_url: None,
+            user_id,
         }
     }
 }
@@ -168,6 +832,15 @@ impl<'de> Deserialize<'de> for BookmarkDoc {
             Url,
             Body,
             Tags,
+            #[serde(rename = "tag_paths")]
+            TagPaths,
+            Highlights,
+            Archive,
+            Status,
+            Visibility,
+            Starred,
+            #[serde(rename = "user_id")]
+            UserId,
         }
 
         struct UnitArray<T>(T);
@@ -236,7 +909,14 @@ impl<'de> Deserialize<'de> for BookmarkDoc {
                 let mut title: Option<UnitArray<_>> = None;
                 let mut url: Option<UnitArray<_>> = None;
                 let mut body: Option<UnitArray<_>> = None;
-                let mut tags: Option<UnitArray<_>> = None;
+                let mut tags: Option<Vec<String>> = None;
+                let mut tag_paths: Option<Vec<String>> = None;
+                let mut highlights: Option<UnitArray<_>> = None;
+                let mut archive: Option<UnitArray<_>> = None;
+                let mut status: Option<UnitArray<_>> = None;
+                let mut visibility: Option<UnitArray<_>> = None;
+                let mut starred: Option<UnitArray<u64>> = None;
+                let mut user_id: Option<UnitArray<_>> = None;
                 while let Some(key) = map.next_key()? {
                     match key {
                         Field::Id => {
@@ -277,7 +957,65 @@ impl<'de> Deserialize<'de> for BookmarkDoc {
                             if tags.is_some() {
                                 return Err(de::Error::duplicate_field("tags"));
                             }
-                            tags = Some(map.next_value::<UnitArray<_>>()?);
+                            tags = Some(map.next_value()?);
+                        }
+                        Field::TagPaths => {
+                            if tag_paths.is_some() {
+                                return Err(de::Error::duplicate_field(
+                                    "tag_paths",
+                                ));
+                            }
+                            tag_paths = Some(map.next_value()?);
+                        }
+                        Field::Highlights => {
+                            if highlights.is_some() {
+                                return Err(de::Error::duplicate_field(
+                                    "highlights",
+                                ));
+                            }
+                            highlights =
+                                Some(map.next_value::<UnitArray<_>>()?);
+                        }
+                        Field::Archive => {
+                            if archive.is_some() {
+                                return Err(de::Error::duplicate_field(
+                                    "archive",
+                                ));
+                            }
+                            archive = Some(map.next_value::<UnitArray<_>>()?);
+                        }
+                        Field::Status => {
+                            if status.is_some() {
+                                return Err(de::Error::duplicate_field(
+                                    "status",
+                                ));
+                            }
+                            status = Some(map.next_value::<UnitArray<_>>()?);
+                        }
+                        Field::Visibility => {
+                            if visibility.is_some() {
+                                return Err(de::Error::duplicate_field(
+                                    "visibility",
+                                ));
+                            }
+                            visibility =
+                                Some(map.next_value::<UnitArray<_>>()?);
+                        }
+                        Field::Starred => {
+                            if starred.is_some() {
+                                return Err(de::Error::duplicate_field(
+                                    "starred",
+                                ));
+                            }
+                            starred = Some(map.next_value::<UnitArray<_>>()?);
+                        }
+                        Field::UserId => {
+                            if user_id.is_some() {
+                                return Err(de::Error::duplicate_field(
+                                    "user_id",
+                                ));
+                            }
+                            user_id = Some(map.next_value::<UnitArray<_>>()?);
                         }
                     }
                 }
@@ -296,9 +1034,19 @@ impl<'de> Deserialize<'de> for BookmarkDoc {
                 let body = body
                     .map(|x| x.0)
                     .ok_or_else(|| de::Error::missing_field("body"))?;
-                let tags = tags
+                // multi-valued, missing when a bookmark has no tags
+                let tags = tags.unwrap_or_default();
+                // tag paths, highlights and archive are not stored in the index
+                let tag_paths = tag_paths.unwrap_or_default();
+                let highlights = highlights.map(|x| x.0).unwrap_or_default();
+                let archive = archive.map(|x| x.0).unwrap_or_default();
+                // docs indexed before reading status was tracked
+                let status = status.map(|x| x.0).unwrap_or_default();
+                let visibility = visibility.map(|x| x.0).unwrap_or_default();
+                let starred = starred.map(|x| x.0 != 0).unwrap_or_default();
+                let user_id = user_id
                     .map(|x| x.0)
-                    .ok_or_else(|| de::Error::missing_field("tags"))?;
+                    .ok_or_else(|| de::Error::missing_field("user_id"))?;
                 Ok(BookmarkDoc {
                     id,
                     created,
@@ -306,12 +1054,32 @@ impl<'de> Deserialize<'de> for BookmarkDoc {
                     url,
                     body,
                     tags,
+                    tag_paths,
+                    highlights,
+                    archive,
+                    status,
+                    visibility,
+                    starred,
+                    user_id,
                 })
             }
         }
 
-        const FIELDS: &[&str] =
-            &["id", "created", "title", "url", "body", "tags"];
+        const FIELDS: &[&str] = &[
+            "id",
+            "created",
+            "title",
+            "url",
+            "body",
+            "tags",
+            "tag_paths",
+            "highlights",
+            "archive",
+            "status",
+            "visibility",
+            "starred",
+            "user_id",
+        ];
 
         deserializer.deserialize_struct(
             "BookmarkDoc",
@@ -325,8 +1093,30 @@ impl<'de> Deserialize<'de> for BookmarkDoc {
 pub struct TagSet(HashSet<String>);
 
 impl TagSet {
-    fn join(&self, sep: &str) -> String {
-        itertools::join(self.0.iter().map(normalize_tag), sep)
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    // sorted, so that the same tags always make the same doc
+    fn to_sorted_vec(&self) -> Vec<String> {
+        let tags: BTreeSet<_> = self
+            .0
+            .iter()
+            .map(|tag| normalize_tag(tag))
+            .filter(|tag| !tag.is_empty())
+            .collect();
+
+        tags.into_iter().collect()
+    }
+
+    fn paths(&self) -> Vec<String> {
+        let paths: BTreeSet<_> = self
+            .0
+            .iter()
+            .flat_map(|tag| tag_ancestors(&normalize_tag(tag)))
+            .collect();
+
+        paths.into_iter().collect()
     }
 }
 
@@ -334,12 +1124,77 @@ impl TagSet {
 
 #[allow(dead_code)]
 mod foreign_derives {
-    use super::{Jsonb, TagSet};
+    use super::{AuditDiff, Jsonb, TagSet};
 
     #[derive(FromSqlRow, AsExpression)]
     #[diesel(foreign_derive)]
     #[sql_type = "Jsonb"]
     struct TagSetProxy(TagSet);
+
+    #[derive(FromSqlRow, AsExpression)]
+    #[diesel(foreign_derive)]
+    #[sql_type = "Jsonb"]
+    struct AuditDiffProxy(AuditDiff);
+}
+
+impl FromSql<Text, Pg> for ReadingStatus {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Pg>>::from_sql(bytes)?;
+
+        s.parse().map_err(Into::into)
+    }
+}
+
+impl ToSql<Text, Pg> for ReadingStatus {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for Visibility {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Pg>>::from_sql(bytes)?;
+
+        s.parse().map_err(Into::into)
+    }
+}
+
+impl ToSql<Text, Pg> for Visibility {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for Role {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Pg>>::from_sql(bytes)?;
+
+        s.parse().map_err(Into::into)
+    }
+}
+
+impl ToSql<Text, Pg> for Role {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for TokenScope {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let s = <String as FromSql<Text, Pg>>::from_sql(bytes)?;
+
+        s.parse().map_err(Into::into)
+    }
+}
+
+impl ToSql<Text, Pg> for TokenScope {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
 }
 
 impl FromSql<Jsonb, Pg> for TagSet {
@@ -371,6 +1226,24 @@ impl ToSql<Jsonb, Pg> for TagSet {
     }
 }
 
+impl FromSql<Jsonb, Pg> for AuditDiff {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let bytes = not_none!(bytes.and_then(seek_json_start));
+
+        serde_json::from_slice(bytes).map_err(Into::into)
+    }
+}
+
+impl ToSql<Jsonb, Pg> for AuditDiff {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        // prefix jsonb version num.
+        out.write_all(&[1])?;
+        serde_json::to_writer(out, self)
+            .map(|_| IsNull::No)
+            .map_err(Into::into)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -401,6 +1274,53 @@ mod tests {
         assert_eq!(tag_set, expected);
     }
 
+    #[test]
+    fn test_tag_ancestors() {
+        assert_eq!(
+            tag_ancestors("lang/rust/async"),
+            vec!["lang", "lang/rust", "lang/rust/async"]
+        );
+        assert_eq!(tag_ancestors(" lang / go/"), vec!["lang", "lang/go"]);
+        assert!(tag_ancestors("/").is_empty());
+    }
+
+    #[test]
+    fn test_tag_paths_doc() {
+        let tags: TagSet =
+            serde_json::from_str(r#"["lang/rust", "lang/go", "web"]"#).unwrap();
+
+        assert_eq!(tags.to_sorted_vec(), vec!["lang/go", "lang/rust", "web"]);
+        assert_eq!(tags.paths(), vec!["lang", "lang/go", "lang/rust", "web"]);
+
+        let tags: TagSet =
+            serde_json::from_str(r#"["Lang / Go", "lang/go/", " "]"#).unwrap();
+        assert_eq!(tags.to_sorted_vec(), vec!["lang/go"]);
+        assert_eq!(tags.paths(), vec!["lang", "lang/go"]);
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(
+            slugify("Rust, not for beginners"),
+            "rust-not-for-beginners"
+        );
+        assert_eq!(slugify("  tag:rust  "), "tag-rust");
+        assert_eq!(slugify("分散 システム"), "分散-システム");
+    }
+
+    #[test]
+    fn test_status_transitions() {
+        use ReadingStatus::*;
+
+        assert!(Unread.can_transition_to(Read));
+        assert!(Read.can_transition_to(Archived));
+        assert!(Archived.can_transition_to(Unread));
+        assert!(!Archived.can_transition_to(Read));
+        assert!(!Read.can_transition_to(Read));
+        assert_eq!("reading".parse(), Ok(Reading));
+        assert!("done".parse::<ReadingStatus>().is_err());
+    }
+
     #[test]
     fn test_sql_round_trip() {
         // TODO: construct Pg Output
@@ -419,7 +1339,8 @@ mod tests {
 	    "bar",
 	    "foo"
 	  ],
-          "toshi_index": null
+          "toshi_index": null,
+	  "user_id": 1
 	}"#;
         let bookmark: Result<Bookmark, _> = serde_json::from_str(json);
         assert!(bookmark.is_ok());
@@ -433,8 +1354,10 @@ mod tests {
 	  "title": "second",
 	  "url": "http://ok",
 	  "body": "world",
-	  "tags": [],
-          "toshi_index": null
+	  "tags": ["c++", "c#"],
+          "toshi_index": null,
+	  "starred": true,
+	  "user_id": 1
 	}"#;
 
         let json2 = r#"{
@@ -443,7 +1366,9 @@ mod tests {
 	  "title": ["second"],
 	  "url": ["http://ok"],
 	  "body": ["world"],
-	  "tags": ["foo bar"]
+	  "tags": ["c#", "c++"],
+	  "starred": [1],
+	  "user_id": [1]
 	}"#;
 
         let bookmark: Result<Bookmark, _> = serde_json::from_str(json1);
@@ -455,4 +1380,51 @@ mod tests {
             bookmark_doc.unwrap().into_bookmark_lossy()
         );
     }
+
+    #[test]
+    fn test_highlights_doc() {
+        let json = r#"{
+	  "id": 2,
+	  "created": "2019-06-02T10:39:20.840523",
+	  "title": "second",
+	  "url": "http://ok",
+	  "body": "world",
+	  "tags": [],
+	  "user_id": 1
+	}"#;
+        let bookmark: Bookmark = serde_json::from_str(json).unwrap();
+        let highlight = |id, quote: &str| Highlight {
+            id,
+            bookmark_id: bookmark.id,
+            created: bookmark.created,
+            quote: quote.to_owned(),
+            comment: None,
+            position: id,
+        };
+        let item = HighlightedBookmark {
+            highlights: vec![highlight(1, "first"), highlight(2, "second")],
+            bookmark: bookmark.clone(),
+        };
+
+        let doc = BookmarkDoc::from(item);
+        assert_eq!(doc.highlights, "first\n\nsecond");
+        assert_eq!(doc.into_bookmark_lossy(), bookmark);
+    }
+
+    #[test]
+    fn test_role_grants() {
+        use Permission::*;
+
+        assert!(Role::Viewer.grants(ViewPrivate));
+        assert!(!Role::Viewer.grants(EditBookmarks));
+        assert!(Role::Editor.grants(EditBookmarks));
+        assert!(!Role::Editor.grants(ManageTags));
+        assert!(Role::Admin.grants(ManageUsers));
+        assert!(!Role::Editor.grants(ManageIndex));
+        assert!(Role::Admin.grants(ManageIndex));
+
+        let role: Role = serde_json::from_str(r#""editor""#).unwrap();
+        assert_eq!(role, Role::Editor);
+        assert_eq!(role.as_str().parse(), Ok(Role::Editor));
+    }
 }
@@ -0,0 +1,482 @@
+use std::sync::RwLock;
+use std::time::Duration;
+
+use actix_web::client::Client;
+use futures::future::{self, Either, Future};
+use lazy_static::lazy_static;
+use log::*;
+use ring::constant_time::verify_slices_are_equal;
+use ring::digest;
+use serde_derive::Deserialize;
+use serde_json::{Map, Value};
+use url::Url;
+
+use crate::config::OidcConfig;
+use crate::db::OidcLogin;
+use crate::error::ServiceError;
+use crate::sessions::random_token;
+
+// Holds the state of a sign in, between leaving for the provider and
+// coming back to the callback
+pub const FLOW_COOKIE: &str = "oidc_flow";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref DISCOVERY: RwLock<Option<Discovery>> = RwLock::new(None);
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Discovery {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+}
+
+// How the admin ui starts a sign in
+#[derive(Debug, Deserialize)]
+pub struct LoginQuery {
+    // ends in a cookie session instead of tokens in the fragment
+    #[serde(default)]
+    pub cookie: bool,
+}
+
+// Where the provider sends users back to
+#[derive(Debug, Deserialize)]
+pub struct Callback {
+    pub code: String,
+    pub state: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+// Claims of the signed in user, as returned by the userinfo endpoint
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserInfo(Map<String, Value>);
+
+impl UserInfo {
+    // Only verified emails are trusted to match users, some providers
+    // give `email_verified` as a string
+    pub fn verified_email(&self) -> Option<&str> {
+        let verified = match self.0.get("email_verified") {
+            Some(Value::Bool(verified)) => *verified,
+            Some(Value::String(verified)) => verified == "true",
+            _ => false,
+        };
+        self.0
+            .get("email")
+            .and_then(Value::as_str)
+            .filter(|_| verified)
+    }
+
+    pub fn preferred_username(&self) -> Option<&str> {
+        self.0.get("preferred_username").and_then(Value::as_str)
+    }
+
+    // The claim is either a list of groups or a single one
+    pub fn in_group(&self, claim: &str, group: &str) -> bool {
+        match self.0.get(claim) {
+            Some(Value::Array(groups)) => {
+                groups.iter().any(|g| g.as_str() == Some(group))
+            }
+            Some(Value::String(g)) => g == group,
+            _ => false,
+        }
+    }
+}
+
+// The `state` and PKCE code verifier of a sign in, and whether it ends in a
+// cookie session
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flow {
+    pub state: String,
+    pub verifier: String,
+    pub cookie: bool,
+}
+
+impl Flow {
+    pub fn start(cookie: bool) -> Self {
+        Flow {
+            state: random_token(),
+            verifier: random_token(),
+            cookie,
+        }
+    }
+
+    pub fn to_cookie_value(&self) -> String {
+        if self.cookie {
+            format!("{}.{}.cookie", self.state, self.verifier)
+        } else {
+            format!("{}.{}", self.state, self.verifier)
+        }
+    }
+
+    pub fn from_cookie_value(value: &str) -> Option<Self> {
+        let mut parts = value.splitn(3, '.');
+        let state = parts.next().filter(|s| !s.is_empty())?;
+        let verifier = parts.next().filter(|s| !s.is_empty())?;
+        let cookie = match parts.next() {
+            None => false,
+            Some("cookie") => true,
+            Some(_) => return None,
+        };
+        Some(Flow {
+            state: state.to_owned(),
+            verifier: verifier.to_owned(),
+            cookie,
+        })
+    }
+
+    pub fn matches_state(&self, state: &str) -> bool {
+        verify_slices_are_equal(self.state.as_bytes(), state.as_bytes()).is_ok()
+    }
+
+    pub fn challenge(&self) -> String {
+        pkce_challenge(&self.verifier)
+    }
+}
+
+// S256 code challenge of a code verifier
+pub fn pkce_challenge(verifier: &str) -> String {
+    let hash = digest::digest(&digest::SHA256, verifier.as_bytes());
+    base64::encode_config(hash.as_ref(), base64::URL_SAFE_NO_PAD)
+}
+
+fn provider_error<E: std::fmt::Debug>(err: E) -> ServiceError {
+    error!("Identity provider error: {:?}", err);
+    ServiceError::InternalServerError
+}
+
+// Where users are sent to sign in
+pub fn authorize_url(
+    discovery: &Discovery,
+    config: &OidcConfig,
+    flow: &Flow,
+) -> Result<String, ServiceError> {
+    let challenge = flow.challenge();
+    let params = [
+        ("response_type", "code"),
+        ("client_id", config.client_id.as_str()),
+        ("redirect_uri", config.redirect_url.as_str()),
+        ("scope", config.scopes.as_str()),
+        ("state", flow.state.as_str()),
+        ("code_challenge", challenge.as_str()),
+        ("code_challenge_method", "S256"),
+    ];
+    Url::parse_with_params(&discovery.authorization_endpoint, &params[..])
+        .map(String::from)
+        .map_err(provider_error)
+}
+
+// Endpoints of the provider, fetched once
+pub fn discover(
+    client: &Client,
+    config: &OidcConfig,
+) -> impl Future<Item = Discovery, Error = ServiceError> {
+    if let Some(discovery) = DISCOVERY.read().ok().and_then(|d| d.clone()) {
+        return Either::A(future::ok(discovery));
+    }
+
+    let issuer = config.issuer.trim_end_matches('/').to_owned();
+    let url = format!("{}/.well-known/openid-configuration", issuer);
+    Either::B(
+        client
+            .get(url.as_str())
+            .timeout(TIMEOUT)
+            .send()
+            .map_err(provider_error)
+            .and_then(|mut resp| {
+                resp.json::<Discovery>().map_err(provider_error)
+            })
+            .and_then(move |discovery| {
+                if discovery.issuer.trim_end_matches('/') != issuer {
+                    return Err(provider_error(format!(
+                        "issuer {} doesn't match",
+                        discovery.issuer
+                    )));
+                }
+                if let Ok(mut cached) = DISCOVERY.write() {
+                    *cached = Some(discovery.clone());
+                }
+                Ok(discovery)
+            }),
+    )
+}
+
+// Trades the code the provider sent users back with for the claims of
+// the user. Tokens come straight from the provider, so the claims are
+// trusted without checking signatures.
+pub fn fetch_user_info(
+    client: &Client,
+    discovery: &Discovery,
+    config: &OidcConfig,
+    code: &str,
+    flow: &Flow,
+) -> impl Future<Item = UserInfo, Error = ServiceError> {
+    let mut params = vec![
+        ("grant_type", "authorization_code".to_owned()),
+        ("code", code.to_owned()),
+        ("redirect_uri", config.redirect_url.clone()),
+        ("client_id", config.client_id.clone()),
+        ("code_verifier", flow.verifier.clone()),
+    ];
+    if let Some(ref secret) = config.client_secret {
+        params.push(("client_secret", secret.clone()));
+    }
+
+    let client = client.clone();
+    let userinfo_endpoint = discovery.userinfo_endpoint.clone();
+    client
+        .post(discovery.token_endpoint.as_str())
+        .timeout(TIMEOUT)
+        .send_form(&params)
+        .map_err(provider_error)
+        .and_then(|mut resp| {
+            if !resp.status().is_success() {
+                // codes are single use and expire, as when retrying
+                warn!("Code rejected by identity provider: {}", resp.status());
+                return Either::A(future::err(ServiceError::Unauthorized));
+            }
+            Either::B(resp.json::<TokenResponse>().map_err(provider_error))
+        })
+        .and_then(move |token| {
+            client
+                .get(userinfo_endpoint.as_str())
+                .bearer_auth(token.access_token)
+                .timeout(TIMEOUT)
+                .send()
+                .map_err(provider_error)
+                .and_then(|mut resp| {
+                    resp.json::<UserInfo>().map_err(provider_error)
+                })
+        })
+}
+
+// Signs in the user the claims are of, when the email is verified
+pub fn login_of(
+    info: &UserInfo,
+    config: &OidcConfig,
+) -> Result<OidcLogin, ServiceError> {
+    let email = info
+        .verified_email()
+        .ok_or(ServiceError::Unauthorized)?
+        .trim()
+        .to_lowercase();
+    Ok(OidcLogin {
+        email,
+        name: info.preferred_username().map(String::from),
+        is_admin: config
+            .admin_group
+            .as_ref()
+            .map(|group| info.in_group(&config.groups_claim, group)),
+        provision: config.auto_provision,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_oidc::{routes, Provider};
+    use actix_web::http::header;
+    use actix_web::{web, App, HttpServer};
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    fn user_info(claims: Value) -> UserInfo {
+        serde_json::from_value(claims).unwrap()
+    }
+
+    fn oidc_config(issuer: &str) -> OidcConfig {
+        OidcConfig {
+            issuer: issuer.into(),
+            client_id: "reads".into(),
+            client_secret: None,
+            scopes: "openid email".into(),
+            redirect_url: "http://localhost:8080/api/auth/oidc/callback".into(),
+            ui_url: "/admin/".into(),
+            auto_provision: false,
+            admin_group: None,
+            groups_claim: "groups".into(),
+        }
+    }
+
+    // runs the identity provider of the mock-oidc binary
+    fn serve_mock_provider(email: &str, groups: &[&str]) -> String {
+        let (tx, rx) = mpsc::channel();
+        let email = email.to_owned();
+        let groups = groups.iter().map(|&g| g.to_owned()).collect();
+
+        thread::spawn(move || {
+            let sys = actix_rt::System::new("mock-oidc");
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let issuer = format!("http://{}", listener.local_addr().unwrap());
+            let provider =
+                web::Data::new(Provider::new(issuer.clone(), email, groups));
+            HttpServer::new(move || {
+                App::new().register_data(provider.clone()).configure(routes)
+            })
+            .listen(listener)
+            .unwrap()
+            .start();
+
+            tx.send(issuer).unwrap();
+            let _ = sys.run();
+        });
+
+        rx.recv().unwrap()
+    }
+
+    #[test]
+    fn test_pkce_challenge() {
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mJ0DAKcW7kZvRMk6Jp4TBxjdh9NiJk"),
+            "MsQ3-7I9MiF69V17G_zai8lSmFIAOtUQq3OHkBUvoFc"
+        );
+    }
+
+    #[test]
+    fn test_flow_cookie() {
+        let flow = Flow::start(false);
+        let value = flow.to_cookie_value();
+
+        assert_eq!(Flow::from_cookie_value(&value), Some(flow.clone()));
+        assert!(flow.matches_state(&flow.state));
+        assert!(!flow.matches_state(&flow.verifier));
+        assert_eq!(Flow::from_cookie_value("state"), None);
+        assert_eq!(Flow::from_cookie_value(".verifier"), None);
+        assert_eq!(Flow::from_cookie_value("state.verifier.other"), None);
+
+        let flow = Flow::start(true);
+        let value = flow.to_cookie_value();
+        assert_eq!(Flow::from_cookie_value(&value), Some(flow));
+    }
+
+    #[test]
+    fn test_user_info() {
+        let info = user_info(json!({
+            "email": "ann@example.com",
+            "email_verified": true,
+            "groups": ["staff", "admins"],
+        }));
+        assert_eq!(info.verified_email(), Some("ann@example.com"));
+        assert!(info.in_group("groups", "admins"));
+        assert!(!info.in_group("groups", "owners"));
+        assert!(!info.in_group("roles", "admins"));
+
+        let info = user_info(json!({
+            "email": "ann@example.com",
+            "email_verified": "true",
+            "role": "admins",
+        }));
+        assert_eq!(info.verified_email(), Some("ann@example.com"));
+        assert!(info.in_group("role", "admins"));
+
+        let info = user_info(json!({ "email": "ann@example.com" }));
+        assert_eq!(info.verified_email(), None);
+    }
+
+    #[test]
+    fn test_authorize_url() {
+        let discovery = Discovery {
+            issuer: "http://localhost:8090".into(),
+            authorization_endpoint: "http://localhost:8090/authorize".into(),
+            token_endpoint: "http://localhost:8090/token".into(),
+            userinfo_endpoint: "http://localhost:8090/userinfo".into(),
+        };
+        let config = oidc_config("http://localhost:8090");
+        let flow = Flow {
+            state: "s".into(),
+            verifier: "v".into(),
+            cookie: false,
+        };
+
+        let url =
+            Url::parse(&authorize_url(&discovery, &config, &flow).unwrap())
+                .unwrap();
+        let params: Map<String, Value> = url
+            .query_pairs()
+            .map(|(k, v)| (k.into_owned(), Value::String(v.into_owned())))
+            .collect();
+        assert_eq!(url.path(), "/authorize");
+        assert_eq!(
+            Value::Object(params),
+            json!({
+                "response_type": "code",
+                "client_id": "reads",
+                "redirect_uri": "http://localhost:8080/api/auth/oidc/callback",
+                "scope": "openid email",
+                "state": "s",
+                "code_challenge": pkce_challenge("v"),
+                "code_challenge_method": "S256",
+            })
+        );
+    }
+
+    #[test]
+    fn test_mock_provider_login() {
+        let issuer = serve_mock_provider("Ann@example.com", &["admins"]);
+        let config = OidcConfig {
+            auto_provision: true,
+            admin_group: Some("admins".into()),
+            ..oidc_config(&issuer)
+        };
+        let flow = Flow::start(false);
+        let mut sys = actix_rt::System::new("test");
+        let client = Client::default();
+
+        // the provider approves right away, sending back to the callback
+        let flow_state = flow.clone();
+        let callback = discover(&client, &config).and_then(|discovery| {
+            let url = authorize_url(&discovery, &config, &flow_state).unwrap();
+            client.get(url.as_str()).send().map_err(provider_error).map(
+                move |resp| {
+                    let location =
+                        resp.headers().get(header::LOCATION).cloned();
+                    (discovery, location)
+                },
+            )
+        });
+        let (discovery, location) = sys.block_on(callback).unwrap();
+
+        let location = location.unwrap();
+        let location = Url::parse(location.to_str().unwrap()).unwrap();
+        let params: HashMap<_, _> = location.query_pairs().collect();
+        assert!(location.as_str().starts_with(&config.redirect_url));
+        assert!(flow.matches_state(&params["state"]));
+
+        let info = sys
+            .block_on(fetch_user_info(
+                &client,
+                &discovery,
+                &config,
+                &params["code"],
+                &flow,
+            ))
+            .unwrap();
+        let login = login_of(&info, &config).unwrap();
+        assert_eq!(login.email, "ann@example.com");
+        assert_eq!(login.name.as_ref().map(String::as_str), Some("Ann"));
+        assert_eq!(login.is_admin, Some(true));
+        assert!(login.provision);
+
+        // codes are single use
+        let reused = sys.block_on(fetch_user_info(
+            &client,
+            &discovery,
+            &config,
+            &params["code"],
+            &flow,
+        ));
+        match reused {
+            Err(ServiceError::Unauthorized) => {}
+            other => panic!("code accepted twice: {:?}", other),
+        }
+    }
+}
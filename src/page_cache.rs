@@ -0,0 +1,89 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+
+// Rendered search pages, served in their place while search is down
+const CAPACITY: usize = 200;
+// Older pages might still list bookmarks made private or deleted since
+const MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+lazy_static! {
+    static ref PAGES: Mutex<PageCache> = Mutex::new(PageCache::new(CAPACITY));
+}
+
+// Keys must tell apart audiences, pages show them different bookmarks
+pub fn remember(key: String, body: String) {
+    PAGES.lock().unwrap().insert(key, body, Instant::now());
+}
+
+pub fn recall(key: &str) -> Option<String> {
+    PAGES.lock().unwrap().get(key, Instant::now())
+}
+
+// Pages by key, the least recently rendered dropped first
+#[derive(Debug)]
+pub struct PageCache {
+    capacity: usize,
+    pages: HashMap<String, (Instant, String)>,
+    order: VecDeque<String>,
+}
+
+impl PageCache {
+    pub fn new(capacity: usize) -> Self {
+        PageCache {
+            capacity,
+            pages: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    pub fn insert(&mut self, key: String, body: String, now: Instant) {
+        if self.pages.insert(key.clone(), (now, body)).is_some() {
+            self.order.retain(|k| *k != key);
+        }
+        self.order.push_back(key);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.pages.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn get(&self, key: &str, now: Instant) -> Option<String> {
+        self.pages
+            .get(key)
+            .filter(|(rendered, _)| now.duration_since(*rendered) < MAX_AGE)
+            .map(|(_, body)| body.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_oldest() {
+        let now = Instant::now();
+        let mut cache = PageCache::new(2);
+        cache.insert("a".into(), "1".into(), now);
+        cache.insert("b".into(), "2".into(), now);
+        cache.insert("a".into(), "3".into(), now);
+        cache.insert("c".into(), "4".into(), now);
+
+        assert_eq!(cache.get("a", now), Some("3".into()));
+        assert_eq!(cache.get("b", now), None);
+        assert_eq!(cache.get("c", now), Some("4".into()));
+    }
+
+    #[test]
+    fn test_expires() {
+        let now = Instant::now();
+        let mut cache = PageCache::new(2);
+        cache.insert("a".into(), "1".into(), now);
+
+        assert_eq!(cache.get("a", now + MAX_AGE / 2), Some("1".into()));
+        assert_eq!(cache.get("a", now + MAX_AGE), None);
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use scraper::{ElementRef, Html, Selector};
+
+// Paragraphs shorter than this are mostly captions, bylines and buttons,
+// they don't count towards picking the main content.
+const MIN_PARAGRAPH_LEN: usize = 25;
+
+const BLOCKS: &[&str] = &["p", "pre", "h2", "h3", "h4", "li", "blockquote"];
+const BOILERPLATE: &[&str] = &[
+    "nav", "header", "footer", "aside", "form", "script", "style", "noscript",
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Extracted {
+    pub title: Option<String>,
+    pub byline: Option<String>,
+    // paragraphs of plain text, separated by blank lines
+    pub text: String,
+}
+
+// Readability-style extraction of the article in a html page: the element
+// holding most of the paragraph text is taken as the main content.
+pub fn extract(html: &str) -> Extracted {
+    let doc = Html::parse_document(html);
+
+    Extracted {
+        title: find_title(&doc),
+        byline: find_byline(&doc),
+        text: main_content(&doc)
+            .map(|root| block_texts(root).join("\n\n"))
+            .unwrap_or_default(),
+    }
+}
+
+fn find_title(doc: &Html) -> Option<String> {
+    meta_content(doc, r#"meta[property="og:title"]"#)
+        .or_else(|| first_text(doc, "title"))
+        .or_else(|| first_text(doc, "h1"))
+}
+
+fn find_byline(doc: &Html) -> Option<String> {
+    meta_content(doc, r#"meta[name="author"]"#)
+        .or_else(|| first_text(doc, r#"[rel="author"]"#))
+        .or_else(|| first_text(doc, r#"[itemprop="author"]"#))
+        .or_else(|| first_text(doc, ".byline"))
+}
+
+fn main_content(doc: &Html) -> Option<ElementRef> {
+    let paragraphs = selector("p");
+    let mut scores = HashMap::new();
+
+    for p in doc.select(&paragraphs) {
+        if is_boilerplate(p) {
+            continue;
+        }
+        let len = normalized_text(p).len();
+        if len < MIN_PARAGRAPH_LEN {
+            continue;
+        }
+        if let Some(parent) = p.parent().and_then(ElementRef::wrap) {
+            *scores.entry(parent.id()).or_insert(0) += len;
+        }
+    }
+
+    scores
+        .into_iter()
+        .max_by_key(|&(_, score)| score)
+        .and_then(|(id, _)| doc.tree.get(id))
+        .and_then(ElementRef::wrap)
+}
+
+// Text of block elements under root, nested blocks (a <p> inside a <li>)
+// are only included once, as part of their outermost block.
+fn block_texts(root: ElementRef) -> Vec<String> {
+    let blocks = selector(&BLOCKS.join(", "));
+
+    root.select(&blocks)
+        .filter(|el| el.id() != root.id() && !is_boilerplate(*el))
+        .filter(|el| {
+            !el.ancestors()
+                .take_while(|node| node.id() != root.id())
+                .filter_map(ElementRef::wrap)
+                .any(|parent| BLOCKS.contains(&parent.value().name()))
+        })
+        .map(normalized_text)
+        .filter(|text| !text.is_empty())
+        .collect()
+}
+
+fn is_boilerplate(el: ElementRef) -> bool {
+    el.ancestors()
+        .filter_map(ElementRef::wrap)
+        .any(|parent| BOILERPLATE.contains(&parent.value().name()))
+}
+
+fn meta_content(doc: &Html, css: &str) -> Option<String> {
+    doc.select(&selector(css))
+        .filter_map(|el| el.value().attr("content"))
+        .map(|content| content.split_whitespace().join(" "))
+        .find(|content| !content.is_empty())
+}
+
+fn first_text(doc: &Html, css: &str) -> Option<String> {
+    doc.select(&selector(css))
+        .map(normalized_text)
+        .find(|text| !text.is_empty())
+}
+
+fn normalized_text(el: ElementRef) -> String {
+    el.text().flat_map(str::split_whitespace).join(" ")
+}
+
+fn selector(css: &str) -> Selector {
+    Selector::parse(css).expect("valid css selector")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_article() {
+        let html = r#"
+        <html>
+          <head>
+            <title>Fallback title</title>
+            <meta property="og:title" content="Why links rot">
+            <meta name="author" content="Jane  Doe">
+          </head>
+          <body>
+            <nav><p>Home | About | A very long list of navigation links</p></nav>
+            <div class="sidebar"><p>Subscribe!</p></div>
+            <div class="post">
+              <p>Links on the web disappear at a  steady rate.</p>
+              <ul><li><p>Servers go away, and domains expire.</p></li></ul>
+              <p>Keeping a copy is the only way to be sure.</p>
+            </div>
+            <footer><p>Copyright notice that is fairly long as well</p></footer>
+          </body>
+        </html>
+        "#;
+
+        assert_eq!(
+            extract(html),
+            Extracted {
+                title: Some("Why links rot".to_owned()),
+                byline: Some("Jane Doe".to_owned()),
+                text: "Links on the web disappear at a steady rate.\n\n\
+                       Servers go away, and domains expire.\n\n\
+                       Keeping a copy is the only way to be sure."
+                    .to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_extract_empty() {
+        let extracted = extract("<html><body><h1>Hi</h1></body></html>");
+
+        assert_eq!(extracted.title, Some("Hi".to_owned()));
+        assert_eq!(extracted.byline, None);
+        assert_eq!(extracted.text, "");
+    }
+}
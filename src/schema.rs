@@ -1,3 +1,27 @@
+table! {
+    api_tokens (id) {
+        id -> Int4,
+        user_id -> Int4,
+        name -> Varchar,
+        scope -> Varchar,
+        token_hash -> Varchar,
+        created -> Timestamp,
+        last_used -> Nullable<Timestamp>,
+        revoked -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    audit_log (id) {
+        id -> Int4,
+        created -> Timestamp,
+        actor -> Varchar,
+        action -> Varchar,
+        target_id -> Nullable<Int4>,
+        diff -> Jsonb,
+    }
+}
+
 table! {
     bookmarks (id) {
         id -> Int4,
@@ -7,6 +31,87 @@ table! {
         body -> Text,
         tags -> Jsonb,
         toshi_index -> Nullable<Text>,
+        status -> Varchar,
+        read_at -> Nullable<Timestamp>,
+        starred -> Bool,
+        visibility -> Varchar,
+        last_checked -> Nullable<Timestamp>,
+        last_status -> Nullable<Int4>,
+        final_url -> Nullable<Varchar>,
+        user_id -> Int4,
+    }
+}
+
+table! {
+    collection_items (collection_id, bookmark_id) {
+        collection_id -> Int4,
+        bookmark_id -> Int4,
+        position -> Int4,
+    }
+}
+
+table! {
+    collections (id) {
+        id -> Int4,
+        created -> Timestamp,
+        slug -> Varchar,
+        title -> Varchar,
+        intro -> Text,
+        user_id -> Int4,
+    }
+}
+
+table! {
+    highlights (id) {
+        id -> Int4,
+        bookmark_id -> Int4,
+        created -> Timestamp,
+        quote -> Text,
+        comment -> Nullable<Text>,
+        position -> Int4,
+    }
+}
+
+table! {
+    saved_searches (id) {
+        id -> Int4,
+        created -> Timestamp,
+        name -> Varchar,
+        slug -> Varchar,
+        query -> Text,
+    }
+}
+
+table! {
+    sessions (id) {
+        id -> Int4,
+        user_id -> Int4,
+        created -> Timestamp,
+        last_used -> Timestamp,
+        expires -> Timestamp,
+        refresh_hash -> Varchar,
+        revoked -> Nullable<Timestamp>,
+        user_agent -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    snapshots (id) {
+        id -> Int4,
+        bookmark_id -> Int4,
+        fetched -> Timestamp,
+        http_status -> Nullable<Int4>,
+        final_url -> Varchar,
+        title -> Nullable<Varchar>,
+        byline -> Nullable<Varchar>,
+        content -> Text,
+    }
+}
+
+table! {
+    user_roles (user_id, role) {
+        user_id -> Int4,
+        role -> Varchar,
     }
 }
 
@@ -16,11 +121,31 @@ table! {
         created -> Timestamp,
         email -> Varchar,
         password -> Varchar,
-        is_admin -> Bool,
+        name -> Varchar,
+        active -> Bool,
     }
 }
 
+joinable!(api_tokens -> users (user_id));
+joinable!(bookmarks -> users (user_id));
+joinable!(collection_items -> bookmarks (bookmark_id));
+joinable!(collection_items -> collections (collection_id));
+joinable!(collections -> users (user_id));
+joinable!(highlights -> bookmarks (bookmark_id));
+joinable!(sessions -> users (user_id));
+joinable!(snapshots -> bookmarks (bookmark_id));
+joinable!(user_roles -> users (user_id));
+
 allow_tables_to_appear_in_same_query!(
+    api_tokens,
+    audit_log,
     bookmarks,
+    collection_items,
+    collections,
+    highlights,
+    saved_searches,
+    sessions,
+    snapshots,
+    user_roles,
     users,
 );
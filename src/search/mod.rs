@@ -1,28 +1,37 @@
+use std::time::Duration;
+
 use actix_web::client::Client;
 use actix_web::http::{header::CONTENT_TYPE, uri, StatusCode};
 use actix_web::Error;
-use futures::future::Future;
+use futures::future::{self, Either, Future};
 use serde_derive::*;
 
 mod index;
 mod query;
 mod query_parser;
+mod related;
 
 pub use self::query::Query;
+use self::query::{BoolQueryBuilder, ExactQueryBuilder};
 pub use self::query_parser::QueryParser;
+pub use self::related::more_like_this;
 use crate::config::CONFIG;
 use crate::error::ServiceError;
-use crate::models::{BookmarkDoc, SearchResults};
+use crate::models::{Audience, BookmarkDoc, SearchResults, Visibility};
+
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
 
 #[derive(Deserialize)]
 pub struct Search {
     pub q: String,
 }
 
+#[derive(Clone)]
 pub struct SearchClient {
     rest_client: Client,
     insert_doc_endpoint: uri::Uri,
     query_doc_endpoint: uri::Uri,
+    summary_endpoint: uri::Uri,
 }
 
 impl SearchClient {
@@ -31,6 +40,7 @@ impl SearchClient {
             rest_client: Client::default(),
             insert_doc_endpoint: insert_doc_endpoint(),
             query_doc_endpoint: query_doc_endpoint(),
+            summary_endpoint: summary_endpoint(),
         };
         log::info!("Created toshi client");
         log::info!("  toshi: {}", client.insert_doc_endpoint);
@@ -42,31 +52,36 @@ impl SearchClient {
     pub fn insert_doc(
         &self,
         doc: BookmarkDoc,
+    ) -> impl Future<Item = Result<(), ServiceError>, Error = Error> {
+        send_insert(&self.rest_client, &self.insert_doc_endpoint, doc)
+    }
+
+    pub fn delete_doc(
+        &self,
+        id: i32,
     ) -> impl Future<Item = Result<(), ServiceError>, Error = Error> {
         #[derive(Serialize)]
-        struct InsertPayload<D> {
-            options: InsertOptions,
-            document: D,
+        struct DeletePayload {
+            options: IndexOptions,
+            terms: DocKey,
         }
         #[derive(Serialize)]
-        struct InsertOptions {
-            commit: bool,
-        }
-        impl<D> InsertPayload<D> {
-            fn new(document: D) -> Self {
-                Self {
-                    options: InsertOptions { commit: true },
-                    document,
-                }
-            }
+        struct DocKey {
+            key: String,
         }
+
         self.rest_client
-            .put(&self.insert_doc_endpoint)
+            .delete(&self.insert_doc_endpoint)
             .header(CONTENT_TYPE, "application/json")
-            .send_json(&InsertPayload::new(doc))
+            .send_json(&DeletePayload {
+                options: IndexOptions { commit: true },
+                terms: DocKey {
+                    key: id.to_string(),
+                },
+            })
             .from_err()
             .map(|resp| {
-                if resp.status() == StatusCode::CREATED {
+                if resp.status().is_success() {
                     Ok(())
                 } else {
                     Err(ServiceError::InternalServerError)
@@ -74,9 +89,33 @@ impl SearchClient {
             })
     }
 
+    // Toshi has no update, an existing doc for the same bookmark is
+    // deleted before the new one is inserted.
+    pub fn replace_doc(
+        &self,
+        doc: BookmarkDoc,
+    ) -> impl Future<Item = Result<(), ServiceError>, Error = Error> {
+        let client = self.rest_client.clone();
+        let endpoint = self.insert_doc_endpoint.clone();
+
+        self.delete_doc(doc.id)
+            .and_then(move |deleted| match deleted {
+                Ok(_) => Either::A(send_insert(&client, &endpoint, doc)),
+                Err(err) => Either::B(future::ok(Err(err))),
+            })
+    }
+
     pub fn query_docs(
         &self,
         q: Query,
+    ) -> impl Future<Item = SearchResults, Error = Error> {
+        self.query_docs_with_limit(q, CONFIG.search_limit)
+    }
+
+    pub fn query_docs_with_limit(
+        &self,
+        q: Query,
+        limit: u32,
     ) -> impl Future<Item = SearchResults, Error = Error> {
         #[derive(Serialize)]
         struct QueryPayload<Q> {
@@ -87,10 +126,7 @@ impl SearchClient {
         self.rest_client
             .post(&self.query_doc_endpoint)
             .header(CONTENT_TYPE, "application/json")
-            .send_json(&QueryPayload {
-                query: q,
-                limit: 25,
-            })
+            .send_json(&QueryPayload { query: q, limit })
             .from_err()
             .and_then(|mut resp| {
                 resp.body().from_err().and_then(|body| {
@@ -102,6 +138,103 @@ impl SearchClient {
                 })
             })
     }
+
+    // Whether the index answers, for readiness checks
+    pub fn ping(&self) -> impl Future<Item = (), Error = Error> {
+        self.rest_client
+            .get(&self.summary_endpoint)
+            .timeout(PING_TIMEOUT)
+            .send()
+            .from_err()
+            .and_then(|resp| {
+                if resp.status().is_success() {
+                    Ok(())
+                } else {
+                    Err(ServiceError::InternalServerError.into())
+                }
+            })
+    }
+}
+
+#[derive(Serialize)]
+struct IndexOptions {
+    commit: bool,
+}
+
+fn send_insert(
+    client: &Client,
+    endpoint: &uri::Uri,
+    doc: BookmarkDoc,
+) -> impl Future<Item = Result<(), ServiceError>, Error = Error> {
+    #[derive(Serialize)]
+    struct InsertPayload<D> {
+        options: IndexOptions,
+        document: KeyedDoc<D>,
+    }
+    // `key` is an indexed copy of the bookmark id, which allows deleting
+    // docs by term, `owner` likewise for the user id
+    #[derive(Serialize)]
+    struct KeyedDoc<D> {
+        key: String,
+        owner: String,
+        #[serde(flatten)]
+        doc: D,
+    }
+    impl InsertPayload<BookmarkDoc> {
+        fn new(doc: BookmarkDoc) -> Self {
+            Self {
+                options: IndexOptions { commit: true },
+                document: KeyedDoc {
+                    key: doc.id.to_string(),
+                    owner: doc.user_id.to_string(),
+                    doc,
+                },
+            }
+        }
+    }
+    client
+        .put(endpoint)
+        .header(CONTENT_TYPE, "application/json")
+        .send_json(&InsertPayload::new(doc))
+        .from_err()
+        .map(|resp| {
+            if resp.status() == StatusCode::CREATED {
+                Ok(())
+            } else {
+                Err(ServiceError::InternalServerError)
+            }
+        })
+}
+
+// Restricts a parsed query to bookmarks the audience is allowed to see.
+pub fn visible_to(query: Query, audience: Audience) -> Query {
+    let private = ExactQueryBuilder::new()
+        .with_field("visibility".to_owned())
+        .with_term(Visibility::Private.as_str().to_owned())
+        .build();
+
+    match audience {
+        Audience::Private => query,
+        Audience::Anonymous => BoolQueryBuilder::new()
+            .must(query)
+            .must_not(private)
+            .build(),
+    }
+}
+
+// Restricts a query to the bookmarks of a single user.
+pub fn owned_by(query: Query, user_id: i32) -> Query {
+    BoolQueryBuilder::new()
+        .must(query)
+        .must(owner_term(user_id))
+        .build()
+}
+
+fn owner_term(user_id: i32) -> Query {
+    ExactQueryBuilder::new()
+        .with_field("owner".to_owned())
+        .with_term(user_id.to_string())
+        .build()
 }
 
 fn insert_doc_endpoint() -> uri::Uri {
@@ -125,3 +258,14 @@ fn query_doc_endpoint() -> uri::Uri {
         .build()
         .expect("Invalid endpoint")
 }
+
+fn summary_endpoint() -> uri::Uri {
+    let summary_path = format!("/{}/_summary", CONFIG.toshi_index);
+
+    uri::Builder::new()
+        .scheme("http")
+        .authority(CONFIG.toshi_url.as_str().trim())
+        .path_and_query(summary_path.as_str().trim())
+        .build()
+        .expect("Invalid endpoint")
+}
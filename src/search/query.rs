@@ -152,6 +152,28 @@ impl BoolQueryBuilder {
             },
         }
     }
+    // Like `build`, but every filter must match as well; `should` clauses
+    // are nested so that they remain required rather than becoming
+    // optional next to the filters.
+    pub fn build_with_filters<I>(self, filters: I) -> Query
+    where
+        I: IntoIterator<Item = Query>,
+    {
+        let mut outer = BoolQueryBuilder::new();
+        outer.__must_not = self.__must_not;
+        if !self.__must.is_empty() || !self.__should.is_empty() {
+            let inner = BoolQueryBuilder {
+                __must: self.__must,
+                __must_not: vec![],
+                __should: self.__should,
+                __minimum_should_match: self.__minimum_should_match,
+                __boost: self.__boost,
+            };
+            outer.__must.push(inner.build());
+        }
+        outer.__must.extend(filters);
+        outer.build()
+    }
     pub fn must(mut self, q: Query) -> Self {
         self.__must.push(q);
         self
@@ -286,6 +308,47 @@ mod tests {
     use super::*;
     use serde_json::{json, Value};
 
+    #[test]
+    fn test_ser_filtered() {
+        let term = |field: &str, value: &str| {
+            ExactQueryBuilder::new()
+                .with_field(field.to_owned())
+                .with_term(value.to_owned())
+                .build()
+        };
+        let q = BoolQueryBuilder::new()
+            .should(term("body", "rust"))
+            .must_not(term("tags", "beginner"))
+            .build_with_filters(vec![term("status", "unread")]);
+
+        let s = serde_json::to_string(&q).unwrap();
+        let js_value: Value = serde_json::from_str(s.as_ref()).unwrap();
+
+        assert_eq!(
+            js_value,
+            json!({
+                "bool": {
+                    "must": [
+                        {
+                            "bool": {
+                                "must": [],
+                                "must_not": [],
+                                "should": [{ "term": { "body": "rust" } }],
+                                "minimum_should_match": null,
+                                "boost": null
+                            }
+                        },
+                        { "term": { "status": "unread" } }
+                    ],
+                    "must_not": [{ "term": { "tags": "beginner" } }],
+                    "should": [],
+                    "minimum_should_match": null,
+                    "boost": null
+                }
+            })
+        );
+    }
+
     #[test]
     fn test_ser_fuzzy() {
         let q = FuzzyQueryBuilder::new()
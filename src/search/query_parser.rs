@@ -18,6 +18,12 @@ enum Token {
     #[token = "tag:"]
     Tag,
 
+    #[token = "tag:="]
+    TagExact,
+
+    #[token = "status:"]
+    Status,
+
     #[token = "'"]
     QuoteSingle,
 
@@ -30,16 +36,23 @@ enum Token {
 
 pub struct QueryParser<'a> {
     lexer: Lexer<Token, &'a str>,
+    filters: Vec<Query>,
 }
 
 impl<'a> QueryParser<'a> {
     pub fn new(query_str: &'a str) -> Self {
         Self {
             lexer: Token::lexer(query_str),
+            filters: vec![],
         }
     }
     pub fn parse(mut self) -> Query {
-        self.parse_items(BoolQueryBuilder::new()).build()
+        let builder = self.parse_items(BoolQueryBuilder::new());
+        if self.filters.is_empty() {
+            builder.build()
+        } else {
+            builder.build_with_filters(self.filters)
+        }
     }
 }
 
@@ -51,8 +64,10 @@ enum OneOrMore<T> {
 }
 
 // queries       = query +
-// query         = match | phrase | Inverse match | Inverse phrase
+// query         = match | phrase | Inverse match | Inverse phrase | filter
 // match         = Word | exact | (Tag ":" Word) | (Tag ":" exact)
+//                 | (Tag ":=" Word) | (Tag ":=" exact)
+// filter        = Status ":" Word
 // exact         = <quote> Word <quote>
 // phrase        = <quote> Word {2,*} <quote>
 impl<'a> QueryParser<'a> {
@@ -80,7 +95,8 @@ impl<'a> QueryParser<'a> {
         builder: BoolQueryBuilder,
     ) -> Result<BoolQueryBuilder, BoolQueryBuilder> {
         match self.lexer.token {
-            Token::Tag => self.tag_item(false, builder),
+            Token::Tag | Token::TagExact => self.tag_item(false, builder),
+            Token::Status => self.status_item(false, builder),
             Token::Word | Token::QuoteSingle | Token::QuoteDouble => {
                 self.match_item(false, builder)
             }
@@ -99,7 +115,8 @@ impl<'a> QueryParser<'a> {
             return Err(builder);
         }
         match self.lexer.token {
-            Token::Tag => self.tag_item(true, builder),
+            Token::Tag | Token::TagExact => self.tag_item(true, builder),
+            Token::Status => self.status_item(true, builder),
             Token::Word | Token::QuoteSingle | Token::QuoteDouble => {
                 self.match_item(true, builder)
             }
@@ -126,6 +143,18 @@ impl<'a> QueryParser<'a> {
                                 .with_term(self.lexer.slice().to_owned())
                                 .build(),
                         )
+                        .must_not(
+                            ExactQueryBuilder::new()
+                                .with_field("highlights".to_owned())
+                                .with_term(self.lexer.slice().to_owned())
+                                .build(),
+                        )
+                        .must_not(
+                            ExactQueryBuilder::new()
+                                .with_field("archive".to_owned())
+                                .with_term(self.lexer.slice().to_owned())
+                                .build(),
+                        )
                         .must_not(
                             ExactQueryBuilder::new()
                                 .with_field("title".to_owned())
@@ -152,6 +181,18 @@ impl<'a> QueryParser<'a> {
                                 .with_term(self.lexer.slice().to_owned())
                                 .build(),
                         )
+                        .should(
+                            FuzzyQueryBuilder::new()
+                                .with_field("highlights".to_owned())
+                                .with_term(self.lexer.slice().to_owned())
+                                .build(),
+                        )
+                        .should(
+                            FuzzyQueryBuilder::new()
+                                .with_field("archive".to_owned())
+                                .with_term(self.lexer.slice().to_owned())
+                                .build(),
+                        )
                         .should(
                             ExactQueryBuilder::new()
                                 .with_field("tags".to_owned())
@@ -178,6 +219,18 @@ impl<'a> QueryParser<'a> {
                                         .with_term(term.to_string())
                                         .build(),
                                 )
+                                .must_not(
+                                    ExactQueryBuilder::new()
+                                        .with_field("highlights".to_owned())
+                                        .with_term(term.to_string())
+                                        .build(),
+                                )
+                                .must_not(
+                                    ExactQueryBuilder::new()
+                                        .with_field("archive".to_owned())
+                                        .with_term(term.to_string())
+                                        .build(),
+                                )
                                 .must_not(
                                     ExactQueryBuilder::new()
                                         .with_field("title".to_owned())
@@ -198,6 +251,18 @@ impl<'a> QueryParser<'a> {
                                         .with_term(term.to_string())
                                         .build(),
                                 )
+                                .should(
+                                    ExactQueryBuilder::new()
+                                        .with_field("highlights".to_owned())
+                                        .with_term(term.to_string())
+                                        .build(),
+                                )
+                                .should(
+                                    ExactQueryBuilder::new()
+                                        .with_field("archive".to_owned())
+                                        .with_term(term.to_string())
+                                        .build(),
+                                )
                                 .should(
                                     ExactQueryBuilder::new()
                                         .with_field("title".to_owned())
@@ -234,6 +299,26 @@ impl<'a> QueryParser<'a> {
                                                 .map(|t| t.to_string()),
                                         )
                                         .build(),
+                                )
+                                .must_not(
+                                    PhraseQueryBuilder::new()
+                                        .with_field("highlights".to_owned())
+                                        .with_terms(
+                                            terms
+                                                .into_iter()
+                                                .map(|t| t.to_string()),
+                                        )
+                                        .build(),
+                                )
+                                .must_not(
+                                    PhraseQueryBuilder::new()
+                                        .with_field("archive".to_owned())
+                                        .with_terms(
+                                            terms
+                                                .into_iter()
+                                                .map(|t| t.to_string()),
+                                        )
+                                        .build(),
                                 );
                         } else {
                             builder = builder
@@ -256,6 +341,26 @@ impl<'a> QueryParser<'a> {
                                                 .map(|t| t.to_string()),
                                         )
                                         .build(),
+                                )
+                                .should(
+                                    PhraseQueryBuilder::new()
+                                        .with_field("highlights".to_owned())
+                                        .with_terms(
+                                            terms
+                                                .into_iter()
+                                                .map(|t| t.to_string()),
+                                        )
+                                        .build(),
+                                )
+                                .should(
+                                    PhraseQueryBuilder::new()
+                                        .with_field("archive".to_owned())
+                                        .with_terms(
+                                            terms
+                                                .into_iter()
+                                                .map(|t| t.to_string()),
+                                        )
+                                        .build(),
                                 );
                         }
                     }
@@ -267,63 +372,73 @@ impl<'a> QueryParser<'a> {
 
         Ok(builder)
     }
+    // `tag:lang` matches "lang" and its children such as "lang/rust",
+    // `tag:=lang` only matches bookmarks tagged "lang" itself.
     fn tag_item(
         &mut self,
         inverse: bool,
         mut builder: BoolQueryBuilder,
     ) -> Result<BoolQueryBuilder, BoolQueryBuilder> {
-        if self.assert_token(Token::Tag).is_err() {
-            return Err(builder);
-        }
+        let field = match self.lexer.token {
+            Token::Tag => "tag_paths",
+            Token::TagExact => "tags",
+            _ => return Err(builder),
+        };
+        self.lexer.advance();
 
-        match self.lexer.token {
+        let tag = match self.lexer.token {
             Token::Word => {
-                if inverse {
-                    builder = builder.must_not(
-                        ExactQueryBuilder::new()
-                            .with_field("tags".to_owned())
-                            .with_term(normalize_tag(self.lexer.slice()))
-                            .build(),
-                    )
-                } else {
-                    builder = builder.should(
-                        ExactQueryBuilder::new()
-                            .with_field("tags".to_owned())
-                            .with_term(normalize_tag(self.lexer.slice()))
-                            .build(),
-                    );
-                }
+                let tag = self.lexer.slice();
                 self.lexer.advance();
+                tag
             }
             Token::QuoteSingle | Token::QuoteDouble => {
                 let quote_token = self.lexer.token;
                 self.lexer.advance();
-                let terms = match self.inside_quote(quote_token) {
-                    Ok(terms) => terms,
+                match self.inside_quote(quote_token) {
+                    Ok(OneOrMore::One(tag)) => tag,
+                    Ok(_) => return Ok(builder),
                     Err(_) => return Err(builder),
-                };
-                match terms {
-                    OneOrMore::One(term) => {
-                        if inverse {
-                            builder = builder.must_not(
-                                ExactQueryBuilder::new()
-                                    .with_field("tags".to_owned())
-                                    .with_term(normalize_tag(term))
-                                    .build(),
-                            )
-                        } else {
-                            builder = builder.should(
-                                ExactQueryBuilder::new()
-                                    .with_field("tags".to_owned())
-                                    .with_term(normalize_tag(term))
-                                    .build(),
-                            );
-                        }
-                    }
-                    _ => {}
                 }
             }
             _ => return Err(builder),
+        };
+        let query = ExactQueryBuilder::new()
+            .with_field(field.to_owned())
+            .with_term(normalize_tag(tag))
+            .build();
+        if inverse {
+            builder = builder.must_not(query);
+        } else {
+            builder = builder.must(query);
+        }
+
+        Ok(builder)
+    }
+
+    fn status_item(
+        &mut self,
+        inverse: bool,
+        mut builder: BoolQueryBuilder,
+    ) -> Result<BoolQueryBuilder, BoolQueryBuilder> {
+        if self.assert_token(Token::Status).is_err() {
+            return Err(builder);
+        }
+
+        match self.lexer.token {
+            Token::Word => {
+                let query = ExactQueryBuilder::new()
+                    .with_field("status".to_owned())
+                    .with_term(self.lexer.slice().to_lowercase())
+                    .build();
+                if inverse {
+                    builder = builder.must_not(query);
+                } else {
+                    self.filters.push(query);
+                }
+                self.lexer.advance();
+            }
+            _ => return Err(builder),
         }
 
         Ok(builder)
@@ -344,6 +459,14 @@ impl<'a> QueryParser<'a> {
                     terms.push("tag:");
                     self.lexer.advance();
                 }
+                Token::TagExact => {
+                    terms.push("tag:=");
+                    self.lexer.advance();
+                }
+                Token::Status => {
+                    terms.push("status:");
+                    self.lexer.advance();
+                }
                 Token::Word => {
                     terms.push(self.lexer.slice());
                     self.lexer.advance();
@@ -414,6 +537,24 @@ mod tests {
                       }
                     }
                   },
+                  {
+                    "fuzzy": {
+                      "highlights": {
+                        "value": "hello",
+                        "distance": 0,
+                        "transposition": false
+                      }
+                    }
+                  },
+                  {
+                    "fuzzy": {
+                      "archive": {
+                        "value": "hello",
+                        "distance": 0,
+                        "transposition": false
+                      }
+                    }
+                  },
                   {
                     "term": {
                       "tags": "hello"
@@ -462,6 +603,24 @@ mod tests {
                       }
                     }
                   },
+                  {
+                    "fuzzy": {
+                      "highlights": {
+                        "value": "hello",
+                        "distance": 0,
+                        "transposition": false
+                      }
+                    }
+                  },
+                  {
+                    "fuzzy": {
+                      "archive": {
+                        "value": "hello",
+                        "distance": 0,
+                        "transposition": false
+                      }
+                    }
+                  },
                   {
                     "term": {
                       "tags": "hello"
@@ -485,6 +644,24 @@ mod tests {
                       }
                     }
                   },
+                  {
+                    "fuzzy": {
+                      "highlights": {
+                        "value": "world",
+                        "distance": 0,
+                        "transposition": false
+                      }
+                    }
+                  },
+                  {
+                    "fuzzy": {
+                      "archive": {
+                        "value": "world",
+                        "distance": 0,
+                        "transposition": false
+                      }
+                    }
+                  },
                   {
                     "term": {
                       "tags": "world"
@@ -508,6 +685,24 @@ mod tests {
                       }
                     }
                   },
+                  {
+                    "fuzzy": {
+                      "highlights": {
+                        "value": "nice",
+                        "distance": 0,
+                        "transposition": false
+                      }
+                    }
+                  },
+                  {
+                    "fuzzy": {
+                      "archive": {
+                        "value": "nice",
+                        "distance": 0,
+                        "transposition": false
+                      }
+                    }
+                  },
                   {
                     "term": {
                       "tags": "nice"
@@ -557,6 +752,26 @@ mod tests {
                         ]
                       }
                     }
+                  },
+                  {
+                    "phrase": {
+                      "highlights": {
+                        "terms": [
+                          "two",
+                          "words"
+                        ]
+                      }
+                    }
+                  },
+                  {
+                    "phrase": {
+                      "archive": {
+                        "terms": [
+                          "two",
+                          "words"
+                        ]
+                      }
+                    }
                   }
                 ],
                 "minimum_should_match": null,
@@ -583,14 +798,14 @@ mod tests {
                 "must": [
                   {
                     "term": {
-                      "tags": "github.com"
+                      "tag_paths": "github.com"
                     }
                   }
                 ],
                 "must_not": [
                   {
                     "term": {
-                      "tags": "google.com"
+                      "tag_paths": "google.com"
                     }
                   }
                 ],
@@ -602,6 +817,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_hierarchical_tags() {
+        let raw = r#"tag:lang tag:=lang/rust not:tag:="lang/go""#;
+        let q = QueryParser::new(raw).parse();
+
+        let js_value: Value = serde_json::to_value(&q).unwrap();
+
+        assert_eq!(
+            js_value,
+            json!({
+              "bool": {
+                "must": [
+                  { "term": { "tag_paths": "lang" } },
+                  { "term": { "tags": "lang/rust" } }
+                ],
+                "must_not": [
+                  { "term": { "tags": "lang/go" } }
+                ],
+                "should": [],
+                "minimum_should_match": null,
+                "boost": null
+              }
+            })
+        );
+    }
+
+    #[test]
+    fn test_tags_verbatim() {
+        let raw = r#"tag:C++ not:tag:=C#"#;
+        let q = QueryParser::new(raw).parse();
+
+        let js_value: Value = serde_json::to_value(&q).unwrap();
+
+        assert_eq!(
+            js_value,
+            json!({
+              "bool": {
+                "must": [
+                  { "term": { "tag_paths": "c++" } }
+                ],
+                "must_not": [
+                  { "term": { "tags": "c#" } }
+                ],
+                "should": [],
+                "minimum_should_match": null,
+                "boost": null
+              }
+            })
+        );
+    }
+
     #[test]
     fn test_drops_tag_phrase() {
         let raw = r#"tag:"what up" inf"#;
@@ -637,6 +903,24 @@ mod tests {
                       }
                     }
                   },
+                  {
+                    "fuzzy": {
+                      "highlights": {
+                        "value": "inf",
+                        "distance": 0,
+                        "transposition": false
+                      }
+                    }
+                  },
+                  {
+                    "fuzzy": {
+                      "archive": {
+                        "value": "inf",
+                        "distance": 0,
+                        "transposition": false
+                      }
+                    }
+                  },
                   {
                     "term": {
                       "tags": "inf"
@@ -650,6 +934,93 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_status_filter() {
+        let raw = r#"status:Unread rust not:status:archived"#;
+        let q = QueryParser::new(raw).parse();
+
+        let s = serde_json::to_string_pretty(&q).unwrap();
+        let js_value: Value = serde_json::from_str(s.as_ref()).unwrap();
+
+        eprintln!("{}", s);
+
+        assert_eq!(
+            js_value,
+            json!({
+              "bool": {
+                "must": [
+                  {
+                    "bool": {
+                      "must": [],
+                      "must_not": [],
+                      "should": [
+                        {
+                          "fuzzy": {
+                            "title": {
+                              "value": "rust",
+                              "distance": 0,
+                              "transposition": false
+                            }
+                          }
+                        },
+                        {
+                          "fuzzy": {
+                            "body": {
+                              "value": "rust",
+                              "distance": 0,
+                              "transposition": false
+                            }
+                          }
+                        },
+                        {
+                          "fuzzy": {
+                            "highlights": {
+                              "value": "rust",
+                              "distance": 0,
+                              "transposition": false
+                            }
+                          }
+                        },
+                        {
+                          "fuzzy": {
+                            "archive": {
+                              "value": "rust",
+                              "distance": 0,
+                              "transposition": false
+                            }
+                          }
+                        },
+                        {
+                          "term": {
+                            "tags": "rust"
+                          }
+                        }
+                      ],
+                      "minimum_should_match": null,
+                      "boost": null
+                    }
+                  },
+                  {
+                    "term": {
+                      "status": "unread"
+                    }
+                  }
+                ],
+                "must_not": [
+                  {
+                    "term": {
+                      "status": "archived"
+                    }
+                  }
+                ],
+                "should": [],
+                "minimum_should_match": null,
+                "boost": null
+              }
+            })
+        );
+    }
+
     #[test]
     fn test_mixing_up() {
         let raw = r#"not:'alright not: tag:t' tag:foo why"#;
@@ -667,7 +1038,7 @@ mod tests {
                 "must": [
                   {
                     "term": {
-                      "tags": "foo"
+                      "tag_paths": "foo"
                     }
                   }
                 ],
@@ -695,6 +1066,30 @@ mod tests {
                         ]
                       }
                     }
+                  },
+                  {
+                    "phrase": {
+                      "highlights": {
+                        "terms": [
+                          "alright",
+                          "not:",
+                          "tag:",
+                          "t"
+                        ]
+                      }
+                    }
+                  },
+                  {
+                    "phrase": {
+                      "archive": {
+                        "terms": [
+                          "alright",
+                          "not:",
+                          "tag:",
+                          "t"
+                        ]
+                      }
+                    }
                   }
                 ],
                 "should": [
@@ -716,6 +1111,24 @@ mod tests {
                       }
                     }
                   },
+                  {
+                    "fuzzy": {
+                      "highlights": {
+                        "value": "why",
+                        "distance": 0,
+                        "transposition": false
+                      }
+                    }
+                  },
+                  {
+                    "fuzzy": {
+                      "archive": {
+                        "value": "why",
+                        "distance": 0,
+                        "transposition": false
+                      }
+                    }
+                  },
                   {
                     "term": {
                       "tags": "why"
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use super::query::{BoolQueryBuilder, ExactQueryBuilder, Query};
+use crate::models::Bookmark;
+
+// Number of distinctive words of a bookmark that are searched for
+const MAX_TERMS: usize = 12;
+// A word in the title counts as much as this many in the body
+const TITLE_WEIGHT: usize = 3;
+const MIN_TERM_LEN: usize = 3;
+
+const STOP_WORDS: &[&str] = &[
+    "about", "after", "all", "also", "and", "any", "are", "because", "been",
+    "before", "being", "but", "can", "could", "did", "does", "each", "for",
+    "from", "had", "has", "have", "her", "his", "how", "into", "its", "just",
+    "like", "more", "most", "not", "now", "one", "only", "other", "our", "out",
+    "over", "own", "same", "she", "should", "some", "such", "than", "that",
+    "the", "their", "them", "then", "there", "these", "they", "this", "those",
+    "through", "too", "very", "was", "way", "were", "what", "when", "where",
+    "which", "while", "who", "why", "will", "with", "would", "you", "your",
+];
+
+// "More like this" query for a bookmark: other bookmarks sharing its tags,
+// or the words that stand out in its title and body. Shared tags weigh
+// more than shared words. Returns `None` when there is nothing to match on.
+pub fn more_like_this(bookmark: &Bookmark) -> Option<Query> {
+    let term = |field: &str, value: String| {
+        ExactQueryBuilder::new()
+            .with_field(field.to_owned())
+            .with_term(value)
+            .build()
+    };
+
+    let tags = bookmark
+        .tags
+        .iter()
+        .map(|tag| term("tags", tag.to_owned()))
+        .fold(BoolQueryBuilder::new().boost(2.0), |q, tag| q.should(tag))
+        .build();
+    let words = top_terms(bookmark, MAX_TERMS)
+        .into_iter()
+        .flat_map(|word| vec![term("title", word.clone()), term("body", word)])
+        .fold(BoolQueryBuilder::new(), |q, word| q.should(word))
+        .build();
+
+    let similar = vec![tags, words]
+        .into_iter()
+        .filter(|q| !q.is_empty())
+        .fold(BoolQueryBuilder::new(), |q, group| q.should(group));
+    let similar = similar.minimum_should_match(1).build();
+    if similar.is_empty() {
+        return None;
+    }
+
+    Some(
+        BoolQueryBuilder::new()
+            .must(similar)
+            .must_not(term("key", bookmark.id.to_string()))
+            .build(),
+    )
+}
+
+// Most frequent words of the title and body, ties broken alphabetically
+// so that the same bookmark always gives the same query.
+fn top_terms(bookmark: &Bookmark, n: usize) -> Vec<String> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for word in words(&bookmark.title) {
+        *counts.entry(word).or_insert(0) += TITLE_WEIGHT;
+    }
+    for word in words(&bookmark.body) {
+        *counts.entry(word).or_insert(0) += 1;
+    }
+
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|(a, x), (b, y)| y.cmp(x).then_with(|| a.cmp(b)));
+    counts.into_iter().take(n).map(|(word, _)| word).collect()
+}
+
+// Splits the way the default tantivy tokenizer does, so that the words
+// are terms found in the index.
+fn words<'a>(text: &'a str) -> impl Iterator<Item = String> + 'a {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= MIN_TERM_LEN)
+        .filter(|word| !word.chars().all(|c| c.is_numeric()))
+        .map(str::to_lowercase)
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn bookmark(title: &str, body: &str, tags: &[&str]) -> Bookmark {
+        serde_json::from_value(json!({
+            "id": 7,
+            "created": "2019-10-19T10:00:00",
+            "title": title,
+            "url": "https://example.com",
+            "body": body,
+            "tags": tags,
+            "user_id": 1,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_top_terms() {
+        let b = bookmark(
+            "Rust futures",
+            "Futures and the executor: an executor polls futures, \
+             2019 is the year of async in Rust.",
+            &[],
+        );
+
+        assert_eq!(
+            top_terms(&b, 4),
+            vec!["futures", "rust", "executor", "async"]
+        );
+    }
+
+    #[test]
+    fn test_more_like_this() {
+        let b = bookmark("Postgres", "", &["Databases"]);
+        let q = more_like_this(&b).unwrap();
+
+        let js_value: Value = serde_json::to_value(&q).unwrap();
+        let term =
+            |field: &str, value: &str| json!({ "term": { field: value } });
+
+        assert_eq!(
+            js_value,
+            json!({
+                "bool": {
+                    "must": [{
+                        "bool": {
+                            "must": [],
+                            "must_not": [],
+                            "should": [
+                                {
+                                    "bool": {
+                                        "must": [],
+                                        "must_not": [],
+                                        "should": [term("tags", "Databases")],
+                                        "minimum_should_match": null,
+                                        "boost": 2.0
+                                    }
+                                },
+                                {
+                                    "bool": {
+                                        "must": [],
+                                        "must_not": [],
+                                        "should": [
+                                            term("title", "postgres"),
+                                            term("body", "postgres")
+                                        ],
+                                        "minimum_should_match": null,
+                                        "boost": null
+                                    }
+                                }
+                            ],
+                            "minimum_should_match": 1,
+                            "boost": null
+                        }
+                    }],
+                    "must_not": [term("key", "7")],
+                    "should": [],
+                    "minimum_should_match": null,
+                    "boost": null
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_nothing_to_match() {
+        let b = bookmark("The", "and 42", &[]);
+
+        assert_eq!(more_like_this(&b), None);
+    }
+}
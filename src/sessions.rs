@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use chrono::{NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use log::*;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use ring::digest;
+
+use crate::db::{DbExecutor, QueryRevokedSessions};
+
+// Access tokens can't be revoked by themselves, they are kept short-lived
+// and renewed with the refresh token of their session.
+pub const ACCESS_TOKEN_MINUTES: i64 = 15;
+pub const REFRESH_TOKEN_DAYS: i64 = 30;
+
+// How often revocations made elsewhere (other replicas) are picked up
+const SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    // Revoked sessions whose access tokens may not have expired yet, along
+    // with when they were last marked
+    static ref REVOKED: RwLock<HashMap<i32, Instant>> =
+        RwLock::new(HashMap::new());
+}
+
+pub fn random_token() -> String {
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+
+    to_hex(&secret)
+}
+
+// Returns a new refresh token, along with the hash stored in its place.
+pub fn new_refresh_token() -> (String, String) {
+    let token = random_token();
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+// Tokens are random, a plain (unsalted) hash is enough to keep a database
+// dump from being used to sign in, while allowing lookups.
+pub fn hash_token(token: &str) -> String {
+    to_hex(digest::digest(&digest::SHA256, token.as_bytes()).as_ref())
+}
+
+pub fn refresh_expiry() -> NaiveDateTime {
+    Utc::now().naive_utc() + chrono::Duration::days(REFRESH_TOKEN_DAYS)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn is_revoked(session_id: i32) -> bool {
+    // a poisoned lock fails closed
+    REVOKED
+        .read()
+        .map(|revoked| revoked.contains_key(&session_id))
+        .unwrap_or(true)
+}
+
+// Revocations made by this process take effect right away, instead of on
+// the next sync.
+pub fn mark_revoked(session_id: i32) {
+    if let Ok(mut revoked) = REVOKED.write() {
+        revoked.insert(session_id, Instant::now());
+    }
+}
+
+// Sessions revoked by this process after the sync `started` may be missing
+// from its result, and are kept.
+fn replace_revoked(session_ids: Vec<i32>, started: Instant) {
+    if let Ok(mut revoked) = REVOKED.write() {
+        revoked.retain(|_, marked| *marked >= started);
+        for id in session_ids {
+            revoked.entry(id).or_insert(started);
+        }
+    }
+}
+
+// Keeps the revoked sessions in memory in line with the database, so that
+// checking an access token doesn't need a query.
+pub struct RevocationSync {
+    db: Addr<DbExecutor>,
+}
+
+impl RevocationSync {
+    pub fn new(db: Addr<DbExecutor>) -> Self {
+        RevocationSync { db }
+    }
+
+    fn sync(&mut self, ctx: &mut Context<Self>) {
+        // access tokens of sessions revoked before this have all expired
+        let since = Utc::now().naive_utc()
+            - chrono::Duration::minutes(ACCESS_TOKEN_MINUTES + 1);
+
+        let started = Instant::now();
+        let query = self.db.send(QueryRevokedSessions { since });
+        ctx.spawn(query.into_actor(self).then(move |res, _, _| {
+            match res {
+                Ok(Ok(session_ids)) => replace_revoked(session_ids, started),
+                Ok(Err(err)) => error!("Failed to sync sessions: {:?}", err),
+                Err(err) => error!("Database executor error: {:?}", err),
+            }
+            fut::ok(())
+        }));
+    }
+}
+
+impl Actor for RevocationSync {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.sync(ctx);
+        ctx.run_interval(SYNC_INTERVAL, |act, ctx| act.sync(ctx));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refresh_token() {
+        let (token, hash) = new_refresh_token();
+        let (other, _) = new_refresh_token();
+
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, other);
+        assert_ne!(token, hash);
+        assert_eq!(hash_token(&token), hash);
+        assert_eq!(
+            hash_token(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn test_revoked() {
+        assert!(!is_revoked(41));
+        mark_revoked(41);
+        assert!(is_revoked(41));
+        std::thread::sleep(Duration::from_millis(1));
+
+        // revoked while the sync is running
+        let started = Instant::now();
+        mark_revoked(43);
+        replace_revoked(vec![42], started);
+        assert!(!is_revoked(41));
+        assert!(is_revoked(42));
+        assert!(is_revoked(43));
+        std::thread::sleep(Duration::from_millis(1));
+
+        replace_revoked(vec![], Instant::now());
+        assert!(!is_revoked(42));
+        assert!(!is_revoked(43));
+    }
+}
@@ -0,0 +1,64 @@
+use horrorshow::{html, RenderOnce, TemplateBuffer};
+
+use crate::models::{Bookmark, Snapshot};
+
+// Text of a bookmarked page as it was when it was archived
+pub struct ArchiveItem {
+    bookmark: Bookmark,
+    snapshot: Snapshot,
+}
+
+impl ArchiveItem {
+    pub fn new(bookmark: Bookmark, snapshot: Snapshot) -> Self {
+        Self { bookmark, snapshot }
+    }
+}
+
+impl RenderOnce for ArchiveItem {
+    fn render_once(self, tmpl: &mut TemplateBuffer) {
+        let Snapshot {
+            fetched,
+            http_status,
+            final_url,
+            title,
+            byline,
+            content,
+            ..
+        } = self.snapshot;
+        let title = title.unwrap_or(self.bookmark.title);
+        let fetched = fetched.format("%Y-%m-%d").to_string();
+        let failed = match http_status {
+            Some(status) => !(200..300).contains(&status),
+            None => true,
+        };
+
+        tmpl << html! {
+            div(class = "item archive") {
+                h2 {
+                    a(href = final_url, class = "external") {
+                      : title
+                    }
+                }
+                p(class = "byline") {
+                    @ if let Some(byline) = byline {
+                        : byline;
+                        : " · ";
+                    }
+                    : "Archived on ";
+                    : fetched;
+                }
+                @ if failed {
+                    div(class = "error danger") {
+                        @ if let Some(status) = http_status {
+                            strong: status;
+                        }
+                        : "The page could not be archived.";
+                    }
+                }
+                @ for paragraph in content.split("\n\n") {
+                    p: paragraph;
+                }
+            }
+        };
+    }
+}
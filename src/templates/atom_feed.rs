@@ -0,0 +1,73 @@
+use chrono::naive::NaiveDateTime;
+use chrono::Utc;
+use horrorshow::{html, Raw, RenderOnce, TemplateBuffer};
+use pulldown_cmark::{html, Parser};
+
+use super::IntoBookmark;
+use crate::config::CONFIG;
+use crate::models::Bookmark;
+
+pub struct AtomFeed<I> {
+    title: String,
+    path: String,
+    items: I,
+}
+
+impl<I> AtomFeed<I> {
+    pub fn new(title: String, path: String, items: I) -> Self {
+        Self { title, path, items }
+    }
+}
+
+impl<B, I> RenderOnce for AtomFeed<I>
+where
+    I: Iterator<Item = B>,
+    B: IntoBookmark,
+{
+    fn render_once(self, tmpl: &mut TemplateBuffer) {
+        let Self { title, path, items } = self;
+
+        // newest first, regardless of the order items are given in
+        let mut entries: Vec<Bookmark> =
+            items.map(IntoBookmark::into_bookmark).collect();
+        entries.sort_by(|a, b| b.created.cmp(&a.created));
+
+        let updated = entries
+            .first()
+            .map(|b| b.created)
+            .unwrap_or_else(|| Utc::now().naive_utc());
+        // feed readers expect absolute urls, the site is served
+        // from the same origin the admin ui is allowed from
+        let feed_url = format!("{}{}", CONFIG.allowed_origin, path);
+
+        tmpl << html! {
+            : Raw(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+            feed(xmlns = "http://www.w3.org/2005/Atom") {
+                title: title;
+                id: &feed_url;
+                updated: atom_date(&updated);
+                link(href = &feed_url, rel = "self") {}
+                @ for b in entries {
+                    entry {
+                        title: &b.title;
+                        id: &b.url;
+                        link(href = &b.url) {}
+                        updated: atom_date(&b.created);
+                        content(type = "html"): markdown_html(&b.body);
+                    }
+                }
+            }
+        };
+    }
+}
+
+fn atom_date(date: &NaiveDateTime) -> String {
+    date.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+fn markdown_html(body: &str) -> String {
+    let mut out = String::new();
+    html::push_html(&mut out, Parser::new(body));
+
+    out
+}
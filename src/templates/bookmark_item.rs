@@ -1,11 +1,18 @@
 use std::io;
 
-use crate::models::{Bookmark, BookmarkDoc};
+use crate::models::{Bookmark, BookmarkDoc, Highlight, HighlightedBookmark};
 use horrorshow::{html, RenderOnce, TemplateBuffer};
 use pulldown_cmark::{html, Parser};
 
 pub trait IntoBookmark {
     fn into_bookmark(self) -> Bookmark;
+
+    fn into_parts(self) -> (Bookmark, Vec<Highlight>)
+    where
+        Self: Sized,
+    {
+        (self.into_bookmark(), vec![])
+    }
 }
 
 impl IntoBookmark for Bookmark {
@@ -13,6 +20,15 @@ impl IntoBookmark for Bookmark {
         self
     }
 }
+impl IntoBookmark for HighlightedBookmark {
+    fn into_bookmark(self) -> Bookmark {
+        self.bookmark
+    }
+
+    fn into_parts(self) -> (Bookmark, Vec<Highlight>) {
+        (self.bookmark, self.highlights)
+    }
+}
 impl IntoBookmark for BookmarkDoc {
     fn into_bookmark(self) -> Bookmark {
         self.into_bookmark_lossy()
@@ -21,24 +37,32 @@ impl IntoBookmark for BookmarkDoc {
 
 pub struct BookmarkItem {
     data: Bookmark,
+    highlights: Vec<Highlight>,
 }
 impl BookmarkItem {
     pub fn new<B: IntoBookmark>(b: B) -> Self {
-        Self {
-            data: b.into_bookmark(),
-        }
+        let (data, highlights) = b.into_parts();
+        Self { data, highlights }
     }
 }
 
 impl RenderOnce for BookmarkItem {
     fn render_once(self, tmpl: &mut TemplateBuffer) {
         let Bookmark {
-            title, url, body, ..
+            title,
+            url,
+            body,
+            starred,
+            ..
         } = self.data;
+        let highlights = self.highlights;
 
         tmpl << html! {
             div(class = "item") {
                 h2 {
+                    @ if starred {
+                        span(class = "star", title = "Starred"): "★";
+                    }
                     a(href = url, class = "external") {
                       : title
                     }
@@ -50,6 +74,14 @@ impl RenderOnce for BookmarkItem {
                       let _ = html::write_html(writer, parser);
                   }
                 }
+                @ for highlight in highlights {
+                    blockquote(class = "highlight") {
+                        p: highlight.quote;
+                        @ if let Some(comment) = highlight.comment {
+                            footer: comment;
+                        }
+                    }
+                }
             }
         };
     }
@@ -1,7 +1,8 @@
 use crate::jsonml::{AttrsBuilder, JsonML, JsonMLBuilder, MDParser};
-use crate::models::Bookmark;
+use crate::models::{Highlight, HighlightedBookmark};
 
-pub fn bookmark_jsonml<'a>(bookmark: &'a Bookmark) -> JsonML<'a> {
+pub fn bookmark_jsonml<'a>(item: &'a HighlightedBookmark) -> JsonML<'a> {
+    let bookmark = &item.bookmark;
     let mut parser = MDParser::new(&bookmark.body);
     let body = match parser.jsonml() {
         Ok(jsonml) => jsonml,
@@ -18,8 +19,21 @@ pub fn bookmark_jsonml<'a>(bookmark: &'a Bookmark) -> JsonML<'a> {
                     .attr("class", "external")
                     .attr("href", bookmark.url.as_str())
                     .build();
-                builder
+                let builder = builder
                     .append_element("h2", |builder| {
+                        let builder = if bookmark.starred {
+                            let star_attrs = AttrsBuilder::new()
+                                .attr("class", "star")
+                                .attr("title", "Starred")
+                                .build();
+                            builder.append_element_with_attrs(
+                                "span",
+                                star_attrs,
+                                |builder| builder.append_text_node("★").build(),
+                            )
+                        } else {
+                            builder
+                        };
                         builder
                             .append_element_with_attrs(
                                 "a",
@@ -34,9 +48,32 @@ pub fn bookmark_jsonml<'a>(bookmark: &'a Bookmark) -> JsonML<'a> {
                             )
                             .build()
                     })
-                    .append_element("div", move |_| body)
+                    .append_element("div", move |_| body);
+                item.highlights
+                    .iter()
+                    .fold(builder, append_highlight)
                     .build()
             },
         )
         .build()
 }
+
+fn append_highlight<'a>(
+    builder: JsonMLBuilder<'a>,
+    highlight: &'a Highlight,
+) -> JsonMLBuilder<'a> {
+    let attrs = AttrsBuilder::new().attr("class", "highlight").build();
+    builder.append_element_with_attrs("blockquote", attrs, |builder| {
+        let builder = builder.append_element("p", |builder| {
+            builder.append_text_node(highlight.quote.as_str()).build()
+        });
+        match highlight.comment {
+            Some(ref comment) => builder
+                .append_element("footer", |builder| {
+                    builder.append_text_node(comment.as_str()).build()
+                })
+                .build(),
+            None => builder.build(),
+        }
+    })
+}
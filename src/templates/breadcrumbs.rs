@@ -0,0 +1,93 @@
+use horrorshow::{html, RenderOnce, TemplateBuffer};
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+
+use crate::models::tag_ancestors;
+
+// Trail of links from the tags page down to a (child) tag, or from the
+// home page to a user profile
+pub struct Breadcrumbs {
+    links: Vec<(String, String)>,
+    current: String,
+}
+
+impl Breadcrumbs {
+    pub fn for_tag(tag: &str) -> Self {
+        let mut links = vec![("Tags".to_owned(), "/tags".to_owned())];
+        let mut ancestors = tag_ancestors(tag);
+        let current = ancestors.pop().unwrap_or_default();
+
+        links.extend(ancestors.into_iter().map(|path| {
+            let label = path.rsplit('/').next().unwrap_or_default().to_owned();
+            (label, tag_href(&path))
+        }));
+
+        Self {
+            links,
+            current: current.rsplit('/').next().unwrap_or_default().to_owned(),
+        }
+    }
+
+    pub fn for_user(name: &str) -> Self {
+        Self {
+            links: vec![("Home".to_owned(), "/".to_owned())],
+            current: name.to_owned(),
+        }
+    }
+}
+
+// Page of a tag, the `/` between parent and child are kept as they are
+pub fn tag_href(tag: &str) -> String {
+    let segments = tag
+        .split('/')
+        .map(|s| utf8_percent_encode(s, PATH_SEGMENT_ENCODE_SET).to_string());
+
+    format!("/tags/{}", itertools::join(segments, "/"))
+}
+
+// Profile page of a user
+pub fn user_href(name: &str) -> String {
+    format!("/u/{}", utf8_percent_encode(name, PATH_SEGMENT_ENCODE_SET))
+}
+
+impl RenderOnce for Breadcrumbs {
+    fn render_once(self, tmpl: &mut TemplateBuffer) {
+        let Breadcrumbs { links, current } = self;
+
+        tmpl << html! {
+            nav(class = "item breadcrumbs") {
+                @ for (label, href) in links {
+                    a(href = href): label;
+                    : " / ";
+                }
+                strong: current;
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_breadcrumbs() {
+        let crumbs = Breadcrumbs::for_tag("lang/rust/async io");
+
+        assert_eq!(
+            crumbs.links,
+            vec![
+                ("Tags".to_owned(), "/tags".to_owned()),
+                ("lang".to_owned(), "/tags/lang".to_owned()),
+                ("rust".to_owned(), "/tags/lang/rust".to_owned()),
+            ]
+        );
+        assert_eq!(crumbs.current, "async io");
+        assert_eq!(tag_href("lang/c#"), "/tags/lang/c%23");
+    }
+
+    #[test]
+    fn test_user_href() {
+        assert_eq!(user_href("ann"), "/u/ann");
+        assert_eq!(user_href("ann/b#"), "/u/ann%2Fb%23");
+    }
+}
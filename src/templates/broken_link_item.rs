@@ -0,0 +1,59 @@
+use horrorshow::{html, RenderOnce, TemplateBuffer};
+
+use crate::models::Bookmark;
+
+// A bookmark whose link failed the last check
+pub struct BrokenLinkItem {
+    bookmark: Bookmark,
+}
+
+impl BrokenLinkItem {
+    pub fn new(bookmark: Bookmark) -> Self {
+        Self { bookmark }
+    }
+}
+
+impl RenderOnce for BrokenLinkItem {
+    fn render_once(self, tmpl: &mut TemplateBuffer) {
+        let Bookmark {
+            id,
+            title,
+            url,
+            last_checked,
+            last_status,
+            final_url,
+            ..
+        } = self.bookmark;
+        let status = last_status
+            .map(|status| status.to_string())
+            .unwrap_or_else(|| "Unreachable".to_owned());
+        let checked = last_checked
+            .map(|checked| checked.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        let moved_to = final_url.filter(|final_url| *final_url != url);
+        let archive = format!("/bookmarks/{}/archive", id);
+
+        tmpl << html! {
+            div(class = "item broken-link") {
+                h2 {
+                    a(href = &url, class = "external") {
+                      : title
+                    }
+                }
+                p(class = "byline") {
+                    strong: status;
+                    : " · Checked on ";
+                    : checked;
+                    : " · ";
+                    a(href = archive): "Archived copy";
+                }
+                @ if let Some(moved_to) = moved_to {
+                    p {
+                        : "Redirects to ";
+                        a(href = &moved_to, class = "external"): &moved_to;
+                    }
+                }
+            }
+        };
+    }
+}
@@ -1,23 +1,52 @@
 use horrorshow::helper::doctype;
 use horrorshow::{html, Raw, RenderOnce, TemplateBuffer};
+use pulldown_cmark::{html, Parser};
 
+mod archive_item;
+mod atom_feed;
 mod bookmark_item;
 mod bookmark_jsonml;
+mod breadcrumbs;
+mod broken_link_item;
+mod permalink;
+mod tag_cloud;
 
+pub use archive_item::ArchiveItem;
+pub use atom_feed::AtomFeed;
 pub use bookmark_item::*;
 pub use bookmark_jsonml::bookmark_jsonml;
+pub use breadcrumbs::{tag_href, user_href, Breadcrumbs};
+pub use broken_link_item::BrokenLinkItem;
+pub use permalink::Permalink;
+pub use tag_cloud::TagCloud;
 
 pub struct PageTemplate<I> {
     next_page: Option<i64>,
     query_str: Option<String>,
+    feed_href: Option<String>,
+    more_source: Option<String>,
+    search_action: Option<String>,
+    intro: Option<PageIntro>,
+    breadcrumbs: Option<Breadcrumbs>,
     items: I,
 }
 
+// Heading and markdown text shown above the items of a page
+pub struct PageIntro {
+    title: String,
+    body: String,
+}
+
 impl<I> PageTemplate<I> {
     pub fn new(items: I) -> Self {
         Self {
             next_page: None,
             query_str: None,
+            feed_href: None,
+            more_source: None,
+            search_action: None,
+            intro: None,
+            breadcrumbs: None,
             items,
         }
     }
@@ -25,6 +54,11 @@ impl<I> PageTemplate<I> {
         Self {
             next_page: None,
             query_str: Some(q),
+            feed_href: None,
+            more_source: None,
+            search_action: None,
+            intro: None,
+            breadcrumbs: None,
             items,
         }
     }
@@ -33,6 +67,43 @@ impl<I> PageTemplate<I> {
             next_page,
             items,
             query_str: None,
+            feed_href: None,
+            more_source: None,
+            search_action: None,
+            intro: None,
+            breadcrumbs: None,
+        }
+    }
+    pub fn with_feed(self, href: String) -> Self {
+        Self {
+            feed_href: Some(href),
+            ..self
+        }
+    }
+    pub fn with_intro(self, title: String, body: String) -> Self {
+        Self {
+            intro: Some(PageIntro { title, body }),
+            ..self
+        }
+    }
+    pub fn with_breadcrumbs(self, breadcrumbs: Breadcrumbs) -> Self {
+        Self {
+            breadcrumbs: Some(breadcrumbs),
+            ..self
+        }
+    }
+    // json api the "More" link loads further pages from
+    pub fn with_source(self, source: String) -> Self {
+        Self {
+            more_source: Some(source),
+            ..self
+        }
+    }
+    // page the search box submits to
+    pub fn with_search_action(self, action: String) -> Self {
+        Self {
+            search_action: Some(action),
+            ..self
         }
     }
 }
@@ -49,11 +120,24 @@ where
             items,
             query_str,
             next_page,
+            feed_href,
+            more_source,
+            search_action,
+            intro,
+            breadcrumbs,
         } = self;
         let q = match query_str {
             Some(ref q) => q,
             None => "",
         };
+        let source = match more_source {
+            Some(ref source) => source,
+            None => "/api/bookmarks:page",
+        };
+        let action = match search_action {
+            Some(ref action) => action,
+            None => "/search",
+        };
 
         let css_href = asset!("/static/site.css");
         let script_href = asset!("/static/js/js-enhance.umd.js");
@@ -69,13 +153,18 @@ where
                     link(rel = "stylesheet",
                          type = "text/css",
                          href = css_href);
+                    @ if let Some(feed_href) = feed_href {
+                        link(rel = "alternate",
+                             type = "application/atom+xml",
+                             href = feed_href);
+                    }
                 }
                 body {
                     header {
                         h1 {
                             a(href = "/"): "Insightful Reads"
                         }
-                        form(action = "/search", method = "GET") {
+                        form(action = action, method = "GET") {
                             div {
                                 : Raw(ICON);
                                 input(type = "text", name = "q", value = q);
@@ -83,7 +172,13 @@ where
                             }
                         }
                     }
-                    div(class = "main") {
+                    div(class = "main", data-source = source) {
+                        @ if let Some(breadcrumbs) = breadcrumbs {
+                            : breadcrumbs
+                        }
+                        @ if let Some(intro) = intro {
+                            : intro
+                        }
                         @ for t in items {
                             |tmpl| {
                                 count += 1;
@@ -112,6 +207,21 @@ where
     }
 }
 
+impl RenderOnce for PageIntro {
+    fn render_once(self, tmpl: &mut TemplateBuffer) {
+        let PageIntro { title, body } = self;
+        let mut body_html = String::new();
+        html::push_html(&mut body_html, Parser::new(&body));
+
+        tmpl << html! {
+            div(class = "item intro") {
+                h2: title;
+                div: Raw(body_html);
+            }
+        };
+    }
+}
+
 const ICON: &str = {
     r#"
     <svg aria-hidden="true" version="1.1" xmlns="http://www.w3.org/2000/svg" style="display: none;">
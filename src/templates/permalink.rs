@@ -0,0 +1,46 @@
+use horrorshow::{html, RenderOnce, TemplateBuffer};
+
+use super::bookmark_item::{BookmarkItem, IntoBookmark};
+use crate::models::BookmarkDoc;
+
+// A single bookmark, followed by the ones most similar to it
+pub struct Permalink {
+    item: BookmarkItem,
+    related: Vec<BookmarkDoc>,
+}
+
+impl Permalink {
+    pub fn new<B: IntoBookmark>(
+        bookmark: B,
+        related: Vec<BookmarkDoc>,
+    ) -> Self {
+        Self {
+            item: BookmarkItem::new(bookmark),
+            related,
+        }
+    }
+}
+
+impl RenderOnce for Permalink {
+    fn render_once(self, tmpl: &mut TemplateBuffer) {
+        let Permalink { item, related } = self;
+
+        tmpl << html! {
+            : item;
+            @ if !related.is_empty() {
+                div(class = "item related") {
+                    h3: "Related";
+                    ul {
+                        @ for doc in related {
+                            li {
+                                a(href = format!("/bookmarks/{}", doc.id)) {
+                                  : doc.title
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        };
+    }
+}
@@ -0,0 +1,77 @@
+use horrorshow::{html, RenderOnce, TemplateBuffer};
+
+use super::breadcrumbs::tag_href;
+use crate::models::TagCount;
+
+// Number of font sizes tags are spread over, see `.weight-*` in site.css
+const WEIGHTS: u32 = 5;
+
+pub struct TagCloud {
+    tags: Vec<TagCount>,
+}
+
+impl TagCloud {
+    pub fn new(mut tags: Vec<TagCount>) -> Self {
+        tags.sort_by(|a, b| a.tag.to_lowercase().cmp(&b.tag.to_lowercase()));
+        Self { tags }
+    }
+}
+
+// Weight from 1 to WEIGHTS, on a log scale since a handful of tags are
+// usually used far more than the rest.
+fn tag_weight(count: i64, min: i64, max: i64) -> u32 {
+    if max <= min {
+        return 1;
+    }
+    let scale = |n: i64| (n.max(1) as f64).ln();
+    let ratio = (scale(count) - scale(min)) / (scale(max) - scale(min));
+
+    1 + (ratio * f64::from(WEIGHTS - 1)).round() as u32
+}
+
+impl RenderOnce for TagCloud {
+    fn render_once(self, tmpl: &mut TemplateBuffer) {
+        let counts = self.tags.iter().map(|tag| tag.count);
+        let min = counts.clone().min().unwrap_or(0);
+        let max = counts.max().unwrap_or(0);
+        let tags = self.tags;
+
+        tmpl << html! {
+            div(class = "item tag-cloud") {
+                h2: "Tags";
+                @ if tags.is_empty() {
+                    p: "No tags yet.";
+                }
+                ul {
+                    @ for tag in tags {
+                        li(class = format!("weight-{}", tag_weight(tag.count, min, max))) {
+                            a(
+                                href = tag_href(&tag.tag),
+                                title = format!(
+                                    "{} bookmark(s), last on {}",
+                                    tag.count,
+                                    tag.last_used.format("%Y-%m-%d")
+                                )
+                            ) {
+                                : &tag.tag
+                            }
+                        }
+                    }
+                }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_weight() {
+        assert_eq!(tag_weight(1, 1, 1), 1);
+        assert_eq!(tag_weight(1, 1, 100), 1);
+        assert_eq!(tag_weight(10, 1, 100), 3);
+        assert_eq!(tag_weight(100, 1, 100), 5);
+    }
+}
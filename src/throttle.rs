@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use log::*;
+
+use crate::error::ServiceError;
+
+// Failures after which each further one blocks for twice as long, up to
+// the lockout
+const FREE_ACCOUNT_ATTEMPTS: u32 = 5;
+// Addresses may be shared by many users
+const FREE_IP_ATTEMPTS: u32 = 20;
+const FIRST_DELAY: Duration = Duration::from_secs(1);
+const LOCKOUT: Duration = Duration::from_secs(15 * 60);
+
+// Failures are forgotten once nothing was attempted for this long
+const FORGET_AFTER: Duration = Duration::from_secs(60 * 60);
+// Idle entries are only dropped past this many, to bound memory
+const PRUNE_ABOVE: usize = 10_000;
+
+lazy_static! {
+    static ref BY_IP: Mutex<Throttle> =
+        Mutex::new(Throttle::new(FREE_IP_ATTEMPTS));
+    static ref BY_ACCOUNT: Mutex<Throttle> =
+        Mutex::new(Throttle::new(FREE_ACCOUNT_ATTEMPTS));
+}
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    last: Instant,
+    blocked_until: Option<Instant>,
+}
+
+// Failed attempts by key, with exponential backoff
+#[derive(Debug)]
+pub struct Throttle {
+    free_attempts: u32,
+    failures: HashMap<String, Failures>,
+}
+
+impl Throttle {
+    pub fn new(free_attempts: u32) -> Self {
+        Throttle {
+            free_attempts,
+            failures: HashMap::new(),
+        }
+    }
+
+    // Time left until `key` may be tried again
+    pub fn blocked_for(&self, key: &str, now: Instant) -> Option<Duration> {
+        self.failures
+            .get(key)
+            .and_then(|failures| failures.blocked_until)
+            .filter(|&until| until > now)
+            .map(|until| until - now)
+    }
+
+    // Records a failure, returns for how long `key` is blocked after it
+    pub fn fail(&mut self, key: &str, now: Instant) -> Option<Duration> {
+        if self.failures.len() > PRUNE_ABOVE {
+            self.failures.retain(|_, failures| {
+                elapsed(failures.last, now) < FORGET_AFTER
+            });
+        }
+
+        let failures =
+            self.failures.entry(key.to_owned()).or_insert(Failures {
+                count: 0,
+                last: now,
+                blocked_until: None,
+            });
+        if elapsed(failures.last, now) >= FORGET_AFTER {
+            failures.count = 0;
+        }
+        failures.count += 1;
+        failures.last = now;
+
+        let delay = backoff(failures.count, self.free_attempts);
+        failures.blocked_until = delay.map(|delay| now + delay);
+        delay
+    }
+
+    pub fn succeed(&mut self, key: &str) {
+        self.failures.remove(key);
+    }
+}
+
+// Another thread may have recorded a failure a bit later than `now`
+fn elapsed(since: Instant, now: Instant) -> Duration {
+    if now > since {
+        now - since
+    } else {
+        Duration::from_secs(0)
+    }
+}
+
+fn backoff(count: u32, free_attempts: u32) -> Option<Duration> {
+    if count <= free_attempts {
+        return None;
+    }
+    // capping the exponent keeps the multiplication from overflowing
+    let exponent = (count - free_attempts - 1).min(16);
+    Some((FIRST_DELAY * 2u32.pow(exponent)).min(LOCKOUT))
+}
+
+fn retry_after(delay: Duration) -> ServiceError {
+    // round up, retrying a second early is blocked again
+    let secs = delay.as_secs() + u64::from(delay.subsec_nanos() > 0);
+    ServiceError::TooManyRequests(secs)
+}
+
+// Blocked attempts are turned away before any password is checked
+pub fn check_login(ip: &str, email: &str) -> Result<(), ServiceError> {
+    let now = Instant::now();
+    let by_ip = BY_IP.lock().ok().and_then(|t| t.blocked_for(ip, now));
+    let by_account = BY_ACCOUNT
+        .lock()
+        .ok()
+        .and_then(|t| t.blocked_for(email, now));
+
+    match by_ip.into_iter().chain(by_account).max() {
+        Some(delay) => Err(retry_after(delay)),
+        None => Ok(()),
+    }
+}
+
+// Returns for how long further attempts are blocked, if at all
+pub fn login_failed(ip: &str, email: &str) -> Option<Duration> {
+    let now = Instant::now();
+    warn!(target: "audit", "Failed login for {} from {}", email, ip);
+
+    let by_ip = BY_IP.lock().ok().and_then(|mut t| t.fail(ip, now));
+    if let Some(delay) = by_ip {
+        warn!(target: "audit", "Blocked logins from {} for {:?}", ip, delay);
+    }
+    let by_account =
+        BY_ACCOUNT.lock().ok().and_then(|mut t| t.fail(email, now));
+    if let Some(delay) = by_account {
+        warn!(target: "audit", "Blocked logins for {} for {:?}", email, delay);
+    }
+    by_ip.into_iter().chain(by_account).max()
+}
+
+// Address attempts are counted by: the peer, or behind `proxies` reverse
+// proxies the address the outermost one saw. Each proxy appends the address
+// it saw to X-Forwarded-For, anything further left is up to the client.
+pub fn client_ip(
+    peer: Option<SocketAddr>,
+    forwarded_for: &[&str],
+    proxies: usize,
+) -> String {
+    let hops: Vec<&str> = forwarded_for
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|hop| !hop.is_empty())
+        .collect();
+    if proxies > 0 && hops.len() >= proxies {
+        return hops[hops.len() - proxies].to_owned();
+    }
+    peer.map_or_else(|| "unknown".to_owned(), |addr| addr.ip().to_string())
+}
+
+// Failures from the address are kept, signing in to one account must not
+// allow guessing another's password
+pub fn login_succeeded(email: &str) {
+    if let Ok(mut throttle) = BY_ACCOUNT.lock() {
+        throttle.succeed(email);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_ip() {
+        let peer = "10.0.0.2:41000".parse().ok();
+        let forwarded = &["1.2.3.4, 5.6.7.8", "203.0.113.9"];
+
+        assert_eq!(client_ip(peer, forwarded, 0), "10.0.0.2");
+        assert_eq!(client_ip(peer, forwarded, 1), "203.0.113.9");
+        assert_eq!(client_ip(peer, forwarded, 2), "5.6.7.8");
+        // the proxy didn't append, so the header can't be trusted
+        assert_eq!(client_ip(peer, &[], 1), "10.0.0.2");
+        assert_eq!(client_ip(None, &[], 0), "unknown");
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(5, 5), None);
+        assert_eq!(backoff(6, 5), Some(Duration::from_secs(1)));
+        assert_eq!(backoff(7, 5), Some(Duration::from_secs(2)));
+        assert_eq!(backoff(10, 5), Some(Duration::from_secs(16)));
+        assert_eq!(backoff(20, 5), Some(LOCKOUT));
+        assert_eq!(backoff(1000, 5), Some(LOCKOUT));
+    }
+
+    #[test]
+    fn test_throttle() {
+        let mut throttle = Throttle::new(2);
+        let now = Instant::now();
+
+        assert_eq!(throttle.fail("ann", now), None);
+        assert_eq!(throttle.fail("ann", now), None);
+        assert_eq!(throttle.blocked_for("ann", now), None);
+        assert_eq!(throttle.fail("ann", now), Some(FIRST_DELAY));
+        assert_eq!(throttle.blocked_for("ann", now), Some(FIRST_DELAY));
+        assert_eq!(throttle.blocked_for("bob", now), None);
+
+        let later = now + FIRST_DELAY;
+        assert_eq!(throttle.blocked_for("ann", later), None);
+        assert_eq!(throttle.fail("ann", later), Some(FIRST_DELAY * 2));
+
+        let much_later = later + FORGET_AFTER;
+        assert_eq!(throttle.fail("ann", much_later), None);
+
+        throttle.fail("ann", much_later);
+        throttle.succeed("ann");
+        assert_eq!(throttle.fail("ann", much_later), None);
+    }
+
+    #[test]
+    fn test_retry_after() {
+        match retry_after(Duration::from_millis(1500)) {
+            ServiceError::TooManyRequests(secs) => assert_eq!(secs, 2),
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+}
@@ -1,14 +1,23 @@
 use actix_web::{
-    dev::{Payload, RequestHead},
-    FromRequest, HttpRequest,
+    dev::{
+        Payload, RequestHead, Service, ServiceRequest, ServiceResponse,
+        Transform,
+    },
+    http::Method,
+    Error, FromRequest, HttpRequest,
 };
 use chrono::{Duration, Local};
-use jsonwebtoken::{decode, encode, Header, Validation};
+use futures::future::{ok, Either, FutureResult};
+use futures::Poll;
+use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
 use serde_derive::*;
 
+use crate::api_tokens;
 use crate::config::CONFIG;
+use crate::cookies::{check_csrf, cookie_value, ACCESS_COOKIE};
 use crate::error::ServiceError;
-use crate::models::SlimUser;
+use crate::models::{Audience, Permission, Role, SlimUser};
+use crate::sessions::{is_revoked, ACCESS_TOKEN_MINUTES};
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Claims {
@@ -18,20 +27,29 @@ struct Claims {
     iat: i64,
     // expiry
     exp: i64,
+    // session id
+    jti: String,
+    // user id
+    sub: i32,
     // user email
     email: String,
-    // admin
-    is_admin: bool,
+    // user name
+    name: String,
+    roles: Vec<Role>,
 }
 
 impl Claims {
-    fn from_user(user: &SlimUser) -> Self {
+    fn from_user(user: &SlimUser, session_id: i32) -> Self {
         Claims {
             iss: "localhost".into(),
+            jti: session_id.to_string(),
+            sub: user.id,
             email: user.email.clone(),
+            name: user.name.clone(),
             iat: Local::now().timestamp(),
-            exp: (Local::now() + Duration::hours(24)).timestamp(),
-            is_admin: user.is_admin,
+            exp: (Local::now() + Duration::minutes(ACCESS_TOKEN_MINUTES))
+                .timestamp(),
+            roles: user.roles.clone(),
         }
     }
 }
@@ -39,8 +57,11 @@ impl Claims {
 impl From<Claims> for SlimUser {
     fn from(claims: Claims) -> Self {
         SlimUser {
+            id: claims.sub,
             email: claims.email,
-            is_admin: claims.is_admin,
+            name: claims.name,
+            roles: claims.roles,
+            session_id: claims.jti.parse().ok(),
         }
     }
 }
@@ -55,22 +76,62 @@ impl FromRequest for SlimUser {
     }
 }
 
+impl FromRequest for Audience {
+    type Config = ();
+    type Error = ServiceError;
+    type Future = Result<Audience, ServiceError>;
+
+    fn from_request(req: &HttpRequest, _pl: &mut Payload) -> Self::Future {
+        let user = get_admin(req.head())
+            .filter(|user| user.can(Permission::ViewPrivate));
+        match user {
+            Some(_) => Ok(Audience::Private),
+            None => Ok(Audience::Anonymous),
+        }
+    }
+}
+
+// Access tokens are only issued for a session, see `sessions`
 pub fn create_token(data: &SlimUser) -> Result<String, ServiceError> {
-    let claims = Claims::from_user(data);
-    encode(&Header::default(), &claims, CONFIG.jwt_secret.as_slice())
+    let session_id =
+        data.session_id.ok_or(ServiceError::InternalServerError)?;
+    let claims = Claims::from_user(data, session_id);
+    // replicas only holding public keys can't sign
+    let (key, signing_key) = CONFIG
+        .jwt_keys
+        .signing_key()
+        .and_then(|key| key.signing_key().map(|signing| (key, signing)))
+        .ok_or(ServiceError::InternalServerError)?;
+
+    let mut header = Header::new(key.algorithm);
+    header.kid = Some(key.kid.clone());
+    encode(&header, &claims, signing_key)
         .map_err(|_err| ServiceError::InternalServerError)
 }
 
 pub fn decode_token(token: &str) -> Result<SlimUser, ServiceError> {
+    decode_access_token(extract_bearer_creds(token)?)
+}
+
+fn decode_access_token(token: &str) -> Result<SlimUser, ServiceError> {
+    let key = decode_header(token)
+        .ok()
+        .and_then(|header| header.kid)
+        .and_then(|kid| CONFIG.jwt_keys.verifying_key(&kid))
+        .ok_or(ServiceError::Unauthorized)?;
+
+    // only the algorithm of the key, a token can't pick another one
     decode::<Claims>(
-        extract_bearer_creds(token)?,
-        CONFIG.jwt_secret.as_slice(),
-        &Validation::default(),
+        token,
+        key.verifying_key(),
+        &Validation::new(key.algorithm),
     )
     .map(|data| {
         let now = Local::now().timestamp();
-        if now < data.claims.exp && now >= data.claims.iat {
-            Ok(data.claims.into())
+        let claims = data.claims;
+        let revoked = claims.jti.parse().map(is_revoked).unwrap_or(true);
+        if now < claims.exp && now >= claims.iat && !revoked {
+            Ok(claims.into())
         } else {
             Err(ServiceError::Unauthorized)
         }
@@ -78,6 +139,69 @@ pub fn decode_token(token: &str) -> Result<SlimUser, ServiceError> {
     .map_err(|_err| ServiceError::Unauthorized)?
 }
 
+const MIN_PASSWORD_LEN: usize = 10;
+// bcrypt ignores anything past 72 bytes
+const MAX_PASSWORD_BYTES: usize = 72;
+// Shorter names are part of too many words ("ann" of "planning"), and are
+// only rejected as a whole word
+const MIN_IDENTITY_LEN: usize = 4;
+
+// Checks a new password is strong enough, `identity` (email, name) of the
+// user must not be part of it.
+pub fn check_password(
+    password: &str,
+    identity: &[&str],
+) -> Result<(), ServiceError> {
+    let reject = |reason: String| {
+        Err(ServiceError::BadRequest(format!("Password {}", reason)))
+    };
+
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return reject(format!(
+            "must be at least {} characters long",
+            MIN_PASSWORD_LEN
+        ));
+    }
+    if password.len() > MAX_PASSWORD_BYTES {
+        return reject(format!(
+            "must be at most {} bytes long",
+            MAX_PASSWORD_BYTES
+        ));
+    }
+    let classes = [
+        password.chars().any(char::is_lowercase),
+        password.chars().any(char::is_uppercase),
+        password.chars().any(char::is_numeric),
+        password.chars().any(|c| !c.is_alphanumeric()),
+    ];
+    if classes.iter().filter(|&&class| class).count() < 2 {
+        return reject(
+            "must mix upper and lower case letters, digits or symbols"
+                .to_owned(),
+        );
+    }
+    let lowercase = password.to_lowercase();
+    let words: Vec<_> =
+        lowercase.split(|c: char| !c.is_alphanumeric()).collect();
+    let contains_identity = identity
+        .iter()
+        .filter_map(|s| s.split('@').next())
+        .map(str::to_lowercase)
+        .filter(|s| !s.is_empty())
+        .any(|s| {
+            if s.chars().count() >= MIN_IDENTITY_LEN {
+                lowercase.contains(&s)
+            } else {
+                words.contains(&s.as_str())
+            }
+        });
+    if contains_identity {
+        return reject("must not contain your name or email".to_owned());
+    }
+
+    Ok(())
+}
+
 fn extract_bearer_creds(token: &str) -> Result<&str, ServiceError> {
     const PREFIX: &str = "Bearer ";
     const PREFIX_LEN: usize = 7; // prefix.len() -- not stable yet
@@ -89,16 +213,140 @@ fn extract_bearer_creds(token: &str) -> Result<&str, ServiceError> {
     }
 }
 
-pub fn admin_guard(req: &RequestHead) -> bool {
-    match get_admin(req).filter(|user| user.is_admin) {
-        Some(_) => true,
-        _ => false,
+// Resource middleware letting through users allowed to `permission`, others
+// get a 401 without credentials and a 403 with them. Limited to the methods
+// given with `only`, requests of other methods go through unchecked.
+#[derive(Debug, Clone)]
+pub struct Require {
+    permission: Permission,
+    methods: Vec<Method>,
+}
+
+impl Require {
+    pub fn new(permission: Permission) -> Self {
+        Require {
+            permission,
+            methods: vec![],
+        }
+    }
+
+    pub fn only(mut self, method: Method) -> Self {
+        self.methods.push(method);
+        self
+    }
+
+    fn check(&self, req: &RequestHead) -> Result<(), ServiceError> {
+        if !self.methods.is_empty() && !self.methods.contains(&req.method) {
+            return Ok(());
+        }
+        match get_admin(req) {
+            Some(ref user) if user.can(self.permission) => Ok(()),
+            Some(_) => Err(ServiceError::Forbidden),
+            None => Err(ServiceError::Unauthorized),
+        }
+    }
+}
+
+impl<S> Transform<S> for Require
+where
+    S: Service<
+        Request = ServiceRequest,
+        Response = ServiceResponse,
+        Error = Error,
+    >,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequireService<S>;
+    type Future = FutureResult<Self::Transform, Self::InitError>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequireService {
+            service,
+            require: self.clone(),
+        })
     }
 }
 
+pub struct RequireService<S> {
+    service: S,
+    require: Require,
+}
+
+impl<S> Service for RequireService<S>
+where
+    S: Service<
+        Request = ServiceRequest,
+        Response = ServiceResponse,
+        Error = Error,
+    >,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = Either<S::Future, FutureResult<ServiceResponse, Error>>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.service.poll_ready()
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        match self.require.check(req.head()) {
+            Ok(()) => Either::A(self.service.call(req)),
+            Err(err) => Either::B(ok(req.error_response(err))),
+        }
+    }
+}
+
+// Either an access token or an api token, the latter only for requests
+// within its scope. Without an Authorization header, the access token of
+// a cookie session.
 fn get_admin(req: &RequestHead) -> Option<SlimUser> {
-    req.headers()
+    let token = match req
+        .headers()
         .get("Authorization")
         .and_then(|token| token.to_str().ok())
-        .and_then(|token| decode_token(token).ok())
+    {
+        Some(token) => token,
+        None => return get_cookie_user(req),
+    };
+    match extract_bearer_creds(token) {
+        Ok(token) if api_tokens::is_api_token(token) => {
+            api_tokens::authenticate(token, &req.method, req.uri.path())
+        }
+        _ => decode_token(token).ok(),
+    }
+}
+
+// Browsers send cookies along with requests made by other sites
+fn get_cookie_user(req: &RequestHead) -> Option<SlimUser> {
+    let token = cookie_value(req, ACCESS_COOKIE)?;
+    if !check_csrf(req) {
+        return None;
+    }
+    decode_access_token(&token).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_password() {
+        let identity = &["ann@example.com", "annie"];
+
+        assert!(check_password("correct horse battery", identity).is_ok());
+        assert!(check_password("Tr0ub4dor&3", identity).is_ok());
+        assert!(check_password("short1", identity).is_err());
+        assert!(check_password("onlylowercaseletters", identity).is_err());
+        assert!(check_password("1234567890", identity).is_err());
+        assert!(check_password("Annie-2019-pass", identity).is_err());
+        assert!(check_password(&"aB3".repeat(25), identity).is_err());
+
+        let identity = &["ann@example.com", "ann"];
+        assert!(check_password("Planning-2024!", identity).is_ok());
+        assert!(check_password("Ann-2024-pass", identity).is_err());
+    }
 }